@@ -0,0 +1,58 @@
+// Per-frame cost of laying out and drawing the screen, which shouldn't depend on how much scrollback there is
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+
+use wcmd::font::Font;
+use wcmd::layout::LineIndex;
+use wcmd::render::{Renderer, VisualCommandLine};
+use wcmd::screen::Screen;
+use wcmd::textdump::TextRenderer;
+
+const SCROLLBACK_SIZES: [usize; 3] = [1_000, 10_000, 100_000];
+
+fn scrollback(lines: usize) -> String {
+    (0..lines).map(|i| format!("{:>8} Some output that's long enough to wrap over the right edge of an 80 column window\r\n", i)).collect()
+}
+
+fn frame(c: &mut Criterion) {
+    let mut group = c.benchmark_group("frame");
+    for lines in SCROLLBACK_SIZES {
+        let font = Font::default();
+        let mut screen = Screen::new(0x07);
+        screen.set_text(scrollback(lines));
+        let mut renderer = TextRenderer::new((80, 25), &font);
+        let mut visual_cmd = VisualCommandLine::new(font);
+        visual_cmd.update(renderer.get_size(), &screen);
+
+        group.bench_function(BenchmarkId::from_parameter(lines), |b| b.iter(|| {
+            visual_cmd.update(renderer.get_size(), &screen);
+            visual_cmd.render(&mut renderer, &screen, None);
+        }));
+    }
+    group.finish();
+}
+
+fn append(c: &mut Criterion) {
+    let mut group = c.benchmark_group("append");
+    for lines in SCROLLBACK_SIZES {
+        let font = Font::default();
+        let text = scrollback(lines);
+
+        // Only laying out the new line is measured, copying the text into the screen isn't
+        group.bench_function(BenchmarkId::from_parameter(lines), |b| b.iter_batched_ref(
+            || {
+                let mut screen = Screen::new(0x07);
+                screen.set_text(text.clone());
+                let mut index = LineIndex::new(&font);
+                index.update(&screen, 640);
+                screen.set_text(format!("{}C:\\>dir\r\n", text));
+                (screen, index)
+            },
+            |(screen, index)| index.update(screen, 640),
+            BatchSize::LargeInput,
+        ));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, frame, append);
+criterion_main!(benches);
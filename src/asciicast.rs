@@ -0,0 +1,216 @@
+use std::{fs::File, io::{BufRead, BufReader, Write}, path::Path, time::{Instant, SystemTime, UNIX_EPOCH}};
+
+use serde_json::{json, Value};
+
+use crate::cmd::Cmd;
+
+// What a terminal needs to clear the screen, and what playback takes as a form feed
+pub const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
+
+// Records everything that ends up in the output as asciicast v2
+// (https://docs.asciinema.org/manual/asciicast/v2/), output gathered
+// during a tick is written as a single event when `flush` is called.
+// It's written the way a terminal wants it so other players show the same thing
+pub struct Recorder {
+    file: File,
+    start: Instant,
+    pending: String,
+    last: char,
+}
+
+impl Recorder {
+    pub fn create(path: &Path, size: (u32, u32)) -> std::io::Result<Self> {
+        let mut file = File::create(path)?;
+        let header = json!({
+            "version": 2,
+            "width": size.0,
+            "height": size.1,
+            "timestamp": SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+            "title": "Command Prompt",
+        });
+        writeln!(file, "{}", header)?;
+        Ok(Self {
+            file,
+            start: Instant::now(),
+            pending: "".to_string(),
+            last: '\n',
+        })
+    }
+
+    fn write_event(&mut self, code: &str, data: &str) {
+        let event = json!([self.start.elapsed().as_secs_f64(), code, data]);
+        if let Err(e) = writeln!(self.file, "{}", event) {
+            eprintln!("{}: Could not write recording: {}", line!(), e);
+        }
+    }
+
+    pub fn output_char(&mut self, c: char) {
+        match c {
+            '\x0c' => self.pending.push_str(CLEAR_SCREEN),
+            // Backspace only moves the cursor back in a terminal
+            '\x08' => self.pending.push_str("\x08 \x08"),
+            '\n' if self.last != '\r' => self.pending.push_str("\r\n"),
+            c => self.pending.push(c),
+        }
+        self.last = c;
+    }
+
+    pub fn output_str(&mut self, s: &str) {
+        for c in s.chars() {
+            self.output_char(c);
+        }
+    }
+
+    pub fn resize(&mut self, size: (u32, u32)) {
+        self.flush();
+        self.write_event("r", &format!("{}x{}", size.0, size.1));
+    }
+
+    pub fn flush(&mut self) {
+        if !self.pending.is_empty() {
+            let pending = std::mem::take(&mut self.pending);
+            self.write_event("o", &pending);
+        }
+    }
+}
+
+pub enum CastEvent {
+    Output(String),
+    Resize(u32, u32),
+}
+
+const SPEEDS: [f64; 7] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0, 16.0];
+
+pub struct Player {
+    header_size: (u32, u32),
+    size: (u32, u32),
+    events: Vec<(f64, CastEvent)>,
+    next: usize,
+    time: f64,
+    speed: usize,
+    paused: bool,
+}
+
+fn parse_size(s: &str) -> Option<(u32, u32)> {
+    let mut split = s.split('x');
+    Some((split.next()?.parse().ok()?, split.next()?.parse().ok()?))
+}
+
+impl Player {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| format!("{:?}: {}", path, e))?;
+        let mut lines = BufReader::new(file).lines();
+
+        let header: Value = serde_json::from_str(&lines.next().ok_or("empty recording")?.map_err(|e| e.to_string())?)
+            .map_err(|e| format!("bad header: {}", e))?;
+        if header["version"] != 2 {
+            return Err("only asciicast v2 recordings are supported".to_string());
+        }
+        let size = (header["width"].as_u64().unwrap_or(80) as u32, header["height"].as_u64().unwrap_or(25) as u32);
+
+        let mut events = vec![];
+        for (n, line) in lines.enumerate() {
+            let line = line.map_err(|e| e.to_string())?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let event: Value = serde_json::from_str(&line).map_err(|e| format!("line {}: {}", n + 2, e))?;
+            let time = event[0].as_f64().ok_or(format!("line {}: missing time", n + 2))?;
+            let data = event[2].as_str().unwrap_or("");
+            match event[1].as_str() {
+                Some("o") => events.push((time, CastEvent::Output(data.to_string()))),
+                Some("r") => if let Some((w, h)) = parse_size(data) {
+                    events.push((time, CastEvent::Resize(w, h)));
+                },
+                // Input and marker events don't change what's on screen
+                _ => (),
+            }
+        }
+
+        Ok(Self {
+            header_size: size,
+            size,
+            events,
+            next: 0,
+            time: 0.0,
+            speed: 2,
+            paused: false,
+        })
+    }
+
+    pub fn get_size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    pub fn get_time(&self) -> f64 {
+        self.time
+    }
+
+    pub fn get_duration(&self) -> f64 {
+        self.events.last().map_or(0.0, |e| e.0)
+    }
+
+    pub fn get_speed(&self) -> f64 {
+        SPEEDS[self.speed]
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    pub fn faster(&mut self) {
+        self.speed = (self.speed + 1).min(SPEEDS.len() - 1);
+    }
+
+    pub fn slower(&mut self) {
+        self.speed = self.speed.saturating_sub(1);
+    }
+
+    // Applies every event up to the current time, returns the last size the recording asked for
+    fn apply(&mut self, cmd: &mut Cmd) -> Option<(u32, u32)> {
+        let mut resized = None;
+        while self.next < self.events.len() && self.events[self.next].0 <= self.time {
+            match self.events[self.next].1 {
+                CastEvent::Output(ref s) => cmd.write_text(s),
+                CastEvent::Resize(w, h) => {
+                    self.size = (w, h);
+                    resized = Some((w, h));
+                }
+            }
+            self.next += 1;
+        }
+        resized
+    }
+
+    pub fn advance(&mut self, dt: f64, cmd: &mut Cmd) -> Option<(u32, u32)> {
+        if !self.paused {
+            self.time = (self.time + dt * self.get_speed()).min(self.get_duration());
+        }
+        self.apply(cmd)
+    }
+
+    // Seeking backwards replays the recording from the start since output can't be undone
+    pub fn seek(&mut self, time: f64, cmd: &mut Cmd) -> Option<(u32, u32)> {
+        let time = time.max(0.0).min(self.get_duration());
+        let mut rewound = None;
+        if time < self.time {
+            cmd.clear();
+            self.next = 0;
+            self.size = self.header_size;
+            rewound = Some(self.size);
+        }
+        self.time = time;
+        self.apply(cmd).or(rewound)
+    }
+
+    pub fn status(&self) -> String {
+        let clock = |t: f64| format!("{:02}:{:02}", t as u64 / 60, t as u64 % 60);
+        format!("{} {}/{} {}x",
+            if self.paused { "Paused" } else { "Playing" },
+            clock(self.time), clock(self.get_duration()), self.get_speed())
+    }
+}
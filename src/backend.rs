@@ -0,0 +1,247 @@
+use std::{cell::RefCell, collections::VecDeque, io, path::PathBuf, rc::Rc};
+
+use crate::chrome::WindowsVersion;
+use crate::serial::SerialConfig;
+use crate::subprocess::SubProcess;
+use crate::wakeup::Wakeup;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    // Ctrl+C
+    Interrupt,
+    Terminate,
+    Kill,
+}
+
+// Whatever Cmd is talking to, a child process, a PTY or something in-process for tests
+pub trait Backend {
+    fn write_input(&mut self, bytes: &[u8]) -> io::Result<()>;
+
+    // Everything written since the last call
+    fn read_output(&mut self) -> Vec<u8>;
+
+    // Size of the window in character cells, only some backends care
+    fn resize(&mut self, _cols: u32, _rows: u32) {}
+
+    fn signal(&mut self, signal: Signal) -> io::Result<()>;
+
+    // Only true once everything it wrote has been read
+    fn is_exited(&mut self) -> bool;
+
+    // For backends that produce output on other threads, to wake up a frontend waiting for it
+    fn set_wakeup(&mut self, _wakeup: Option<Wakeup>) {}
+
+    // The other end echoes what's typed itself and wants every key as soon as it's pressed
+    fn is_remote_echo(&self) -> bool {
+        false
+    }
+
+    // Console colors it asked for since the last call, like cmd.exe's `color` does
+    fn take_color(&mut self) -> Option<u8> {
+        None
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BackendKind {
+    // A child process with its output piped
+    Process,
+    // A child process on a pseudo terminal, only on unix
+    Pty,
+    Serial(SerialConfig),
+    // host:port, optionally speaking telnet
+    Tcp { address: String, telnet: bool },
+    // cmd.exe's own commands run in-process with `drive` (the current directory without it) as C:\
+    Builtin { drive: Option<PathBuf>, version: WindowsVersion },
+}
+
+// Starts `command` (the default shell when it's empty) on the given kind of backend
+pub fn spawn(kind: &BackendKind, command: &[String], size: (u32, u32)) -> io::Result<Box<dyn Backend>> {
+    let not_found = || io::Error::new(io::ErrorKind::NotFound, format!("could not run {:?}", command));
+    match kind {
+        BackendKind::Process => {
+            let child = if command.is_empty() {
+                SubProcess::from_cmd("real_cmd")
+            } else {
+                SubProcess::from_args(command)
+            };
+            Ok(Box::new(child.ok_or_else(not_found)?))
+        }
+        #[cfg(unix)]
+        BackendKind::Pty => {
+            let shell = ["sh".to_string(), "-c".to_string(), "real_cmd".to_string()];
+            let command = if command.is_empty() { &shell[..] } else { command };
+            Ok(Box::new(crate::pty::Pty::spawn(command, size)?))
+        }
+        #[cfg(not(unix))]
+        BackendKind::Pty => {
+            let _ = size;
+            Err(io::Error::new(io::ErrorKind::Unsupported, "--pty is only supported on unix"))
+        }
+        #[cfg(unix)]
+        BackendKind::Serial(config) => Ok(Box::new(crate::serial::Serial::open(config)?)),
+        BackendKind::Tcp { address, telnet } => Ok(Box::new(crate::telnet::Tcp::connect(address, *telnet, size)?)),
+        BackendKind::Builtin { drive, version } => {
+            let drive = match drive {
+                Some(drive) => drive.clone(),
+                None => std::env::current_dir()?,
+            };
+            Ok(Box::new(crate::interpreter::Interpreter::new(drive, *version)?))
+        }
+        #[cfg(not(unix))]
+        BackendKind::Serial(_) => Err(io::Error::new(io::ErrorKind::Unsupported, "--serial is only supported on unix")),
+    }
+}
+
+// Sends back everything written to it, until it gets a signal
+#[derive(Default)]
+pub struct Loopback {
+    output: Vec<u8>,
+    exited: bool,
+    wakeup: Option<Wakeup>,
+}
+
+impl Loopback {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Backend for Loopback {
+    fn write_input(&mut self, bytes: &[u8]) -> io::Result<()> {
+        if self.exited {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+        self.output.extend_from_slice(bytes);
+        if let Some(ref wakeup) = self.wakeup {
+            wakeup.wake();
+        }
+        Ok(())
+    }
+
+    fn read_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.output)
+    }
+
+    fn signal(&mut self, _signal: Signal) -> io::Result<()> {
+        self.exited = true;
+        Ok(())
+    }
+
+    fn is_exited(&mut self) -> bool {
+        self.exited && self.output.is_empty()
+    }
+
+    fn set_wakeup(&mut self, wakeup: Option<Wakeup>) {
+        self.wakeup = wakeup;
+    }
+}
+
+pub enum Step {
+    // Handed out by the next `read_output`
+    Output(Vec<u8>),
+    // Nothing after this happens until the input so far contains these bytes
+    Input(Vec<u8>),
+    Exit,
+}
+
+// Everything a scripted backend was asked to do
+#[derive(Default, Debug)]
+pub struct ScriptLog {
+    pub input: Vec<u8>,
+    pub resizes: Vec<(u32, u32)>,
+    pub signals: Vec<Signal>,
+}
+
+// Plays back a script of output, waiting for input where it says so.
+// Clones share the same script and log so a test can keep one to look at
+#[derive(Clone, Default)]
+pub struct Scripted {
+    steps: Rc<RefCell<VecDeque<Step>>>,
+    log: Rc<RefCell<ScriptLog>>,
+    exited: Rc<RefCell<bool>>,
+    // Where in `log.input` the next `Step::Input` starts looking
+    consumed: Rc<RefCell<usize>>,
+}
+
+impl Scripted {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn output(self, bytes: &[u8]) -> Self {
+        self.steps.borrow_mut().push_back(Step::Output(bytes.to_vec()));
+        self
+    }
+
+    pub fn input(self, bytes: &[u8]) -> Self {
+        self.steps.borrow_mut().push_back(Step::Input(bytes.to_vec()));
+        self
+    }
+
+    pub fn exit(self) -> Self {
+        self.steps.borrow_mut().push_back(Step::Exit);
+        self
+    }
+
+    pub fn get_log(&self) -> std::cell::Ref<'_, ScriptLog> {
+        self.log.borrow()
+    }
+}
+
+impl Backend for Scripted {
+    fn write_input(&mut self, bytes: &[u8]) -> io::Result<()> {
+        if *self.exited.borrow() {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+        self.log.borrow_mut().input.extend_from_slice(bytes);
+        Ok(())
+    }
+
+    fn read_output(&mut self) -> Vec<u8> {
+        let mut output = vec![];
+        let mut steps = self.steps.borrow_mut();
+        while let Some(step) = steps.pop_front() {
+            match step {
+                Step::Output(bytes) => output.extend(bytes),
+                Step::Input(expected) => {
+                    let log = self.log.borrow();
+                    let mut consumed = self.consumed.borrow_mut();
+                    let found = if expected.is_empty() {
+                        Some(0)
+                    } else {
+                        log.input[*consumed..].windows(expected.len()).position(|w| w == &expected[..])
+                    };
+                    match found {
+                        Some(at) => *consumed += at + expected.len(),
+                        None => {
+                            steps.push_front(Step::Input(expected));
+                            break;
+                        }
+                    }
+                }
+                Step::Exit => {
+                    *self.exited.borrow_mut() = true;
+                    steps.clear();
+                }
+            }
+        }
+        output
+    }
+
+    fn resize(&mut self, cols: u32, rows: u32) {
+        self.log.borrow_mut().resizes.push((cols, rows));
+    }
+
+    fn signal(&mut self, signal: Signal) -> io::Result<()> {
+        self.log.borrow_mut().signals.push(signal);
+        if signal != Signal::Interrupt {
+            *self.exited.borrow_mut() = true;
+        }
+        Ok(())
+    }
+
+    fn is_exited(&mut self) -> bool {
+        *self.exited.borrow()
+    }
+}
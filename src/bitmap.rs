@@ -0,0 +1,95 @@
+use std::path::Path;
+
+// Decoded RGBA image, enough to hold the font sheet without going through SDL
+pub struct Bitmap {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+fn read_u16(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes([*data.get(at)?, *data.get(at + 1)?]))
+}
+
+fn read_u32(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes([*data.get(at)?, *data.get(at + 1)?, *data.get(at + 2)?, *data.get(at + 3)?]))
+}
+
+// Scales a channel selected by `mask` to 0..=255
+fn channel(pixel: u32, mask: u32) -> u8 {
+    if mask == 0 {
+        return 0;
+    }
+    let shift = mask.trailing_zeros();
+    let max = mask >> shift;
+    (((pixel & mask) >> shift) * 255 / max) as u8
+}
+
+impl Bitmap {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self { width, height, pixels: vec![0; (width * height * 4) as usize] }
+    }
+
+    pub fn load_bmp<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let data = std::fs::read(path.as_ref()).map_err(|e| format!("{:?}: {}", path.as_ref(), e))?;
+        Self::from_bmp(&data)
+    }
+
+    // Only uncompressed 24 and 32 bit bitmaps (optionally with bit fields) are supported
+    pub fn from_bmp(data: &[u8]) -> Result<Self, String> {
+        let bad = || "not a supported bmp file".to_string();
+        if data.get(0..2) != Some(b"BM") {
+            return Err(bad());
+        }
+        let offset = read_u32(data, 10).ok_or_else(bad)? as usize;
+        let header_size = read_u32(data, 14).ok_or_else(bad)?;
+        let width = read_u32(data, 18).ok_or_else(bad)? as i32;
+        let height = read_u32(data, 22).ok_or_else(bad)? as i32;
+        let bpp = read_u16(data, 28).ok_or_else(bad)?;
+        let compression = read_u32(data, 30).ok_or_else(bad)?;
+
+        let (mut r, mut g, mut b, mut a) = (0xff_0000, 0xff00, 0xff, 0);
+        match (compression, bpp) {
+            (0, 24) | (0, 32) => (),
+            (3, 32) => {
+                r = read_u32(data, 54).ok_or_else(bad)?;
+                g = read_u32(data, 58).ok_or_else(bad)?;
+                b = read_u32(data, 62).ok_or_else(bad)?;
+                if header_size >= 56 {
+                    a = read_u32(data, 66).ok_or_else(bad)?;
+                }
+            }
+            _ => return Err(format!("unsupported bmp format: {} bpp, compression {}", bpp, compression)),
+        }
+
+        // Positive heights are stored bottom up
+        let bottom_up = height > 0;
+        let (width, height) = (width.unsigned_abs(), height.unsigned_abs());
+        let bytes_per_pixel = bpp as usize / 8;
+        let stride = (width as usize * bytes_per_pixel + 3) & !3;
+
+        let mut bitmap = Self::new(width, height);
+        for y in 0..height as usize {
+            let row = offset + stride * if bottom_up { height as usize - 1 - y } else { y };
+            for x in 0..width as usize {
+                let at = row + x * bytes_per_pixel;
+                let pixel = if bpp == 24 {
+                    data.get(at..at + 3).map(|p| u32::from_le_bytes([p[0], p[1], p[2], 0]))
+                } else {
+                    read_u32(data, at)
+                }.ok_or_else(bad)?;
+                let out = (y * width as usize + x) * 4;
+                bitmap.pixels[out] = channel(pixel, r);
+                bitmap.pixels[out + 1] = channel(pixel, g);
+                bitmap.pixels[out + 2] = channel(pixel, b);
+                bitmap.pixels[out + 3] = if a == 0 { 0xff } else { channel(pixel, a) };
+            }
+        }
+        Ok(bitmap)
+    }
+
+    pub fn get_pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let at = ((y * self.width + x) * 4) as usize;
+        [self.pixels[at], self.pixels[at + 1], self.pixels[at + 2], self.pixels[at + 3]]
+    }
+}
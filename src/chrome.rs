@@ -0,0 +1,120 @@
+use std::fmt;
+
+use crate::render::ConsoleStyle;
+
+// Which cmd.exe is pretended to be, it decides the banner and how the window looks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowsVersion {
+    Win7,
+    Win81,
+    Win10,
+    Win11,
+}
+
+impl WindowsVersion {
+    pub const ALL: [WindowsVersion; 4] = [WindowsVersion::Win7, WindowsVersion::Win81, WindowsVersion::Win10, WindowsVersion::Win11];
+
+    // `7`, `8.1`, `10` or `11`
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Self::ALL.iter().copied().find(|v| v.get_name() == s)
+            .ok_or(format!("unknown Windows version: {} (7, 8.1, 10 or 11)", s))
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            WindowsVersion::Win7 => "7",
+            WindowsVersion::Win81 => "8.1",
+            WindowsVersion::Win10 => "10",
+            WindowsVersion::Win11 => "11",
+        }
+    }
+
+    // What `ver` prints, without the empty lines around it
+    pub fn get_version_line(&self) -> &'static str {
+        match self {
+            WindowsVersion::Win7 => "Microsoft Windows [Version 6.1.7601]",
+            WindowsVersion::Win81 => "Microsoft Windows [Version 6.3.9600]",
+            WindowsVersion::Win10 => "Microsoft Windows [Version 10.0.19045.4291]",
+            WindowsVersion::Win11 => "Microsoft Windows [Version 10.0.22631.3447]",
+        }
+    }
+
+    // Printed before the first prompt, the copyright line changed a few times
+    pub fn get_banner(&self) -> String {
+        let copyright = match self {
+            WindowsVersion::Win7 => "Copyright (c) 2009 Microsoft Corporation.  All rights reserved.",
+            WindowsVersion::Win81 => "(c) 2013 Microsoft Corporation. All rights reserved.",
+            WindowsVersion::Win10 | WindowsVersion::Win11 => "(c) Microsoft Corporation. All rights reserved.",
+        };
+        format!("{}\n{}\n\n", self.get_version_line(), copyright)
+    }
+
+    pub fn get_style(&self) -> ConsoleStyle {
+        match self {
+            WindowsVersion::Win11 => ConsoleStyle::Win11,
+            _ => ConsoleStyle::Win10,
+        }
+    }
+}
+
+impl fmt::Display for WindowsVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Windows {}", self.get_name())
+    }
+}
+
+// `C:\Users\me>` and such, the line cmd.exe is waiting on
+fn is_prompt(line: &str) -> bool {
+    let mut chars = line.chars();
+    matches!((chars.next(), chars.next(), chars.next()), (Some(drive), Some(':'), Some('\\')) if drive.is_ascii_alphabetic())
+        && line.ends_with('>')
+}
+
+// The window title the way cmd.exe keeps it: the command that's running is shown after the
+// title until the prompt comes back, and elevated windows say so in front
+pub struct Chrome {
+    administrator: bool,
+    // Changed by the `title` command
+    title: String,
+    running: Option<String>,
+}
+
+impl Chrome {
+    pub fn new(administrator: bool) -> Self {
+        Self { administrator, title: "Command Prompt".to_string(), running: None }
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        self.title = title.to_string();
+    }
+
+    // A command line was entered
+    pub fn on_line(&mut self, line: &str) {
+        let line = line.trim();
+        self.running = if line.is_empty() { None } else { Some(line.to_string()) };
+    }
+
+    // `last_line` is the line output is being written to, a prompt there means the command is done
+    pub fn on_output(&mut self, last_line: &str) {
+        if is_prompt(last_line.trim_end()) {
+            self.running = None;
+        }
+    }
+
+    pub fn get_running(&self) -> Option<&str> {
+        self.running.as_deref()
+    }
+
+    pub fn get_title(&self) -> String {
+        let mut title = String::new();
+        if self.administrator {
+            title.push_str("Administrator: ");
+        }
+        title.push_str(&self.title);
+        if let Some(ref running) = self.running {
+            title.push_str(" - ");
+            title.push_str(running);
+        }
+        title
+    }
+}
@@ -1,51 +1,241 @@
-use std::{borrow::BorrowMut, collections::VecDeque, convert::TryInto, io::{Read, Write}, process::{Child, ChildStderr, ChildStdin, ChildStdout}};
+use std::{cell::Ref, collections::VecDeque, ops::Range, time::Instant, convert::TryInto, io::{Read, Write}, process::{Child, ChildStderr, ChildStdin, ChildStdout}};
 
-use crate::subprocess::SubProcess;
+use crate::asciicast::Recorder;
+use crate::scrollback::{ChangeMark, Scrollback, ScrollbackLimit, SharedScrollback};
+use crate::backend::{Backend, Signal};
+use crate::input_filter::InputFilters;
+use crate::progress::Progress;
+use crate::responses::{Playback, Responses, Rng};
+use crate::transcript::{Transcript, TranscriptConfig};
+use crate::wakeup::Wakeup;
 
+// Output events are coalesced, there's at most one of each every time events are drained
 pub enum CmdEvent {
     ChildExited,
-    StdoutChanged
+    StdoutChanged(StdoutChange),
+    // Set with the `ESC ] 0 ; title BEL` (or 2) sequence
+    TitleChanged(String),
+    // Background and foreground like a screen color, from the `color` command
+    ColorChanged(u8),
+    Bell,
+}
+
+pub struct StdoutChange {
+    // Absolute offsets into the scrollback (see `Scrollback`) of everything that was
+    // written or rewritten, can be empty if text was only erased or trimmed
+    pub range: Range<usize>,
+    // The scrollback was cleared before `range` was written
+    pub cleared: bool,
+}
+
+// Where write_bytes is in an escape sequence, only window titles are understood
+enum Escape {
+    None,
+    Started,
+    Osc(Vec<u8>),
 }
 
 pub struct Cmd {
-    child: Option<SubProcess>,
+    child: Option<Box<dyn Backend>>,
     to_ignore: usize,
     ignored: usize,
     pub events: VecDeque<CmdEvent>,
-    stdout: String,
+    stdout: SharedScrollback,
     stdin: String,
     is_running: bool,
+    transcript: Option<Transcript>,
+    recorder: Option<Recorder>,
+    size: (u32, u32),
+    wakeup: Option<Wakeup>,
+    drained: ChangeMark,
+    stdout_touched: bool,
+    cleared: bool,
+    title: Option<String>,
+    color: Option<u8>,
+    bell: bool,
+    escape: Escape,
+    // Lines matching one of these are answered here instead of by the child
+    responses: Option<Responses>,
+    playback: Option<Playback>,
+    rng: Rng,
+    // What typed keys go through before they show up
+    pub input_filters: InputFilters,
+}
+
+impl Default for Cmd {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Cmd {
     pub fn new() -> Self {
+        let stdout = Scrollback::new_shared(ScrollbackLimit::default());
+        let drained = stdout.borrow().mark();
         Self {
             to_ignore: 0,
             ignored: 0,
             events: VecDeque::new(),
-            stdout: "".to_string(),
+            stdout,
             stdin: "".to_string(),
             is_running: true,
             child: None,
+            transcript: None,
+            recorder: None,
+            size: (80, 25),
+            wakeup: None,
+            drained,
+            stdout_touched: false,
+            cleared: false,
+            title: None,
+            color: None,
+            bell: false,
+            escape: Escape::None,
+            responses: None,
+            playback: None,
+            rng: Rng::from_time(),
+            input_filters: InputFilters::new(),
+        }
+    }
+
+    pub fn start_transcript(&mut self, config: &TranscriptConfig) -> std::io::Result<&Transcript> {
+        let transcript = Transcript::open(config)?;
+        Ok(self.transcript.get_or_insert(transcript))
+    }
+
+    pub fn get_transcript(&self) -> Option<&Transcript> {
+        self.transcript.as_ref()
+    }
+
+    pub fn stop_transcript(&mut self) -> Option<Transcript> {
+        self.transcript.take()
+    }
+
+    pub fn start_recording(&mut self, path: &std::path::Path) -> std::io::Result<()> {
+        self.recorder = Some(Recorder::create(path, self.size)?);
+        Ok(())
+    }
+
+    pub fn stop_recording(&mut self) {
+        if let Some(ref mut recorder) = self.recorder {
+            recorder.flush();
+        }
+        self.recorder = None;
+    }
+
+    // Size of the window in character cells
+    pub fn resize(&mut self, cols: u32, rows: u32) {
+        if self.size != (cols, rows) {
+            self.size = (cols, rows);
+            if let Some(ref mut recorder) = self.recorder {
+                recorder.resize(self.size);
+            }
+            if let Some(ref mut child) = self.child {
+                child.resize(cols, rows);
+            }
+        }
+    }
+
+    pub fn set_responses(&mut self, responses: Option<Responses>) {
+        self.responses = responses;
+    }
+
+    pub fn is_playing_response(&self) -> bool {
+        self.playback.is_some()
+    }
+
+    // Plays it like a scripted response, Ctrl+C stops it
+    pub fn start_progress(&mut self, progress: &Progress) {
+        let at_line_start = {
+            let stdout = self.stdout.borrow();
+            stdout.as_str().is_empty() || stdout.as_str().ends_with('\n')
+        };
+        // Frames redraw their own line, not the prompt
+        if !at_line_start {
+            self.put_stdout('\n');
+        }
+        self.playback = Some(progress.playback(&mut self.rng));
+    }
+
+    // Like Ctrl+C, false if nothing was playing
+    pub fn abort_response(&mut self) -> bool {
+        if self.playback.take().is_none() {
+            return false;
+        }
+        self.write_stdout("^C\n");
+        self.write_child(b"\n");
+        true
+    }
+
+    // When update has to be called again for a scripted response to go on
+    pub fn get_deadline(&self) -> Option<Instant> {
+        let playback = self.playback.as_ref().and_then(|p| p.get_deadline());
+        match (playback, self.input_filters.get_deadline()) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
         }
     }
 
+    pub fn set_scrollback_limit(&mut self, limit: ScrollbackLimit) {
+        self.stdout.borrow_mut().set_limit(limit);
+    }
+
+    // Makes the next drain report a change even if nothing was written
     pub fn trigger_stdout_update(&mut self) {
-        self.emit(CmdEvent::StdoutChanged);
+        self.stdout_touched = true;
     }
 
-    fn emit(&mut self, event: CmdEvent) {
-        self.events.push_back(event);
+    pub fn attach_child<B: Backend + 'static>(&mut self, child: Option<B>) {
+        self.attach_backend(child.map(|c| Box::new(c) as Box<dyn Backend>));
     }
 
-    pub fn attach_child(&mut self, child: Option<SubProcess>) {
+    pub fn attach_backend(&mut self, child: Option<Box<dyn Backend>>) {
         self.child = child;
+        if let Some(ref mut child) = self.child {
+            child.set_wakeup(self.wakeup.clone());
+            child.resize(self.size.0, self.size.1);
+        }
+    }
+
+    // Lets a frontend sleep until the child has written something instead of polling
+    pub fn set_wakeup(&mut self, wakeup: Wakeup) {
+        if let Some(ref mut child) = self.child {
+            child.set_wakeup(Some(wakeup.clone()));
+        }
+        self.wakeup = Some(wakeup);
     }
     
+    // Everything that happened since the last call, output changes come first so
+    // they're handled before the child exiting
     pub fn drain_events(&mut self) -> VecDeque<CmdEvent> {
-        let mut replace = VecDeque::new();
-        std::mem::swap(&mut replace, &mut self.events);
-        replace
+        let mut events = VecDeque::new();
+
+        let stdout = self.stdout.borrow();
+        let changed = stdout.get_changed_since(&self.drained);
+        if changed.is_some() || self.stdout_touched || self.cleared {
+            let from = changed.unwrap_or_else(|| stdout.get_end());
+            events.push_back(CmdEvent::StdoutChanged(StdoutChange {
+                range: from..stdout.get_end(),
+                cleared: self.cleared,
+            }));
+        }
+        self.drained = stdout.mark();
+        drop(stdout);
+        self.stdout_touched = false;
+        self.cleared = false;
+
+        if let Some(title) = self.title.take() {
+            events.push_back(CmdEvent::TitleChanged(title));
+        }
+        if let Some(color) = self.color.take() {
+            events.push_back(CmdEvent::ColorChanged(color));
+        }
+        if std::mem::take(&mut self.bell) {
+            events.push_back(CmdEvent::Bell);
+        }
+
+        events.append(&mut self.events);
+        events
     }
 
     pub fn exit(&mut self) {
@@ -56,24 +246,81 @@ impl Cmd {
         return !self.is_running;
     }
 
+    // Whether typed keys go straight to the child instead of being echoed and sent a line at a time
+    pub fn is_remote_echo(&self) -> bool {
+        self.child.as_ref().is_some_and(|c| c.is_remote_echo())
+    }
+
+    fn write_child(&mut self, bytes: &[u8]) {
+        if let Some(ref mut child) = self.child {
+            if let Err(e) = child.write_input(bytes) {
+                eprintln!("{}: Could not write to the child: {}", line!(), e);
+            }
+        }
+    }
+
     pub fn pop_stdin(&mut self) {
-        if self.stdin.pop() != None {
-            self.stdout.pop();
-            self.emit(CmdEvent::StdoutChanged);
+        // Lagging keys get erased once they're there
+        if !self.input_filters.is_idle() {
+            self.input_filters.push_unfiltered('\x08', Instant::now());
+            self.release_keys(false);
+        }
+        else {
+            self.erase_key();
+        }
+    }
+
+    fn erase_key(&mut self) {
+        if self.is_remote_echo() {
+            self.stdin.pop();
+            self.write_child(b"\x08");
+        }
+        else if self.stdin.pop() != None {
+            self.stdout.borrow_mut().pop();
+            if let Some(ref mut recorder) = self.recorder {
+                recorder.output_char('\x08');
+            }
         }
     }
 
     pub fn put_stdin(&mut self, c: char) {
+        if self.input_filters.is_idle() {
+            self.type_key(c);
+            return;
+        }
+        self.input_filters.push(c, &self.stdin, &mut self.rng, Instant::now());
+        self.release_keys(false);
+    }
+
+    // Passes on the keys the input filters let through, all of them when Enter is pressed
+    fn release_keys(&mut self, all: bool) {
+        let keys = if all { self.input_filters.take_all() } else { self.input_filters.take_due(Instant::now()) };
+        for c in keys {
+            if c == '\x08' { self.erase_key() } else { self.type_key(c) }
+        }
+    }
+
+    fn type_key(&mut self, c: char) {
         self.stdin.push(c);
-        self.put_stdout(c);
+        if self.is_remote_echo() {
+            self.write_child(c.encode_utf8(&mut [0; 4]).as_bytes());
+        }
+        else {
+            self.put_stdout(c);
+        }
     }
 
     pub fn get_stdin(&self) -> &str {
         self.stdin.as_str()
     }
 
-    pub fn get_stdout(&self) -> &str {
-        self.stdout.as_str()
+    pub fn get_stdout(&self) -> Ref<'_, str> {
+        Ref::map(self.stdout.borrow(), |s| s.as_str())
+    }
+
+    // The same scrollback Cmd writes to, for a Screen to draw from
+    pub fn get_stdout_buffer(&self) -> SharedScrollback {
+        self.stdout.clone()
     }
 
     pub fn is_handling_subprocess(&self) -> bool {
@@ -81,22 +328,57 @@ impl Cmd {
     }
 
     pub fn destroy_child(&mut self) {
-        if let Some(ref mut child) = &mut self.child {
-            println!("Child process found, destroying");
-            child.kill();
+        self.signal_child(Signal::Kill);
+    }
+
+    pub fn signal_child(&mut self, signal: Signal) {
+        // Ctrl+C stops a scripted response like it would a real command
+        if signal == Signal::Interrupt && self.abort_response() {
+            return;
+        }
+        if let Some(ref mut child) = self.child {
+            if let Err(e) = child.signal(signal) {
+                eprintln!("{}: Could not send {:?} to the child: {}", line!(), signal, e);
+            }
         }
     }
 
     pub fn clear(&mut self) {
-        self.stdout = "".to_string();
+        self.stdout.borrow_mut().clear();
+        if let Some(ref mut recorder) = self.recorder {
+            recorder.output_char('\x0c');
+        }
+        self.cleared = true;
+    }
+
+    // Enter: keys still held back are typed first, then the newline is echoed and the line sent
+    pub fn enter(&mut self) -> String {
+        self.release_keys(true);
+        if !self.is_remote_echo() {
+            self.put_stdout('\n');
+        }
+        self.flush_stdin()
     }
 
     pub fn flush_stdin(&mut self) -> String {
+        self.release_keys(true);
         self.stdin.push('\n');
         self.ignored = 0;
         self.to_ignore = self.stdin.len();
-        if let Some(ref mut child) = &mut self.child {
-            child.write_stdin(self.stdin.as_bytes());
+        if let Some(ref mut transcript) = self.transcript {
+            transcript.write_input(&self.stdin);
+        }
+        let playback = match self.responses {
+            Some(ref responses) if !self.is_remote_echo() => responses.find(&self.stdin, &mut self.rng),
+            _ => None,
+        };
+        if playback.is_some() {
+            self.playback = playback;
+        }
+        else {
+            // Everything but the newline was already sent while typing
+            let line = if self.is_remote_echo() { "\n".to_string() } else { self.stdin.clone() };
+            self.write_child(line.as_bytes());
         }
         let mut old = "".to_string();
         std::mem::swap(&mut self.stdin, &mut old);
@@ -104,40 +386,140 @@ impl Cmd {
     }
 
     pub fn put_stdout(&mut self, c: char) {
-        self.stdout.push(c);
-        self.emit(CmdEvent::StdoutChanged);
+        self.stdout.borrow_mut().push(c);
+        if let Some(ref mut recorder) = self.recorder {
+            recorder.output_char(c);
+        }
     }
 
     pub fn write_stdout(&mut self, s: &str) {
+        self.stdout.borrow_mut().push_str(s);
+        if let Some(ref mut recorder) = self.recorder {
+            recorder.output_str(s);
+        }
+    }
+
+    // Writes already decoded text, form feed (or the sequence recordings clear with) clears and backspace erases.
+    // Carriage return goes back to the start of the line and what follows writes over it
+    pub fn write_text(&mut self, s: &str) {
+        let s = s.replace(crate::asciicast::CLEAR_SCREEN, "\x0c");
+        // Where the next character goes when it isn't the end
+        let mut cursor: Option<usize> = None;
         for c in s.chars() {
-            self.stdout.push(c);
+            match (c, cursor) {
+                ('\r', _) => {
+                    let end = self.stdout.borrow().get_end();
+                    cursor = Some(self.stdout.borrow().get_line_start(end));
+                    if let Some(ref mut recorder) = self.recorder {
+                        recorder.output_char(c);
+                    }
+                }
+                ('\x0c', _) => {
+                    cursor = None;
+                    self.clear();
+                }
+                ('\x08', _) => {
+                    cursor = None;
+                    self.stdout.borrow_mut().pop();
+                    if let Some(ref mut recorder) = self.recorder {
+                        recorder.output_char(c);
+                    }
+                }
+                (c, Some(at)) if c != '\n' => {
+                    let mut stdout = self.stdout.borrow_mut();
+                    let mut rest = stdout.get_from(at).chars();
+                    rest.next();
+                    let line = format!("{}{}", c, rest.as_str());
+                    stdout.replace_from(at, &line);
+                    let at = at + c.len_utf8();
+                    cursor = if at < stdout.get_end() { Some(at) } else { None };
+                    drop(stdout);
+                    if let Some(ref mut recorder) = self.recorder {
+                        recorder.output_char(c);
+                    }
+                }
+                _ => {
+                    cursor = None;
+                    self.put_stdout(c);
+                }
+            }
         }
-        self.emit(CmdEvent::StdoutChanged);
     }
 
     pub fn write_bytes(&mut self, b: &[u8]) {
-        for chr in b {
-            // Clear screen 
-            if *chr == 0x0c { 
-                self.clear()
-            }
-            else {
-                self.put_stdout(crate::cp437::cp437_to_unicode(*chr));
+        if let Some(ref mut transcript) = self.transcript {
+            transcript.write_output(b);
+        }
+        for &chr in b {
+            match std::mem::replace(&mut self.escape, Escape::None) {
+                Escape::Osc(mut osc) => match chr {
+                    0x07 => self.set_title(&osc),
+                    // The string terminator is ESC \
+                    0x1b => {
+                        self.set_title(&osc);
+                        self.escape = Escape::Started;
+                    }
+                    _ => {
+                        osc.push(chr);
+                        self.escape = Escape::Osc(osc);
+                    }
+                },
+                Escape::Started if chr == b']' => self.escape = Escape::Osc(vec![]),
+                Escape::Started if chr == b'\\' => (),
+                escape => {
+                    // Anything else is shown like it always was
+                    if let Escape::Started = escape {
+                        self.put_stdout(crate::cp437::cp437_to_unicode(0x1b));
+                    }
+                    match chr {
+                        // Clear screen
+                        0x0c => self.clear(),
+                        0x07 => self.bell = true,
+                        0x1b => self.escape = Escape::Started,
+                        _ => self.put_stdout(crate::cp437::cp437_to_unicode(chr)),
+                    }
+                }
             }
-        }   
+        }
+    }
+
+    fn set_title(&mut self, osc: &[u8]) {
+        let osc = osc.iter().map(|&b| crate::cp437::cp437_to_unicode(b)).collect::<String>();
+        if let Some(title) = osc.strip_prefix("0;").or_else(|| osc.strip_prefix("2;")) {
+            self.title = Some(title.to_string());
+        }
     }
 
     pub fn update(&mut self) {
+        if let Some(ref wakeup) = self.wakeup {
+            wakeup.reset();
+        }
+
+        // Taken out of the child first so writing it doesn't overlap with borrowing it.
+        // Checking if it's dead goes first, once it is nothing can show up after the output is taken
+        let mut output = vec![];
         let mut process_done = false;
+        if let Some(ref mut child) = self.child {
+            process_done = child.is_exited();
+            output = child.read_output();
+            if let Some(color) = child.take_color() {
+                self.color = Some(color);
+            }
+        }
+        self.write_bytes(&output);
+        self.release_keys(false);
 
-        let this = (self as *mut Self);
-        if let Some(ref mut child) = &mut self.child {
-            unsafe {
-                (*this).write_bytes(&child.get_bytes_stderr());
-                (*this).write_bytes(&child.get_bytes_stdout());
+        if let Some(ref mut playback) = self.playback {
+            let text = playback.take_due(Instant::now());
+            let done = playback.is_done();
+            if let Some(ref mut transcript) = self.transcript {
+                transcript.write_output(&text.chars().map(crate::cp437::unicode_to_cp437).collect::<Vec<u8>>());
             }
-            if child.is_dead() {
-                process_done = true;
+            self.write_text(&text);
+            if done {
+                self.playback = None;
+                // An empty line gets the child to print its prompt again
+                self.write_child(b"\n");
             }
         }
 
@@ -146,12 +528,10 @@ impl Cmd {
             self.child = None;
         }
 
-        let n = 50000;
-        // To avoid clogging up stdout remove we only leave last 50000 characters
-        let amount_to_drain = self.stdout.char_indices().rev().nth(n-1).map_or(0, |(idx, ch)| idx);
+        self.stdout.borrow_mut().trim();
 
-        if amount_to_drain > 10000 {
-            self.stdout.drain(0..amount_to_drain);
+        if let Some(ref mut recorder) = self.recorder {
+            recorder.flush();
         }
     }
 }
@@ -1,15 +1,26 @@
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Clone)]
 pub enum FontArrangment {
     ASCII
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Font {
     pub arrangment: FontArrangment,
     pub glyph_size: (usize, usize),
     pub sheet_width: usize
 }
 
+impl Default for Font {
+    // The 8x16 CP437 sheet from font.bmp
+    fn default() -> Self {
+        Self {
+            arrangment: FontArrangment::ASCII,
+            glyph_size: (8, 16),
+            sheet_width: 255,
+        }
+    }
+}
+
 impl Font {
     // I'm not sure if there's a better way to do this, so this
     // is an iterative solution that is quite expensive
@@ -55,8 +66,34 @@ impl Font {
         }
     }
 
-    pub fn get_glyph_rect_sdl(&self, chr: char) -> sdl2::rect::Rect {
-        let rect = self.get_glyph_rect(chr);
-        sdl2::rect::Rect::new(rect.0 as i32, rect.1 as i32, rect.2 as u32, rect.3 as u32)
+    // Walks the string the same way it's laid out on screen, calling `f` with
+    // the byte offset, the glyph and its position for every drawable glyph
+    pub fn layout_string_wrapped<F: FnMut(usize, char, i32, i32)>(&self, x: i32, y: i32, right_bound: u32, string: &str, mut f: F) -> (i32, i32) {
+        let mut mutx = x;
+        let mut muty = y;
+
+        for (offset, glyph) in string.char_indices() {
+            // Check if we should wrap over the screen
+            match glyph {
+                '\r' => (),
+                '\n' => {
+                    muty += self.glyph_size.1 as i32;
+                    mutx = x;
+                },
+                '\t' => {
+                    mutx += self.glyph_size.0 as i32 * 4;
+                },
+                _ => {
+                    f(offset, glyph, mutx, muty);
+                    mutx += self.glyph_size.0 as i32;
+                }
+            }
+            if (mutx+self.glyph_size.0 as i32) > (right_bound as i32) {
+                muty += self.glyph_size.1 as i32;
+                mutx = x;
+            }
+        }
+
+        (mutx, muty)
     }
 }
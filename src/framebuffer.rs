@@ -0,0 +1,98 @@
+use std::{fs::File, io::BufWriter, path::Path};
+
+use crate::bitmap::Bitmap;
+use crate::font::Font;
+use crate::render::{Color, Rect, Renderer};
+
+// Software renderer into an in-memory RGBA buffer, glyphs are blended from
+// the font sheet the same way SDL color mods the font texture
+pub struct FramebufferRenderer {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+    font: Font,
+    font_sheet: Bitmap,
+    frames: usize,
+}
+
+impl FramebufferRenderer {
+    pub fn new(size: (u32, u32), font: Font, font_sheet: Bitmap) -> Self {
+        Self {
+            width: size.0,
+            height: size.1,
+            pixels: vec![0; (size.0 * size.1 * 4) as usize],
+            font,
+            font_sheet,
+            frames: 0,
+        }
+    }
+
+    pub fn get_pixels(&self) -> &[u8] {
+        self.pixels.as_slice()
+    }
+
+    pub fn get_frames(&self) -> usize {
+        self.frames
+    }
+
+    fn blend(&mut self, x: i32, y: i32, color: Color, alpha: u8) {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return;
+        }
+        let at = ((y as u32 * self.width + x as u32) * 4) as usize;
+        let mix = |dst: u8, src: u8| ((src as u32 * alpha as u32 + dst as u32 * (255 - alpha as u32)) / 255) as u8;
+        self.pixels[at] = mix(self.pixels[at], color.r);
+        self.pixels[at + 1] = mix(self.pixels[at + 1], color.g);
+        self.pixels[at + 2] = mix(self.pixels[at + 2], color.b);
+        self.pixels[at + 3] = 0xff;
+    }
+
+    pub fn write_png(&self, path: &Path) -> Result<(), String> {
+        let file = File::create(path).map_err(|e| format!("{:?}: {}", path, e))?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+        writer.write_image_data(&self.pixels).map_err(|e| e.to_string())
+    }
+}
+
+impl Renderer for FramebufferRenderer {
+    fn get_size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn fill_rect(&mut self, rect: Rect, color: Color) {
+        for y in rect.y.max(0)..(rect.y + rect.h as i32).min(self.height as i32) {
+            for x in rect.x.max(0)..(rect.x + rect.w as i32).min(self.width as i32) {
+                self.blend(x, y, color, 0xff);
+            }
+        }
+    }
+
+    fn draw_cell_run(&mut self, x: i32, y: i32, glyphs: &str, color: Color) {
+        let mut gx = x;
+        for glyph in glyphs.chars() {
+            let (sx, sy, w, h) = self.font.get_glyph_rect(glyph);
+            for py in 0..h as u32 {
+                for px in 0..w as u32 {
+                    if sx as u32 + px >= self.font_sheet.width || sy as u32 + py >= self.font_sheet.height {
+                        continue;
+                    }
+                    let [r, g, b, a] = self.font_sheet.get_pixel(sx as u32 + px, sy as u32 + py);
+                    let modded = Color::rgb(
+                        (r as u32 * color.r as u32 / 255) as u8,
+                        (g as u32 * color.g as u32 / 255) as u8,
+                        (b as u32 * color.b as u32 / 255) as u8,
+                    );
+                    self.blend(gx + px as i32, y + py as i32, modded, a);
+                }
+            }
+            gx += self.font.glyph_size.0 as i32;
+        }
+    }
+
+    fn present(&mut self) {
+        self.frames += 1;
+    }
+}
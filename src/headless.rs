@@ -0,0 +1,84 @@
+use std::{path::{Path, PathBuf}, time::{Duration, Instant}};
+
+use crate::backend::{self, BackendKind};
+use crate::bitmap::Bitmap;
+use crate::chrome::WindowsVersion;
+use crate::cmd::{Cmd, CmdEvent};
+use crate::font::Font;
+use crate::framebuffer::FramebufferRenderer;
+use crate::render::{ConsoleStyle, Renderer, VisualCommandLine};
+use crate::screen::Screen;
+
+// Commands that never exit are killed after this and whatever they printed is captured
+const SCREENSHOT_TIMEOUT: Duration = Duration::from_secs(10);
+// How long output is still read after killing it, what it started in the background
+// can keep the output open forever
+const KILL_GRACE: Duration = Duration::from_secs(1);
+
+pub fn get_font_path() -> Option<PathBuf> {
+    let mut dir = std::env::home_dir()?;
+    dir.push("WinCmd");
+    dir.push("font.bmp");
+    Some(dir)
+}
+
+// Renders the screen into an off-screen framebuffer of `size` character cells
+pub fn render_to_framebuffer(font_sheet: Bitmap, screen: &Screen, size: (u32, u32), style: ConsoleStyle) -> FramebufferRenderer {
+    let font = Font::default();
+    let pixel_size = (size.0 * font.glyph_size.0 as u32, size.1 * font.glyph_size.1 as u32);
+    let mut renderer = FramebufferRenderer::new(pixel_size, font.clone(), font_sheet);
+
+    let mut visual_cmd = VisualCommandLine::new(font);
+    visual_cmd.set_style(style);
+    // Twice since the first pass decides if there's room taken by the scrollbar
+    visual_cmd.update(pixel_size, screen);
+    visual_cmd.update(pixel_size, screen);
+    visual_cmd.render(&mut renderer, screen, None);
+    renderer.present();
+
+    renderer
+}
+
+// Runs the command to completion without opening a window and saves what it printed,
+// looking like `windows` if it's given
+pub fn screenshot(path: &Path, kind: &BackendKind, command: &[String], size: (u32, u32), windows: Option<WindowsVersion>) -> Result<(), String> {
+    let font_path = get_font_path().ok_or("home directory not found")?;
+    let font_sheet = Bitmap::load_bmp(&font_path)?;
+
+    let cmd = run_headless(kind, command, size, windows, SCREENSHOT_TIMEOUT)?;
+    let screen = Screen::from_buffer(cmd.get_stdout_buffer(), 0x07);
+    let style = windows.map(|v| v.get_style()).unwrap_or_default();
+    render_to_framebuffer(font_sheet, &screen, size, style).write_png(path)
+}
+
+// Runs the command until it exits, it's killed after `timeout` and left behind
+// a little later if its output still isn't closed
+pub fn run_headless(kind: &BackendKind, command: &[String], size: (u32, u32), windows: Option<WindowsVersion>, timeout: Duration) -> Result<Cmd, String> {
+    let mut cmd = Cmd::new();
+    cmd.resize(size.0, size.1);
+    if let Some(version) = windows {
+        cmd.write_stdout(&version.get_banner());
+    }
+    cmd.attach_backend(Some(backend::spawn(kind, command, size).map_err(|e| e.to_string())?));
+
+    let start = Instant::now();
+    let mut timed_out = false;
+    'running: loop {
+        cmd.update();
+        for event in cmd.drain_events() {
+            if let CmdEvent::ChildExited = event {
+                break 'running;
+            }
+        }
+        if start.elapsed() > timeout && !timed_out {
+            cmd.destroy_child();
+            timed_out = true;
+        }
+        if start.elapsed() > timeout + KILL_GRACE {
+            cmd.update();
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    Ok(cmd)
+}
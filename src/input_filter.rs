@@ -0,0 +1,297 @@
+use std::{collections::VecDeque, fmt, time::{Duration, Instant}};
+
+use crate::responses::Rng;
+
+// Characters a line can only have so many of
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CharClass {
+    Digits,
+    Letters,
+    Upper,
+    Lower,
+    Punctuation,
+    Spaces,
+}
+
+impl CharClass {
+    pub const ALL: [CharClass; 6] = [CharClass::Digits, CharClass::Letters, CharClass::Upper, CharClass::Lower, CharClass::Punctuation, CharClass::Spaces];
+
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Self::ALL.iter().copied().find(|class| class.get_name() == s)
+            .ok_or(format!("unknown character class: {} (digits, letters, upper, lower, punctuation or spaces)", s))
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            CharClass::Digits => "digits",
+            CharClass::Letters => "letters",
+            CharClass::Upper => "upper",
+            CharClass::Lower => "lower",
+            CharClass::Punctuation => "punctuation",
+            CharClass::Spaces => "spaces",
+        }
+    }
+
+    pub fn contains(&self, c: char) -> bool {
+        match self {
+            CharClass::Digits => c.is_ascii_digit(),
+            CharClass::Letters => c.is_alphabetic(),
+            CharClass::Upper => c.is_uppercase(),
+            CharClass::Lower => c.is_lowercase(),
+            CharClass::Punctuation => c.is_ascii_punctuation(),
+            CharClass::Spaces => c.is_whitespace(),
+        }
+    }
+}
+
+// One step typed keys go through before they reach the command line. Written like
+// `lag min=50 max=400`, see `get_params` for what each one takes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputFilter {
+    // Percent chance a key is lost
+    Drop { chance: u32 },
+    // Percent chance a key comes out twice
+    Double { chance: u32 },
+    // Every key shows up between `min` and `max` milliseconds late
+    Lag { min: u64, max: u64 },
+    // Percent chance a digit changes places with a digit typed right after it
+    SwapDigits { chance: u32 },
+    // Keys of the class past `max` on a line are dropped
+    Limit { class: CharClass, max: usize },
+    // Finishing typing `from` erases it and types `to` instead
+    Rewrite { from: String, to: String },
+}
+
+fn parse_chance(value: &str) -> Result<u32, String> {
+    value.trim_end_matches('%').parse().ok().filter(|&c| c <= 100).ok_or(format!("invalid chance: {}", value))
+}
+
+fn parse_millis(value: &str) -> Result<u64, String> {
+    value.parse().map_err(|_| format!("invalid number of milliseconds: {}", value))
+}
+
+impl InputFilter {
+    // Each kind with its default parameters
+    pub fn new(kind: &str) -> Result<Self, String> {
+        Ok(match kind {
+            "drop" => InputFilter::Drop { chance: 5 },
+            "double" => InputFilter::Double { chance: 5 },
+            "lag" => InputFilter::Lag { min: 50, max: 400 },
+            "swap-digits" => InputFilter::SwapDigits { chance: 30 },
+            "limit" => InputFilter::Limit { class: CharClass::Digits, max: 3 },
+            "rewrite" => InputFilter::Rewrite { from: "exit".to_string(), to: "exti".to_string() },
+            _ => return Err(format!("unknown input filter: {}", kind)),
+        })
+    }
+
+    // `KIND name=value ...`
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut words = spec.split_whitespace();
+        let mut filter = Self::new(words.next().ok_or("empty input filter")?)?;
+        for word in words {
+            let (name, value) = word.split_once('=').ok_or(format!("expected name=value: {}", word))?;
+            filter.set_param(name, value)?;
+        }
+        Ok(filter)
+    }
+
+    pub fn get_kind(&self) -> &'static str {
+        match self {
+            InputFilter::Drop { .. } => "drop",
+            InputFilter::Double { .. } => "double",
+            InputFilter::Lag { .. } => "lag",
+            InputFilter::SwapDigits { .. } => "swap-digits",
+            InputFilter::Limit { .. } => "limit",
+            InputFilter::Rewrite { .. } => "rewrite",
+        }
+    }
+
+    pub fn get_params(&self) -> Vec<(String, String)> {
+        let params: Vec<(&str, String)> = match self {
+            InputFilter::Drop { chance } | InputFilter::Double { chance } | InputFilter::SwapDigits { chance } => vec![("chance", chance.to_string())],
+            InputFilter::Lag { min, max } => vec![("min", min.to_string()), ("max", max.to_string())],
+            InputFilter::Limit { class, max } => vec![("max", max.to_string()), ("class", class.get_name().to_string())],
+            InputFilter::Rewrite { from, to } => vec![("from", from.clone()), ("to", to.clone())],
+        };
+        params.into_iter().map(|(name, value)| (name.to_string(), value)).collect()
+    }
+
+    pub fn set_param(&mut self, name: &str, value: &str) -> Result<(), String> {
+        match (self, name) {
+            (InputFilter::Drop { chance } | InputFilter::Double { chance } | InputFilter::SwapDigits { chance }, "chance") => *chance = parse_chance(value)?,
+            (InputFilter::Lag { min, .. }, "min") => *min = parse_millis(value)?,
+            (InputFilter::Lag { max, .. }, "max") => *max = parse_millis(value)?,
+            (InputFilter::Limit { class, max }, "max") => *max = value.parse().map_err(|_| format!("invalid number of {}: {}", class.get_name(), value))?,
+            (InputFilter::Limit { class, .. }, "class") => *class = CharClass::parse(value)?,
+            (InputFilter::Rewrite { from, .. }, "from") => *from = value.to_string(),
+            (InputFilter::Rewrite { to, .. }, "to") => *to = value.to_string(),
+            _ => return Err(format!("no such parameter: {}", name)),
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for InputFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.get_kind())?;
+        for (name, value) in self.get_params() {
+            write!(f, " {}={}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+// The filters typed keys go through in order, each under a name so it can be swapped
+// out or removed again. A backspace in the output erases the key before it
+#[derive(Default)]
+pub struct InputFilters {
+    filters: Vec<(String, InputFilter)>,
+    // Keys on their way to the command line and when they get there
+    pending: VecDeque<(Instant, char)>,
+    // A digit waiting for the key it's swapped with
+    held: Option<char>,
+}
+
+impl InputFilters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Replaces the one with the same name or adds it at the end
+    pub fn set(&mut self, name: &str, filter: InputFilter) {
+        match self.filters.iter_mut().find(|f| f.0 == name) {
+            Some(entry) => entry.1 = filter,
+            None => self.filters.push((name.to_string(), filter)),
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        let len = self.filters.len();
+        self.filters.retain(|f| f.0 != name);
+        self.filters.len() != len
+    }
+
+    pub fn get(&self, name: &str) -> Option<&InputFilter> {
+        self.filters.iter().find(|f| f.0 == name).map(|f| &f.1)
+    }
+
+    pub fn list(&self) -> impl Iterator<Item = (&str, &InputFilter)> {
+        self.filters.iter().map(|f| (f.0.as_str(), &f.1))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty()
+    }
+
+    // Nothing is on its way, keys can go straight through
+    pub fn is_idle(&self) -> bool {
+        self.filters.is_empty() && self.pending.is_empty() && self.held.is_none()
+    }
+
+    // Runs a key typed at the end of `line` through every filter and queues what comes out
+    pub fn push(&mut self, c: char, line: &str, rng: &mut Rng, now: Instant) {
+        // The line as it will be once everything on its way has arrived
+        let mut line: Vec<char> = line.chars().collect();
+        for &(_, c) in &self.pending {
+            erase_or_push(&mut line, c);
+        }
+
+        let mut keys = vec![c];
+        let mut lag = Duration::ZERO;
+        for (_, filter) in &self.filters {
+            let mut out = vec![];
+            let mut seen = line.clone();
+            for c in keys {
+                if c == '\x08' {
+                    out.push(c);
+                    seen.pop();
+                    continue;
+                }
+                let start = out.len();
+                match filter {
+                    InputFilter::Drop { chance } => {
+                        if rng.range(1, 100) > *chance as i64 {
+                            out.push(c);
+                        }
+                    }
+                    InputFilter::Double { chance } => {
+                        out.push(c);
+                        if rng.range(1, 100) <= *chance as i64 {
+                            out.push(c);
+                        }
+                    }
+                    InputFilter::Lag { min, max } => {
+                        lag += Duration::from_millis(rng.range(*min as i64, *max as i64) as u64);
+                        out.push(c);
+                    }
+                    InputFilter::SwapDigits { chance } => match self.held.take() {
+                        Some(held) if c.is_ascii_digit() => out.extend([c, held]),
+                        Some(held) => out.extend([held, c]),
+                        None if c.is_ascii_digit() && rng.range(1, 100) <= *chance as i64 => self.held = Some(c),
+                        None => out.push(c),
+                    },
+                    InputFilter::Limit { class, max } => {
+                        if !class.contains(c) || seen.iter().filter(|&&s| class.contains(s)).count() < *max {
+                            out.push(c);
+                        }
+                    }
+                    InputFilter::Rewrite { from, to } => {
+                        seen.push(c);
+                        let from: Vec<char> = from.chars().collect();
+                        if !from.is_empty() && seen.ends_with(&from) {
+                            out.extend(std::iter::repeat_n('\x08', from.len() - 1));
+                            out.extend(to.chars());
+                        }
+                        else {
+                            out.push(c);
+                        }
+                        seen.pop();
+                    }
+                }
+                for &c in &out[start..] {
+                    erase_or_push(&mut seen, c);
+                }
+            }
+            keys = out;
+        }
+
+        // Keys never overtake each other however much they lag
+        let due = self.pending.back().map_or(now, |p| p.0.max(now)) + lag;
+        self.pending.extend(keys.into_iter().map(|c| (due, c)));
+    }
+
+    // Queues a key that doesn't go through the filters, behind anything still on its way
+    pub fn push_unfiltered(&mut self, c: char, now: Instant) {
+        let due = self.pending.back().map_or(now, |p| p.0.max(now));
+        self.pending.push_back((due, c));
+    }
+
+    // Keys that have arrived by `now`
+    pub fn take_due(&mut self, now: Instant) -> Vec<char> {
+        let mut keys = vec![];
+        while self.pending.front().is_some_and(|p| p.0 <= now) {
+            keys.extend(self.pending.pop_front().map(|p| p.1));
+        }
+        keys
+    }
+
+    // Everything on its way including a held back digit, for when Enter is pressed
+    pub fn take_all(&mut self) -> Vec<char> {
+        let mut keys: Vec<char> = self.pending.drain(..).map(|p| p.1).collect();
+        keys.extend(self.held.take());
+        keys
+    }
+
+    pub fn get_deadline(&self) -> Option<Instant> {
+        self.pending.front().map(|p| p.0)
+    }
+}
+
+fn erase_or_push(line: &mut Vec<char>, c: char) {
+    if c == '\x08' {
+        line.pop();
+    }
+    else {
+        line.push(c);
+    }
+}
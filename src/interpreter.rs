@@ -0,0 +1,597 @@
+use std::{fs, io, path::{Path, PathBuf}, time::{SystemTime, UNIX_EPOCH}};
+
+use crate::backend::{Backend, Signal};
+use crate::chrome::WindowsVersion;
+use crate::cp437::unicode_to_cp437;
+use crate::responses::{local_offset, Rng};
+use crate::subprocess::SubProcess;
+use crate::transcript::civil_time;
+use crate::wakeup::Wakeup;
+
+const PATH_NOT_FOUND: &str = "The system cannot find the path specified.";
+const FILE_NOT_FOUND: &str = "The system cannot find the file specified.";
+const SYNTAX_ERROR: &str = "The syntax of the command is incorrect.";
+
+// cmd.exe's own commands for when there's no cmd.exe to run, everything else is started
+// as a program. A directory stands in for C:\ and nothing above it can be reached
+pub struct Interpreter {
+    drive: PathBuf,
+    // Directories below `drive`, spelled like they are on disk
+    cwd: Vec<String>,
+    version: WindowsVersion,
+    // Names are looked up ignoring case like on Windows
+    vars: Vec<(String, String)>,
+    // `echo off` hides the prompt
+    echo: bool,
+    // What's been typed since the last newline
+    line: Vec<u8>,
+    output: Vec<u8>,
+    // A program started from the prompt, it gets the input until it exits
+    child: Option<SubProcess>,
+    color: Option<u8>,
+    exited: bool,
+    rng: Rng,
+    wakeup: Option<Wakeup>,
+}
+
+// Whitespace separated, quotes keep spaces in and are taken out
+fn split_args(s: &str) -> Vec<String> {
+    let mut args = vec![];
+    let mut arg: Option<String> = None;
+    let mut quoted = false;
+    for c in s.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                arg.get_or_insert_with(String::new);
+            }
+            c if c.is_whitespace() && !quoted => args.extend(arg.take()),
+            c => arg.get_or_insert_with(String::new).push(c),
+        }
+    }
+    args.extend(arg);
+    args
+}
+
+// `*` and `?` ignoring case, `*.*` is everything like it is on Windows
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    fn matches(pattern: &[char], name: &[char]) -> bool {
+        match pattern.split_first() {
+            None => name.is_empty(),
+            Some(('*', rest)) => (0..=name.len()).any(|i| matches(rest, &name[i..])),
+            Some((&p, rest)) => name.split_first().is_some_and(|(&n, name)| (p == '?' || p == n) && matches(rest, name)),
+        }
+    }
+    let pattern = if pattern == "*.*" { "*" } else { pattern };
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let name: Vec<char> = name.to_lowercase().chars().collect();
+    matches(&pattern, &name)
+}
+
+// The name as it's spelled on disk if there's one differing only in case
+fn find_entry(dir: &Path, name: &str) -> String {
+    if dir.join(name).exists() {
+        return name.to_string();
+    }
+    fs::read_dir(dir).into_iter().flatten().flatten()
+        .map(|e| e.file_name().to_string_lossy().into_owned())
+        .find(|e| e.to_lowercase() == name.to_lowercase())
+        .unwrap_or(name.to_string())
+}
+
+// 1,234,567
+fn group_digits(n: u64) -> String {
+    let digits = n.to_string();
+    // The first group is the short one
+    let first = (digits.len() - 1) % 3 + 1;
+    let mut grouped = digits[..first].to_string();
+    for start in (first..digits.len()).step_by(3) {
+        grouped.push(',');
+        grouped.push_str(&digits[start..start + 3]);
+    }
+    grouped
+}
+
+// `10/19/2026  09:15 PM` like `dir` shows it
+fn format_time(time: SystemTime) -> String {
+    let secs = match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    };
+    let (year, month, day, hour, minute, _) = civil_time(secs + local_offset(secs));
+    let am_pm = if hour < 12 { "AM" } else { "PM" };
+    let hour = if hour % 12 == 0 { 12 } else { hour % 12 };
+    format!("{:02}/{:02}/{:04}  {:02}:{:02} {}", month, day, year, hour, minute, am_pm)
+}
+
+#[cfg(unix)]
+fn get_free_bytes(path: &Path) -> u64 {
+    use std::os::unix::ffi::OsStrExt;
+    let Ok(path) = std::ffi::CString::new(path.as_os_str().as_bytes()) else {
+        return 0;
+    };
+    // SAFETY: statvfs is plain old data, all zeroes is a valid value for it
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: path is a NUL terminated string that outlives the call, and statvfs only writes
+    // to the struct it's given
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return 0;
+    }
+    stat.f_bavail as u64 * stat.f_frsize as u64
+}
+
+#[cfg(not(unix))]
+fn get_free_bytes(_path: &Path) -> u64 {
+    0
+}
+
+struct Entry {
+    name: String,
+    is_dir: bool,
+    size: u64,
+    modified: SystemTime,
+}
+
+impl Entry {
+    fn new(name: String, path: &Path) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+        Some(Self {
+            name,
+            is_dir: metadata.is_dir(),
+            size: metadata.len(),
+            modified: metadata.modified().unwrap_or(UNIX_EPOCH),
+        })
+    }
+}
+
+impl Interpreter {
+    pub fn new(drive: PathBuf, version: WindowsVersion) -> io::Result<Self> {
+        if !drive.is_dir() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("{:?} is not a directory", drive)));
+        }
+        let mut vars: Vec<(String, String)> = std::env::vars().collect();
+        for (name, value) in [("ComSpec", "C:\\Windows\\system32\\cmd.exe"), ("OS", "Windows_NT")] {
+            if !vars.iter().any(|v| v.0.eq_ignore_ascii_case(name)) {
+                vars.push((name.to_string(), value.to_string()));
+            }
+        }
+        let mut interpreter = Self {
+            drive,
+            cwd: vec![],
+            version,
+            vars,
+            echo: true,
+            line: vec![],
+            output: vec![],
+            child: None,
+            color: None,
+            exited: false,
+            rng: Rng::from_time(),
+            wakeup: None,
+        };
+        interpreter.prompt();
+        Ok(interpreter)
+    }
+
+    // `C:\Users\me`
+    pub fn get_path(&self) -> String {
+        format!("C:\\{}", self.cwd.join("\\"))
+    }
+
+    fn get_host_path(&self, dirs: &[String]) -> PathBuf {
+        let mut path = self.drive.clone();
+        path.extend(dirs);
+        path
+    }
+
+    // CD and RANDOM are made up every time unless they were set
+    pub fn get_var(&mut self, name: &str) -> Option<String> {
+        if let Some((_, value)) = self.vars.iter().find(|v| v.0.eq_ignore_ascii_case(name)) {
+            return Some(value.clone());
+        }
+        match name.to_ascii_uppercase().as_str() {
+            "CD" => Some(self.get_path()),
+            "RANDOM" => Some(self.rng.range(0, 32767).to_string()),
+            _ => None,
+        }
+    }
+
+    // An empty value removes it
+    pub fn set_var(&mut self, name: &str, value: &str) {
+        let found = self.vars.iter().position(|v| v.0.eq_ignore_ascii_case(name));
+        match (found, value.is_empty()) {
+            (Some(i), true) => {
+                self.vars.remove(i);
+            }
+            (Some(i), false) => self.vars[i].1 = value.to_string(),
+            (None, false) => self.vars.push((name.to_string(), value.to_string())),
+            (None, true) => (),
+        }
+    }
+
+    fn print(&mut self, s: &str) {
+        self.output.extend(s.chars().map(unicode_to_cp437));
+    }
+
+    fn println(&mut self, s: &str) {
+        self.print(s);
+        self.print("\n");
+    }
+
+    fn prompt(&mut self) {
+        if self.echo {
+            let path = self.get_path();
+            self.print(&format!("{}>", path));
+        }
+    }
+
+    // cmd.exe leaves an empty line between what a command printed and the next prompt
+    fn finish(&mut self) {
+        if self.echo && !self.exited {
+            self.print("\n");
+            self.prompt();
+        }
+    }
+
+    // `%NAME%` is replaced by the variable, left alone when there's none
+    pub fn expand(&mut self, line: &str) -> String {
+        let mut expanded = String::new();
+        let mut rest = line;
+        while let Some(start) = rest.find('%') {
+            expanded.push_str(&rest[..start]);
+            let after = &rest[start + 1..];
+            match after.find('%').and_then(|end| Some((end, self.get_var(&after[..end])?))) {
+                Some((end, value)) => {
+                    expanded.push_str(&value);
+                    rest = &after[end + 1..];
+                }
+                None => {
+                    expanded.push('%');
+                    rest = after;
+                }
+            }
+        }
+        expanded.push_str(rest);
+        expanded
+    }
+
+    // A path typed at the prompt as directories below C:\, None if it's on another drive
+    fn resolve(&self, path: &str) -> Option<Vec<String>> {
+        let path = match path.get(..2) {
+            Some(drive) if drive.eq_ignore_ascii_case("c:") => &path[2..],
+            Some(drive) if drive.ends_with(':') => return None,
+            _ => path,
+        };
+        let mut dirs = if path.starts_with(['\\', '/']) { vec![] } else { self.cwd.clone() };
+        for part in path.split(['\\', '/']) {
+            match part {
+                "" | "." => (),
+                ".." => {
+                    dirs.pop();
+                }
+                _ => {
+                    let entry = find_entry(&self.get_host_path(&dirs), part);
+                    dirs.push(entry);
+                }
+            }
+        }
+        Some(dirs)
+    }
+
+    fn run(&mut self, line: &str) {
+        let line = self.expand(line);
+        let line = line.trim().trim_start_matches('@');
+        if line.is_empty() {
+            self.prompt();
+            return;
+        }
+        // `cd..` and `echo.` work without a space
+        let end = line.find(|c: char| c.is_whitespace() || matches!(c, '.' | '/' | '\\' | ',' | ';' | '=')).unwrap_or(line.len());
+        let (name, rest) = line.split_at(end);
+        let args = rest.trim();
+        match name.to_ascii_lowercase().as_str() {
+            "cls" => {
+                self.print("\x0c");
+                self.prompt();
+                return;
+            }
+            "exit" => {
+                self.exited = true;
+                return;
+            }
+            "echo" => self.echo_command(rest),
+            "cd" | "chdir" => self.cd(args),
+            "dir" => self.dir(args),
+            "type" => self.type_files(args),
+            "set" => self.set(args),
+            "ver" => {
+                let version = self.version.get_version_line();
+                self.println(&format!("\n{}", version));
+            }
+            "title" => self.print(&format!("\x1b]0;{}\x07", args)),
+            "color" => self.color_command(args),
+            "c:" if rest.is_empty() => (),
+            drive if rest.is_empty() && drive.len() == 2 && drive.ends_with(':') => self.println("The system cannot find the drive specified."),
+            _ => {
+                if self.start(line) {
+                    return;
+                }
+            }
+        }
+        self.finish();
+    }
+
+    fn echo_command(&mut self, rest: &str) {
+        // The character after `echo` only separates it from the text
+        let mut chars = rest.chars();
+        let separator = chars.next();
+        let text = chars.as_str();
+        match separator {
+            None => {
+                let state = if self.echo { "on" } else { "off" };
+                self.println(&format!("ECHO is {}.", state));
+            }
+            Some(c) if c.is_whitespace() && text.trim().eq_ignore_ascii_case("on") => self.echo = true,
+            Some(c) if c.is_whitespace() && text.trim().eq_ignore_ascii_case("off") => self.echo = false,
+            Some(_) => self.println(text),
+        }
+    }
+
+    fn cd(&mut self, args: &str) {
+        let lower = args.to_ascii_lowercase();
+        let args = if lower.starts_with("/d ") { args[3..].trim() } else { args };
+        let path = args.trim_matches('"');
+        if path.is_empty() || path.eq_ignore_ascii_case("c:") {
+            let path = self.get_path();
+            self.println(&path);
+            return;
+        }
+        match self.resolve(path) {
+            Some(dirs) if self.get_host_path(&dirs).is_dir() => self.cwd = dirs,
+            _ => self.println(PATH_NOT_FOUND),
+        }
+    }
+
+    fn dir(&mut self, args: &str) {
+        let mut bare = false;
+        let mut all = false;
+        let mut target = String::new();
+        for arg in split_args(args) {
+            match arg.to_ascii_lowercase().as_str() {
+                "/b" => bare = true,
+                flag if flag.starts_with("/a") => all = true,
+                flag if flag.starts_with('/') => (),
+                _ => target = arg,
+            }
+        }
+        let Some(mut dirs) = self.resolve(&target) else {
+            self.println("The system cannot find the drive specified.");
+            return;
+        };
+        // A file or a pattern lists what matches it in its directory
+        let mut pattern = "*".to_string();
+        if !self.get_host_path(&dirs).is_dir() {
+            pattern = dirs.pop().unwrap_or(pattern);
+        }
+        let host = self.get_host_path(&dirs);
+        let Ok(read_dir) = fs::read_dir(&host) else {
+            self.println(PATH_NOT_FOUND);
+            return;
+        };
+
+        // Below C:\ every directory has . and .. at the top
+        let mut entries = vec![];
+        if !dirs.is_empty() && !bare {
+            let parent = self.get_host_path(&dirs[..dirs.len() - 1]);
+            for (name, path) in [(".", &host), ("..", &parent)] {
+                if matches_pattern(&pattern, name) {
+                    entries.extend(Entry::new(name.to_string(), path));
+                }
+            }
+        }
+        let mut found: Vec<Entry> = read_dir.flatten()
+            .map(|e| e.file_name().to_string_lossy().into_owned())
+            .filter(|name| (all || !name.starts_with('.')) && matches_pattern(&pattern, name))
+            .filter_map(|name| {
+                let path = host.join(&name);
+                Entry::new(name, &path)
+            })
+            .collect();
+        found.sort_by_key(|e| e.name.to_lowercase());
+        entries.extend(found);
+
+        if bare {
+            for entry in &entries {
+                self.println(&entry.name);
+            }
+            if entries.is_empty() {
+                self.println("File Not Found");
+            }
+            return;
+        }
+
+        self.println(" Volume in drive C has no label.");
+        self.println(" Volume Serial Number is 5A3C-91E2");
+        self.println("");
+        let path = format!("C:\\{}", dirs.join("\\"));
+        self.println(&format!(" Directory of {}", path));
+        self.println("");
+        if entries.is_empty() {
+            self.println("File Not Found");
+            return;
+        }
+        let (mut files, mut bytes, mut dir_count) = (0, 0, 0);
+        for entry in &entries {
+            let time = format_time(entry.modified);
+            let line = if entry.is_dir {
+                dir_count += 1;
+                format!("{}    {:<14} {}\n", time, "<DIR>", entry.name)
+            }
+            else {
+                files += 1;
+                bytes += entry.size;
+                format!("{} {:>17} {}\n", time, group_digits(entry.size), entry.name)
+            };
+            self.print(&line);
+        }
+        self.println(&format!("{:>16} File(s) {:>14} bytes", files, group_digits(bytes)));
+        self.println(&format!("{:>16} Dir(s) {:>15} bytes free", dir_count, group_digits(get_free_bytes(&host))));
+    }
+
+    // Files are written as they are, like the output of a program
+    fn type_files(&mut self, args: &str) {
+        let files = split_args(args);
+        if files.is_empty() {
+            self.println(SYNTAX_ERROR);
+        }
+        for file in files {
+            let path = self.resolve(&file).map(|dirs| self.get_host_path(&dirs));
+            match path {
+                Some(ref path) if path.is_dir() => self.println("Access is denied."),
+                Some(path) => match fs::read(&path) {
+                    Ok(contents) => self.output.extend(contents),
+                    Err(_) => self.println(FILE_NOT_FOUND),
+                },
+                None => self.println(FILE_NOT_FOUND),
+            }
+        }
+    }
+
+    fn set(&mut self, args: &str) {
+        let args = args.trim_matches('"');
+        if args.starts_with('/') {
+            self.println(SYNTAX_ERROR);
+            return;
+        }
+        if let Some((name, value)) = args.split_once('=') {
+            if name.is_empty() {
+                self.println(SYNTAX_ERROR);
+            }
+            else {
+                self.set_var(name, value);
+            }
+            return;
+        }
+        // Everything starting with it
+        let prefix = args.to_lowercase();
+        let mut found: Vec<String> = self.vars.iter()
+            .filter(|v| v.0.to_lowercase().starts_with(&prefix))
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+        found.sort_by_key(|v| v.to_lowercase());
+        if found.is_empty() {
+            self.println(&format!("Environment variable {} not defined", args));
+        }
+        for var in found {
+            self.println(&var);
+        }
+    }
+
+    // Background and foreground as two hex digits, the same as a screen color
+    fn color_command(&mut self, args: &str) {
+        if args.is_empty() {
+            self.color = Some(0x07);
+            return;
+        }
+        match u8::from_str_radix(args, 16) {
+            // Text the same color as the background can't be read, cmd.exe doesn't allow it either
+            Ok(color) if args.len() <= 2 && color >> 4 != color & 0x0f => self.color = Some(color),
+            Ok(_) if args.len() <= 2 => (),
+            _ => {
+                self.println("Sets the default console foreground and background colors.\n");
+                self.println("COLOR [attr]\n");
+                self.println("  attr        Specifies color attribute of console output");
+            }
+        }
+    }
+
+    // Starts a program, false with the error printed if there's none by that name
+    fn start(&mut self, line: &str) -> bool {
+        let args = split_args(line);
+        let dir = self.get_host_path(&self.cwd);
+        let vars = self.vars.iter().map(|(name, value)| (name.as_str(), value.as_str()));
+        match SubProcess::from_args_in(&args, &dir, vars) {
+            Some(mut child) => {
+                child.set_wakeup(self.wakeup.clone());
+                self.child = Some(child);
+                true
+            }
+            None => {
+                let name = args.first().cloned().unwrap_or_default();
+                self.println(&format!("'{}' is not recognized as an internal or external command,", name));
+                self.println("operable program or batch file.");
+                false
+            }
+        }
+    }
+
+    fn wake(&self) {
+        if let Some(ref wakeup) = self.wakeup {
+            wakeup.wake();
+        }
+    }
+}
+
+impl Backend for Interpreter {
+    fn write_input(&mut self, bytes: &[u8]) -> io::Result<()> {
+        if self.exited {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+        for (i, &b) in bytes.iter().enumerate() {
+            // Whatever comes after the line that started a program is for the program
+            if let Some(ref mut child) = self.child {
+                return child.write_input(&bytes[i..]);
+            }
+            match b {
+                b'\n' => {
+                    let line = std::mem::take(&mut self.line);
+                    self.run(&String::from_utf8_lossy(&line));
+                }
+                b'\r' => (),
+                _ => self.line.push(b),
+            }
+        }
+        self.wake();
+        Ok(())
+    }
+
+    fn read_output(&mut self) -> Vec<u8> {
+        if let Some(ref mut child) = self.child {
+            // Checked first so nothing it writes can come after the prompt
+            let done = child.is_exited();
+            let output = child.read_output();
+            self.output.extend(output);
+            if done {
+                self.child = None;
+                self.finish();
+            }
+        }
+        std::mem::take(&mut self.output)
+    }
+
+    // Ctrl+C only stops a program, at the prompt there's nothing to stop
+    fn signal(&mut self, signal: Signal) -> io::Result<()> {
+        if signal != Signal::Interrupt {
+            self.exited = true;
+        }
+        match self.child {
+            Some(ref mut child) => child.signal(signal),
+            None => Ok(()),
+        }
+    }
+
+    fn is_exited(&mut self) -> bool {
+        self.exited && self.child.is_none() && self.output.is_empty()
+    }
+
+    fn set_wakeup(&mut self, wakeup: Option<Wakeup>) {
+        if let Some(ref mut child) = self.child {
+            child.set_wakeup(wakeup.clone());
+        }
+        self.wakeup = wakeup;
+    }
+
+    fn take_color(&mut self) -> Option<u8> {
+        self.color.take()
+    }
+}
@@ -0,0 +1,120 @@
+use std::ops::Range;
+
+use crate::font::Font;
+use crate::screen::Screen;
+use crate::scrollback::ChangeMark;
+
+// Where every wrapped row of the screen text starts, so drawing and scrolling
+// only have to look at the rows that are visible. Output that's appended only
+// lays out the last row again instead of the whole scrollback
+pub struct LineIndex {
+    glyph_size: (i32, i32),
+    right_bound: u32,
+    mark: Option<ChangeMark>,
+    base: usize,
+    // Absolute offset (see `Scrollback`) of the first character of every row, the first one is always `base`
+    rows: Vec<usize>,
+    end_x: i32,
+}
+
+impl LineIndex {
+    pub fn new(font: &Font) -> Self {
+        Self {
+            glyph_size: (font.glyph_size.0 as i32, font.glyph_size.1 as i32),
+            right_bound: 0,
+            mark: None,
+            base: 0,
+            rows: vec![0],
+            end_x: 0,
+        }
+    }
+
+    pub fn update(&mut self, screen: &Screen, right_bound: u32) {
+        let buffer = screen.get_buffer();
+        let changed_from = match self.mark {
+            Some(ref mark) if right_bound == self.right_bound => buffer.get_changed_since(mark),
+            _ => Some(buffer.get_base()),
+        };
+        self.right_bound = right_bound;
+        self.mark = Some(buffer.mark());
+        if let Some(offset) = changed_from {
+            self.layout_from(buffer.as_str(), buffer.get_base(), offset);
+        }
+    }
+
+    // Same wrapping rules as `Font::layout_string_wrapped`
+    fn layout_from(&mut self, text: &str, base: usize, mut offset: usize) {
+        // Rows that were trimmed out of the scrollback
+        let trimmed = self.rows.partition_point(|&start| start < base);
+        self.rows.drain(..trimmed);
+        if self.rows.first() != Some(&base) {
+            self.rows = vec![base];
+            offset = base;
+        }
+        self.base = base;
+
+        let row = self.get_row_at_offset(offset);
+        self.rows.truncate(row + 1);
+
+        let start = self.rows[row];
+        let (gw, _) = self.glyph_size;
+        let mut x = 0;
+        for (i, glyph) in text[start - base..].char_indices() {
+            match glyph {
+                '\r' => (),
+                '\n' => {
+                    self.rows.push(start + i + 1);
+                    x = 0;
+                    continue;
+                },
+                '\t' => x += gw * 4,
+                _ => x += gw,
+            }
+            if x + gw > self.right_bound as i32 {
+                self.rows.push(start + i + glyph.len_utf8());
+                x = 0;
+            }
+        }
+        self.end_x = x;
+    }
+
+    pub fn get_row_count(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn get_row_start(&self, row: usize) -> usize {
+        self.rows[row]
+    }
+
+    // Absolute offset of the first row, subtract it from row starts to index into the screen text
+    pub fn get_base(&self) -> usize {
+        self.base
+    }
+
+    // The row the byte at the absolute `offset` is drawn on
+    pub fn get_row_at_offset(&self, offset: usize) -> usize {
+        self.rows.partition_point(|&start| start <= offset).saturating_sub(1)
+    }
+
+    // Pixel position right after the last glyph, where the caret goes
+    pub fn get_end(&self) -> (i32, i32) {
+        (self.end_x, (self.rows.len() as i32 - 1) * self.glyph_size.1)
+    }
+
+    // Rows at least partially inside a window of `height` pixels scrolled down by `scroll`
+    pub fn get_visible_rows(&self, scroll: u32, height: u32) -> Range<usize> {
+        let gh = self.glyph_size.1 as u32;
+        let first = (scroll / gh) as usize;
+        let last = (scroll + height).div_ceil(gh) as usize;
+        first.min(self.rows.len())..last.min(self.rows.len())
+    }
+
+    // The text of `rows`, which can be laid out on its own starting from the top of the first row
+    pub fn get_rows_text<'a>(&self, text: &'a str, rows: Range<usize>) -> &'a str {
+        if rows.is_empty() {
+            return "";
+        }
+        let end = self.rows.get(rows.end).map_or(text.len(), |&end| end - self.base);
+        &text[self.rows[rows.start] - self.base..end]
+    }
+}
@@ -0,0 +1,33 @@
+// Terminal model without any windowing, the SDL window in main.rs is just one frontend for it
+pub mod asciicast;
+pub mod backend;
+pub mod bitmap;
+pub mod chrome;
+pub mod cmd;
+pub mod cp437;
+pub mod font;
+pub mod framebuffer;
+pub mod headless;
+pub mod input_filter;
+pub mod interpreter;
+pub mod layout;
+pub mod options;
+pub mod panel;
+pub mod prank;
+pub mod profile;
+pub mod progress;
+#[cfg(unix)]
+pub mod pty;
+pub mod remote;
+pub mod render;
+pub mod responses;
+pub mod screen;
+pub mod scrollback;
+pub mod search;
+pub mod serial;
+pub mod subprocess;
+pub mod substitute;
+pub mod telnet;
+pub mod textdump;
+pub mod transcript;
+pub mod wakeup;
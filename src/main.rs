@@ -1,14 +1,13 @@
 #![windows_subsystem = "windows"]
 extern crate sdl2;
 
-mod cp437;
-mod screen;
-mod cmd;
-mod font;
-mod render;
-mod subprocess;
+mod sdl_renderer;
 
-use cmd::{Cmd, CmdEvent};
+use sdl_renderer::SdlRenderer;
+use wcmd::asciicast::Player;
+use wcmd::backend::{BackendKind, Signal};
+use wcmd::chrome::{Chrome, WindowsVersion};
+use wcmd::cmd::{Cmd, CmdEvent};
 use sdl2::event::{Event, WindowEvent};
 use sdl2::keyboard::Keycode;
 use sdl2::{
@@ -16,35 +15,74 @@ use sdl2::{
     mouse::{MouseButton, MouseWheelDirection},
     pixels::Color,
 };
-use subprocess::SubProcess;
-use std::{convert::TryInto, process::{Command, Stdio}, time::Duration};
+use wcmd::options::Options;
+use wcmd::profile::{get_profile_path, Profile};
+use wcmd::remote::{self, RemoteControl};
+use wcmd::responses::Responses;
+use wcmd::search::Search;
+use wcmd::panel::{ControlPanel, PanelKey};
+use wcmd::prank::{KeyChord, PrankContext, PrankModes};
+use wcmd::substitute::Rules;
+use wcmd::transcript::TranscriptConfig;
+use wcmd::wakeup::Wakeup;
+use std::{convert::TryInto, process::{Command, Stdio}, time::{Duration, Instant}};
 
-const JF_UNFOCUS_AFTER_KEY: u16 = 0b1000_0000_0000_0000;
-const JF_ROLL_COLOR_AFTER_KEY: u16 = 0b0100_0000_0000_0000;
-const JF_DISALLOW_MORE_THAN_3_DIGITS_ON_LINE: u16 = 0b0010_0000_0000_0000;
-const JF_ALWAYS_ON_TOP: u16 = 0b0001_0000_0000_0000;
-const JF_SUBSTITUTE: u16 = 0b0000_1000_0000_0000;
-const JF_SCROLL_UP: u16 = 0b0000_0100_0000_0000;
+// Same as the Windows default caret blink rate
+const CARET_BLINK: Duration = Duration::from_millis(530);
 
-pub fn bitflip(mut s: u16, b: u16) -> u16 {
-    s ^= b;
-    s
+// How long the colors stay inverted when the child rings the bell
+const VISUAL_BELL: Duration = Duration::from_millis(100);
+
+// Pushed from the subprocess reader threads so the loop can sleep until there's output
+struct ChildOutput;
+
+// What the prank modes get to change, borrowed again for every hook
+macro_rules! prank_context {
+    ($cmd:expr, $visual_cmd:expr, $focus_lost:expr) => {
+        PrankContext { cmd: &mut $cmd, visual_cmd: &mut $visual_cmd, focus_lost: &mut $focus_lost, raise_window: false }
+    };
+}
+
+fn key_chord(keycode: Keycode, keymod: Mod) -> KeyChord {
+    let mut chord = KeyChord::new(&keycode.name());
+    chord.ctrl = keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD);
+    chord.alt = keymod.intersects(Mod::LALTMOD | Mod::RALTMOD);
+    chord.shift = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+    chord
 }
 
 pub fn main() {
-    let mut color_roll = 0;
-    let mut joke_bitmap = 0_u16;
-    use font::*;
-    use render::*;
-    use screen::*;
+    use wcmd::font::*;
+    use wcmd::render::*;
+    use wcmd::screen::*;
+    let options = Options::from_args(std::env::args());
+    if let Ok(Options { screenshot: Some(ref path), ref backend, ref command, screenshot_size, windows, .. }) = options {
+        if let Err(e) = wcmd::headless::screenshot(path, backend, command, screenshot_size, windows) {
+            eprintln!("wcmd: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let mut cmd = Cmd::new();
-    let mut screen = Screen::new(0x07);
+    let mut screen = Screen::from_buffer(cmd.get_stdout_buffer(), 0x07);
+    let mut transcript_config = TranscriptConfig::default();
+    let mut player: Option<Player> = None;
+    let mut rules = Rules::default();
+    let mut bindings = vec![];
+    let mut panel_key = ControlPanel::default_key();
+    let mut mirror = false;
+    let mut control = None;
+    let mut profile = None;
+    // Modes and filters the command line set up, they win over the profile
+    let mut from_command_line = vec![];
+    let mut windows: Option<WindowsVersion> = None;
+    let mut chrome = Chrome::new(false);
+    // What Escape starts again
+    let mut backend = BackendKind::Process;
+    let mut command = vec![];
 
-    let default_font = Font {
-        arrangment: FontArrangment::ASCII,
-        glyph_size: (8, 16),
-        sheet_width: 255,
-    };
+    let default_font = Font::default();
 
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
@@ -74,14 +112,75 @@ pub fn main() {
                 font_surface = Some(sdl2::surface::Surface::load_bmp("./font.bmp").unwrap());
                 smiley_surface = Some(sdl2::surface::Surface::load_bmp("./smiley.bmp").unwrap());
                 std::env::set_current_dir(cwd);
-                if std::env::args().count() > 1 {
-                    cmd.attach_child(SubProcess::from_args(std::env::args()));
-                }
-                else {
-                    cmd.attach_child(SubProcess::from_cmd("real_cmd"));
-                }
-                if !cmd.is_handling_subprocess() {
-                    cmd.write_stdout("real_cmd.exe not found (you might have run the executable directly or installation is broken");
+                match options {
+                    Ok(options) => {
+                        cmd.set_scrollback_limit(options.scrollback);
+                        transcript_config = options.transcript;
+                        if options.log {
+                            if let Err(e) = cmd.start_transcript(&transcript_config) {
+                                cmd.write_stdout(&format!("wcmd: could not open log file: {}\n", e));
+                            }
+                        }
+                        if let Some(path) = options.record {
+                            if let Err(e) = cmd.start_recording(&path) {
+                                cmd.write_stdout(&format!("wcmd: could not record to {:?}: {}\n", path, e));
+                            }
+                        }
+                        if let Some(path) = options.substitutions {
+                            from_command_line.push("substitute".to_string());
+                            match Rules::load(&path) {
+                                Ok(loaded) => rules = loaded,
+                                Err(e) => cmd.write_stdout(&format!("wcmd: could not load substitutions: {}\n", e)),
+                            }
+                        }
+                        if let Some(path) = options.responses {
+                            match Responses::load(&path) {
+                                Ok(responses) => cmd.set_responses(Some(responses)),
+                                Err(e) => cmd.write_stdout(&format!("wcmd: could not load responses: {}\n", e)),
+                            }
+                        }
+                        bindings = options.bindings;
+                        for filter in options.filters {
+                            from_command_line.push(filter.get_kind().to_string());
+                            cmd.input_filters.set(filter.get_kind(), filter);
+                        }
+                        panel_key = options.panel_key;
+                        mirror = options.mirror;
+                        control = options.control;
+                        windows = options.windows;
+                        chrome = Chrome::new(options.administrator);
+                        if let Some(version) = windows {
+                            cmd.write_stdout(&version.get_banner());
+                        }
+                        profile = options.profile.and_then(|name| get_profile_path(&name));
+                        if let Some(path) = options.play {
+                            match Player::load(&path) {
+                                Ok(p) => {
+                                    let (cols, rows) = p.get_size();
+                                    canvas.window_mut().set_size(cols * 8, rows * 16).unwrap();
+                                    cmd.resize(cols, rows);
+                                    player = Some(p);
+                                }
+                                Err(e) => {
+                                    cmd.write_stdout(&format!("wcmd: could not play {:?}: {}", path, e));
+                                }
+                            }
+                        }
+                        else {
+                            backend = options.backend;
+                            command = options.command;
+                            match wcmd::backend::spawn(&backend, &command, (80, 25)) {
+                                Ok(backend) => cmd.attach_backend(Some(backend)),
+                                Err(_) if command.is_empty() && backend == BackendKind::Process => {
+                                    cmd.write_stdout("real_cmd.exe not found (you might have run the executable directly or installation is broken");
+                                }
+                                Err(e) => cmd.write_stdout(&format!("wcmd: {}", e)),
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        cmd.write_stdout(&format!("wcmd: {}", e));
+                    }
                 }
             }
             Err(e) => {
@@ -100,69 +199,321 @@ pub fn main() {
            
     let mut font_texture = font_surface.as_texture(&texture_creator).unwrap();
     let mut smiley_texture = smiley_surface.as_texture(&texture_creator).unwrap();   
-    let mut visual_cmd = VisualCommandLine::new(font_texture, default_font);
+    let style = windows.map(|v| v.get_style()).unwrap_or_default();
+    let mut visual_cmd = VisualCommandLine::new(default_font.clone());
+    visual_cmd.set_style(style);
+
+    // A second window for the operator with the same session and the panel always open
+    let mirror_canvas = if mirror {
+        let window = video_subsystem.window("wcmd operator", 80 * 8, 25 * 16).resizable().build().unwrap();
+        Some(window.into_canvas().build().unwrap())
+    } else {
+        None
+    };
+    let mirror_texture_creator = mirror_canvas.as_ref().map(|c| c.texture_creator());
+    let mut mirror = mirror_canvas.zip(mirror_texture_creator.as_ref()).map(|(canvas, texture_creator)| {
+        let texture = font_surface.as_texture(texture_creator).unwrap();
+        let mut mirror_cmd = VisualCommandLine::new(default_font.clone());
+        mirror_cmd.set_style(style);
+        (SdlRenderer::new(canvas, texture, default_font.clone()), mirror_cmd)
+    });
+    let mirror_id = mirror.as_ref().map(|m| m.0.canvas.window().id());
+    let mut panel = ControlPanel::new(default_font.clone(), panel_key);
+    let mut pranks = PrankModes::with_builtins(rules);
+    // These are matched before or after the modes' keys, a mode bound to one would never see it
+    // or would take it away
+    pranks.reserve(panel.get_key().clone(), "the operator panel");
+    // Behind the same modifiers as the modes, whoever is typing shouldn't stop the logging
+    let transcript_key = KeyChord::parse("Ctrl+Shift+F7").expect("valid key");
+    pranks.reserve(transcript_key.clone(), "logging");
+    for (mode, key) in bindings {
+        if let Err(e) = pranks.bind(&mode, key) {
+            cmd.write_stdout(&format!("wcmd: --bind: {}\n", e));
+        }
+    }
+    let mut renderer = SdlRenderer::new(canvas, font_texture, default_font);
+    let main_id = renderer.canvas.window().id();
+
+    let event_subsystem = sdl_context.event().unwrap();
+    event_subsystem.register_custom_event::<ChildOutput>().unwrap();
+    let event_sender = event_subsystem.event_sender();
+    let wakeup = Wakeup::new(move || {
+        if let Err(e) = event_sender.push_custom_event(ChildOutput) {
+            eprintln!("{}: Could not push wakeup: {}", line!(), e);
+        }
+    });
+    cmd.set_wakeup(wakeup.clone());
+
+    let remote = control.and_then(|address| match RemoteControl::bind(&address, Some(wakeup)) {
+        Ok(remote) => {
+            eprintln!("Listening for control connections on {}", remote.get_address());
+            Some(remote)
+        }
+        Err(e) => {
+            cmd.write_stdout(&format!("wcmd: could not listen on {:?}: {}\n", address, e));
+            None
+        }
+    });
+    // Colors before the prank modes get to change them
+    let mut base_color = 0x07;
+
+    // No point drawing faster than the display refreshes
+    let refresh_rate = match renderer.canvas.window().display_mode() {
+        Ok(mode) if mode.refresh_rate > 0 => mode.refresh_rate,
+        _ => 60,
+    };
+    let frame_time = Duration::from_secs(1) / refresh_rate as u32;
+    let mut last_frame: Option<Instant> = None;
+    let mut next_blink = Instant::now() + CARET_BLINK;
+    let mut needs_redraw = true;
 
     let mut event_pump = sdl_context.event_pump().unwrap();
     let mut focus_lost = false;
+    // A profile that isn't there yet is made when the window closes
+    if let Some(path) = profile.as_ref().filter(|p| p.exists()) {
+        match Profile::load(path) {
+            Ok(mut loaded) => {
+                loaded.leave_out(&from_command_line);
+                let mut ctx = prank_context!(cmd, visual_cmd, focus_lost);
+                for e in loaded.apply(&mut pranks, &mut ctx, &mut base_color) {
+                    cmd.write_stdout(&format!("wcmd: profile: {}\n", e));
+                }
+            }
+            Err(e) => cmd.write_stdout(&format!("wcmd: could not load profile: {}\n", e)),
+        }
+    }
+    let mut search: Option<Search> = None;
+    let mut bell_until: Option<Instant> = None;
+    let mut last_tick = Instant::now();
+    let mut title = "Command Prompt".to_string();
     'running: loop {
         if cmd.is_exited() {
             break;
         }
 
-        if (joke_bitmap & JF_SCROLL_UP) > 0 {
-            visual_cmd.scroll_by(-1);
+        let dt = last_tick.elapsed().as_secs_f64();
+        last_tick = Instant::now();
+        if let Some(ref mut p) = player {
+            if let Some((cols, rows)) = p.advance(dt, &mut cmd) {
+                renderer.canvas.window_mut().set_size(cols * 8, rows * 16).unwrap();
+            }
+            let status = format!("Command Prompt - {}", p.status());
+            if status != title {
+                renderer.canvas.window_mut().set_title(&status).unwrap();
+                title = status;
+            }
+        }
+
+        pranks.update(dt, &mut prank_context!(cmd, visual_cmd, focus_lost));
+
+        // Playback and some jokes change every frame, otherwise sleep until
+        // there's an event, output from the child or the caret has to blink
+        let animating = player.as_ref().is_some_and(|p| !p.is_paused()) || pranks.is_animating();
+        let mut deadline = next_blink;
+        if needs_redraw || animating {
+            deadline = deadline.min(last_frame.map_or_else(Instant::now, |t| t + frame_time));
         }
-        canvas.set_draw_color(Color::RGB(0, 0, 0));
-        canvas.clear();
-        for event in event_pump.poll_iter() {
+        if let Some(until) = bell_until {
+            deadline = deadline.min(until);
+        }
+        if let Some(due) = cmd.get_deadline() {
+            deadline = deadline.min(due);
+        }
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        let first_event = if timeout.is_zero() {
+            event_pump.poll_event()
+        } else {
+            // Rounded up so we don't wake up just before the deadline and spin
+            event_pump.wait_event_timeout((timeout.as_micros() as u32).div_ceil(1000))
+        };
+
+        for event in first_event.into_iter().chain(event_pump.poll_iter()) {
+            // Wakeups only matter if they produce output, which is handled below
+            if !event.is_user_event() {
+                needs_redraw = true;
+            }
+            if let Event::KeyDown { .. } | Event::TextInput { .. } = event {
+                visual_cmd.show_caret();
+                next_blink = Instant::now() + CARET_BLINK;
+            }
             match event {
                 Event::Quit { .. } => {
                     cmd.destroy_child();
                     break 'running;
                 }
+                // With a mirror open closing the main window doesn't quit by itself
+                Event::Window { window_id, win_event: WindowEvent::Close, .. } if window_id == main_id => {
+                    cmd.destroy_child();
+                    break 'running;
+                }
+                Event::Window { window_id, win_event: WindowEvent::Close, .. } if Some(window_id) == mirror_id => {
+                    mirror = None;
+                }
+                Event::Window { window_id, win_event: WindowEvent::Resized(..) | WindowEvent::FocusGained, .. } if Some(window_id) == mirror_id => {
+                    if let (Some((ref mut mirror_renderer, _)), Some(ref texture_creator)) = (&mut mirror, &mirror_texture_creator) {
+                        mirror_renderer.set_font_texture(font_surface.as_texture(texture_creator).unwrap());
+                    }
+                }
                 Event::KeyDown {
-                    keycode: Some(Keycode::F1),
+                    keycode: Some(keycode),
+                    keymod,
                     ..
-                } => {
-                    joke_bitmap = bitflip(joke_bitmap, JF_UNFOCUS_AFTER_KEY);
+                } if key_chord(keycode, keymod) == *panel.get_key() => {
+                    panel.toggle();
                 }
+                // Keys in the mirror and while the panel is open are the operator's
                 Event::KeyDown {
-                    keycode: Some(Keycode::F2),
+                    window_id,
+                    keycode: Some(keycode),
                     ..
-                } => {
-                    joke_bitmap = bitflip(joke_bitmap, JF_ROLL_COLOR_AFTER_KEY);
+                } if panel.is_open() || Some(window_id) == mirror_id => {
+                    let key = match keycode {
+                        Keycode::Up => Some(PanelKey::Up),
+                        Keycode::Down => Some(PanelKey::Down),
+                        Keycode::Return => Some(PanelKey::Enter),
+                        Keycode::Backspace => Some(PanelKey::Backspace),
+                        Keycode::Escape => Some(PanelKey::Escape),
+                        _ => None,
+                    };
+                    if let Some(key) = key {
+                        let mut ctx = prank_context!(cmd, visual_cmd, focus_lost);
+                        panel.key(key, &mut pranks, &mut ctx);
+                        if ctx.raise_window {
+                            renderer.canvas.window_mut().raise();
+                        }
+                    }
                 }
+                Event::TextInput { window_id, text, .. } if panel.is_open() || Some(window_id) == mirror_id => {
+                    panel.text_input(&text);
+                }
+                event if mirror_id.is_some() && event.get_window_id() == mirror_id => {}
                 Event::KeyDown {
-                    keycode: Some(Keycode::F3),
+                    keycode: Some(Keycode::F),
+                    keymod,
                     ..
-                } => {
-                    joke_bitmap = bitflip(joke_bitmap, JF_DISALLOW_MORE_THAN_3_DIGITS_ON_LINE);
+                } if search.is_none() && keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                    let mut s = Search::new();
+                    s.update(&screen.get_buffer());
+                    search = Some(s);
                 }
                 Event::KeyDown {
-                    keycode: Some(Keycode::F4),
+                    keycode: Some(Keycode::R),
+                    keymod,
                     ..
-                } => {
-                    joke_bitmap = bitflip(joke_bitmap, JF_ALWAYS_ON_TOP);
+                } if search.is_some() && keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                    if let Some(ref mut s) = search {
+                        s.toggle_mode(&screen.get_buffer());
+                        if let Some((offset, _)) = s.get_current() {
+                            visual_cmd.scroll_to_offset(renderer.canvas.window().size(), &screen, offset);
+                        }
+                    }
                 }
                 Event::KeyDown {
-                    keycode: Some(Keycode::F5),
+                    keycode: Some(Keycode::C),
+                    keymod,
                     ..
-                } => {
-                    joke_bitmap = bitflip(joke_bitmap, JF_SUBSTITUTE);
-                    cmd.trigger_stdout_update();
+                } if search.is_none() && player.is_none() && keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                    cmd.signal_child(Signal::Interrupt);
                 }
                 Event::KeyDown {
-                    keycode: Some(Keycode::F6),
+                    keycode: Some(Keycode::Escape),
                     ..
-                } => {
-                    joke_bitmap = bitflip(joke_bitmap, JF_SCROLL_UP);
+                } if search.is_some() => {
+                    search = None;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } if search.is_some() => {
+                    if let Some(ref mut s) = search {
+                        s.pop(&screen.get_buffer());
+                        if let Some((offset, _)) = s.get_current() {
+                            visual_cmd.scroll_to_offset(renderer.canvas.window().size(), &screen, offset);
+                        }
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Return),
+                    keymod,
+                    ..
+                } if search.is_some() => {
+                    if let Some(ref mut s) = search {
+                        let found = if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                            s.prev_match()
+                        } else {
+                            s.next_match()
+                        };
+                        if let Some((offset, _)) = found {
+                            visual_cmd.scroll_to_offset(renderer.canvas.window().size(), &screen, offset);
+                        }
+                    }
+                }
+                Event::TextInput { text, .. } if search.is_some() => {
+                    if let Some(ref mut s) = search {
+                        for i in text.chars() {
+                            s.push(i, &screen.get_buffer());
+                        }
+                        if let Some((offset, _)) = s.get_current() {
+                            visual_cmd.scroll_to_offset(renderer.canvas.window().size(), &screen, offset);
+                        }
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    ..
+                } if player.is_some() && search.is_none() => {
+                    if let Some(ref mut p) = player {
+                        let resized = match keycode {
+                            Keycode::Space => { p.toggle_pause(); None }
+                            Keycode::Up => { p.faster(); None }
+                            Keycode::Down => { p.slower(); None }
+                            Keycode::Left => p.seek(p.get_time() - 5.0, &mut cmd),
+                            Keycode::Right => p.seek(p.get_time() + 5.0, &mut cmd),
+                            Keycode::Home => p.seek(0.0, &mut cmd),
+                            Keycode::Escape => break 'running,
+                            _ => None,
+                        };
+                        if let Some((cols, rows)) = resized {
+                            renderer.canvas.window_mut().set_size(cols * 8, rows * 16).unwrap();
+                        }
+                    }
+                }
+                // Recordings can't be typed into
+                Event::TextInput { .. } if player.is_some() => {}
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    keymod,
+                    ..
+                } if pranks.is_bound(&key_chord(keycode, keymod)) => {
+                    let mut ctx = prank_context!(cmd, visual_cmd, focus_lost);
+                    pranks.on_key(&key_chord(keycode, keymod), &mut ctx);
+                    if ctx.raise_window {
+                        renderer.canvas.window_mut().raise();
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    keymod,
+                    ..
+                } if key_chord(keycode, keymod) == transcript_key => {
+                    // Whether it's logging shows in the title
+                    let started = match cmd.stop_transcript() {
+                        Some(_) => Ok(()),
+                        None => cmd.start_transcript(&transcript_config).map(|_| ()),
+                    };
+                    if let Err(e) = started {
+                        cmd.write_stdout(&format!("wcmd: could not open log file: {}\n", e));
+                    }
                 }
                 Event::KeyDown {
                     keycode: Some(Keycode::Escape),
                     ..
                 } => {
                     cmd.destroy_child();
-                    cmd.attach_child(SubProcess::from_cmd("real_cmd"));
+                    match wcmd::backend::spawn(&backend, &command, (80, 25)) {
+                        Ok(child) => cmd.attach_backend(Some(child)),
+                        Err(e) => eprintln!("{}: Could not restart: {}", line!(), e),
+                    }
                 }
                 Event::KeyDown {
                     keycode: Some(Keycode::Backspace),
@@ -176,98 +527,147 @@ pub fn main() {
                     keycode: Some(Keycode::Return),
                     ..
                 } => {
-                    if !focus_lost {
-                        cmd.put_stdout('\n');
-                        cmd.flush_stdin();
+                    if let Some(line) = pranks.enter(&mut prank_context!(cmd, visual_cmd, focus_lost)) {
+                        // Only cmd.exe's prompt says when the command is over
+                        if windows.is_some() {
+                            chrome.on_line(&line);
+                        }
                     }
                 }
                 Event::Window { win_event: WindowEvent::Resized(_, _), .. } |
                 Event::Window { win_event: WindowEvent::FocusGained, .. } => {
                     // This is needed because for some strange reason
                     // Because DirectX9 device is becoming 'lost'
+                    let (width, height) = renderer.canvas.window().size();
+                    cmd.resize(width / 8, height / 16);
                     font_texture = font_surface.as_texture(&texture_creator).unwrap();
-                    visual_cmd.set_font_texture(font_texture);
+                    renderer.set_font_texture(font_texture);
                     smiley_texture = smiley_surface.as_texture(&texture_creator).unwrap();   
                 }
+                Event::Window { win_event: WindowEvent::FocusLost, .. } => {
+                    let mut ctx = prank_context!(cmd, visual_cmd, focus_lost);
+                    pranks.on_focus_lost(&mut ctx);
+                    if ctx.raise_window {
+                        renderer.canvas.window_mut().raise();
+                    }
+                }
                 Event::MouseWheel { y, .. } => {
                     visual_cmd.scroll_by(-y * 16);
                 }
-                Event::TextInput { text, .. } => {
-                    if !focus_lost {
-                        visual_cmd.lock_scroll();
-                        for i in text.chars() {
-                            let should_put = if (joke_bitmap
-                                & JF_DISALLOW_MORE_THAN_3_DIGITS_ON_LINE)
-                                > 0
-                            {
-                                !(i.is_digit(10)
-                                    && cmd.get_stdin().chars().filter(|c| c.is_digit(10)).count()
-                                        >= 3)
-                            } else {
-                                true
-                            };
-
-                            if should_put {
-                                cmd.put_stdin(i);
-                            }
-                        }
-                        if (joke_bitmap & JF_ROLL_COLOR_AFTER_KEY) > 0 {
-                            color_roll += 1;
-                        }
-                    }
-                    // We are going to simulate focus here
-                    if (joke_bitmap & JF_UNFOCUS_AFTER_KEY) > 0 {
-                        focus_lost = true;
-                    }
+                Event::TextInput { text, .. } if !focus_lost => {
+                    pranks.type_text(&text, &mut prank_context!(cmd, visual_cmd, focus_lost));
                 }
                 Event::MouseMotion {
                     xrel, yrel, x, y, ..
                 } => {
                     sdl_context
                         .mouse()
-                        .capture(visual_cmd.mouse_move(&canvas, (x, y), yrel));
+                        .capture(visual_cmd.mouse_move(&renderer, (x, y), yrel));
                 }
                 Event::MouseButtonDown { x, y, .. } => {
                     focus_lost = false;
-                    visual_cmd.mouse_press(&canvas, (x, y));
+                    visual_cmd.mouse_press(&renderer, (x, y));
                 }
                 Event::MouseButtonUp { x, y, .. } => {
-                    visual_cmd.mouse_release(&canvas, (x, y));
+                    visual_cmd.mouse_release(&renderer, (x, y));
                 }
                 _ => {}
             }
         }
 
-        if (joke_bitmap & JF_ALWAYS_ON_TOP) > 0 {
-            canvas.window_mut().raise()
+        if Instant::now() >= next_blink {
+            if !focus_lost {
+                visual_cmd.blink();
+                needs_redraw = true;
+            }
+            next_blink = Instant::now() + CARET_BLINK;
+        }
+
+        if bell_until.is_some_and(|until| Instant::now() >= until) {
+            bell_until = None;
+            needs_redraw = true;
         }
 
-        screen.color = ((color_roll % 8) << 4) | ((color_roll + 7) % 8);
+        cmd.update();
         for event in cmd.drain_events() {
             match event {
                 CmdEvent::ChildExited => {
                     break 'running;
                 },
-                CmdEvent::StdoutChanged => {
-                    let mut s = cmd.get_stdout().to_string();
-                    if (joke_bitmap & JF_SUBSTITUTE) > 0 {
-                        s = s.replace("Foreign", "Trusted")
-                            .replace("ESTABLISHED", "SECURE")
-                            .replace("REFUND", "AIRPLANE")
-                            .replace("Refund", "Airplane")
-                            .replace("refund", "airplane");
-                    }
-                    screen.set_text(s);
+                CmdEvent::StdoutChanged(_) => {
+                    pranks.on_output(&mut prank_context!(cmd, visual_cmd, focus_lost));
+                    chrome.on_output(cmd.get_stdout().rsplit('\n').next().unwrap_or_default());
+                    needs_redraw = true;
+                }
+                CmdEvent::TitleChanged(new_title) => chrome.set_title(&new_title),
+                CmdEvent::ColorChanged(color) => {
+                    base_color = color;
+                    needs_redraw = true;
+                }
+                CmdEvent::Bell => {
+                    bell_until = Some(Instant::now() + VISUAL_BELL);
+                    needs_redraw = true;
                 }
             }
         }
-        cmd.update();
 
-        if !focus_lost {
-            visual_cmd.update(canvas.window().size(), &screen);
+        // Playback keeps its status in the title
+        if player.is_none() {
+            let mut new_title = chrome.get_title();
+            if let Some(transcript) = cmd.get_transcript() {
+                new_title = format!("{} - Logging to {}", new_title, transcript.get_path().display());
+            }
+            if new_title != title {
+                renderer.canvas.window_mut().set_title(&new_title).unwrap();
+                title = new_title;
+            }
+        }
+
+        for request in remote.iter().flat_map(|r| r.poll()) {
+            let mut ctx = prank_context!(cmd, visual_cmd, focus_lost);
+            let reply = remote::handle(&request.request, &mut pranks, &mut ctx, &mut base_color);
+            if ctx.raise_window {
+                renderer.canvas.window_mut().raise();
+            }
+            request.respond(reply);
+            // Whatever it changed is picked up by the next update
+            needs_redraw = true;
+        }
+
+        let frame_due = last_frame.is_none_or(|t| t.elapsed() >= frame_time);
+        if (needs_redraw || animating) && frame_due {
+            screen.color = base_color;
+            screen.set_overlay(None);
+            pranks.render(&mut screen);
+            if bell_until.is_some() {
+                screen.color = screen.color.rotate_left(4);
+            }
+            // Searches what's shown
+            if let Some(ref mut s) = search {
+                s.update_changed(&screen.get_buffer());
+            }
+            if !focus_lost {
+                visual_cmd.update(renderer.canvas.window().size(), &screen);
+            }
+            visual_cmd.render(&mut renderer, &screen, search.as_ref());
+            if mirror.is_none() && panel.is_open() {
+                panel.render(&mut renderer, &pranks);
+            }
+            renderer.present();
+            if let Some((ref mut mirror_renderer, ref mut mirror_cmd)) = mirror {
+                mirror_cmd.update(mirror_renderer.canvas.window().size(), &screen);
+                mirror_cmd.render(mirror_renderer, &screen, None);
+                panel.render(mirror_renderer, &pranks);
+                mirror_renderer.present();
+            }
+            last_frame = Some(Instant::now());
+            needs_redraw = false;
+        }
+    }
+    cmd.stop_recording();
+    if let Some(path) = profile {
+        if let Err(e) = Profile::capture(&pranks, &cmd, base_color).save(&path) {
+            eprintln!("{}: Could not save profile: {}", line!(), e);
         }
-        visual_cmd.render(&mut canvas, &screen);
-        canvas.present();
-        ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 30));
     }
 }
@@ -0,0 +1,270 @@
+use std::path::PathBuf;
+
+use crate::backend::BackendKind;
+use crate::chrome::WindowsVersion;
+use crate::input_filter::InputFilter;
+use crate::panel::ControlPanel;
+use crate::prank::KeyChord;
+use crate::remote::ControlAddress;
+use crate::serial::{FlowControl, LineEnding, Parity, SerialConfig};
+use crate::scrollback::{ScrollbackLimit, DEFAULT_SCROLLBACK_LINES};
+use crate::transcript::{TranscriptConfig, TranscriptFormat};
+
+// Command line options of wcmd itself, they have to come before the command
+// so that anything meant for the child (like `/C dir`) is passed through untouched
+pub struct Options {
+    pub scrollback: ScrollbackLimit,
+    pub transcript: TranscriptConfig,
+    // Start logging right away instead of waiting for the hotkey
+    pub log: bool,
+    pub record: Option<PathBuf>,
+    // Replay an asciicast recording instead of running a command
+    pub play: Option<PathBuf>,
+    // Run the command without a window and save its output as a png
+    pub screenshot: Option<PathBuf>,
+    // In character cells
+    pub screenshot_size: (u32, u32),
+    // What the command runs on
+    pub backend: BackendKind,
+    // Rules for the substitute mode, the built in ones are used without it
+    pub substitutions: Option<PathBuf>,
+    // Scripted output for some commands, see `responses`
+    pub responses: Option<PathBuf>,
+    // Prank modes moved to other keys, by name
+    pub bindings: Vec<(String, KeyChord)>,
+    // Always on input filters, in the order keys go through them
+    pub filters: Vec<InputFilter>,
+    // Opens the operator panel
+    pub panel_key: KeyChord,
+    // Show the session and the panel in a second window too
+    pub mirror: bool,
+    // Socket other programs can drive the session through
+    pub control: Option<ControlAddress>,
+    // Look like this cmd.exe, with its banner, otherwise it's up to the child
+    pub windows: Option<WindowsVersion>,
+    // Titles start with "Administrator: "
+    pub administrator: bool,
+    // Where prank modes and colors are restored from and saved to, see `profile`
+    pub profile: Option<String>,
+    pub command: Vec<String>,
+}
+
+fn parse_size(s: &str) -> Option<(u32, u32)> {
+    let mut split = s.split('x');
+    let size = (split.next()?.parse().ok()?, split.next()?.parse().ok()?);
+    if size.0 == 0 || size.1 == 0 || split.next().is_some() {
+        return None;
+    }
+    Some(size)
+}
+
+impl Options {
+    pub fn from_args<I: Iterator<Item = String>>(mut args: I) -> Result<Self, String> {
+        let mut options = Options {
+            scrollback: ScrollbackLimit::default(),
+            transcript: TranscriptConfig::default(),
+            log: false,
+            record: None,
+            play: None,
+            screenshot: None,
+            screenshot_size: (80, 25),
+            backend: BackendKind::Process,
+            substitutions: None,
+            responses: None,
+            bindings: vec![],
+            filters: vec![],
+            panel_key: ControlPanel::default_key(),
+            mirror: false,
+            control: None,
+            windows: None,
+            administrator: false,
+            profile: Some("default".to_string()),
+            command: vec![],
+        };
+        let mut spill = None;
+        // Only used with --serial, which can come before or after them
+        let mut serial = SerialConfig::new(PathBuf::new());
+
+        // Skip the executable name
+        args.next();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--" => break,
+                "--scrollback" => {
+                    let value = args.next().ok_or("--scrollback expects a number of lines or \"unlimited\"")?;
+                    options.scrollback = if value == "unlimited" {
+                        ScrollbackLimit::Unlimited
+                    }
+                    else {
+                        ScrollbackLimit::Lines(value.parse().map_err(|_| format!("--scrollback: invalid number of lines: {}", value))?)
+                    };
+                }
+                "--scrollback-spill" => {
+                    spill = Some(PathBuf::from(args.next().ok_or("--scrollback-spill expects a file path")?));
+                }
+                "--log" => {
+                    options.transcript.path = Some(PathBuf::from(args.next().ok_or("--log expects a file path")?));
+                    options.log = true;
+                }
+                "--log-raw" => {
+                    options.transcript.format = TranscriptFormat::Raw;
+                }
+                "--log-max-size" => {
+                    let value = args.next().ok_or("--log-max-size expects a size in bytes")?;
+                    options.transcript.max_size = value.parse().map_err(|_| format!("--log-max-size: invalid size: {}", value))?;
+                }
+                "--record" => {
+                    options.record = Some(PathBuf::from(args.next().ok_or("--record expects a file path")?));
+                }
+                "--play" => {
+                    options.play = Some(PathBuf::from(args.next().ok_or("--play expects a file path")?));
+                }
+                "--screenshot" => {
+                    options.screenshot = Some(PathBuf::from(args.next().ok_or("--screenshot expects a file path")?));
+                }
+                "--screenshot-size" => {
+                    let value = args.next().ok_or("--screenshot-size expects COLSxROWS")?;
+                    options.screenshot_size = parse_size(&value).ok_or(format!("--screenshot-size: invalid size: {}", value))?;
+                }
+                "--substitutions" => {
+                    options.substitutions = Some(PathBuf::from(args.next().ok_or("--substitutions expects a file path")?));
+                }
+                "--responses" => {
+                    options.responses = Some(PathBuf::from(args.next().ok_or("--responses expects a file path")?));
+                }
+                "--bind" => {
+                    let value = args.next().ok_or("--bind expects MODE=KEY")?;
+                    let (mode, key) = value.split_once('=').ok_or(format!("--bind: expected MODE=KEY: {}", value))?;
+                    options.bindings.push((mode.to_string(), KeyChord::parse(key).map_err(|e| format!("--bind: {}", e))?));
+                }
+                "--filter" => {
+                    let value = args.next().ok_or("--filter expects a filter like \"lag min=50 max=400\"")?;
+                    options.filters.push(InputFilter::parse(&value).map_err(|e| format!("--filter: {}", e))?);
+                }
+                "--panel-key" => {
+                    let value = args.next().ok_or("--panel-key expects a key like Ctrl+Alt+F12")?;
+                    options.panel_key = KeyChord::parse(&value).map_err(|e| format!("--panel-key: {}", e))?;
+                }
+                "--mirror" => {
+                    options.mirror = true;
+                }
+                "--control" => {
+                    let value = args.next().ok_or("--control expects a socket path or tcp:PORT")?;
+                    options.control = Some(ControlAddress::parse(&value)?);
+                }
+                "--windows" => {
+                    let value = args.next().ok_or("--windows expects 7, 8.1, 10 or 11")?;
+                    options.windows = Some(WindowsVersion::parse(&value).map_err(|e| format!("--windows: {}", e))?);
+                }
+                "--admin" => {
+                    options.administrator = true;
+                }
+                "--profile" => {
+                    options.profile = Some(args.next().ok_or("--profile expects a name or a file path")?);
+                }
+                "--no-profile" => {
+                    options.profile = None;
+                }
+                "--builtin" => {
+                    options.backend = BackendKind::Builtin { drive: None, version: WindowsVersion::Win10 };
+                }
+                "--drive" => {
+                    let drive = PathBuf::from(args.next().ok_or("--drive expects a directory")?);
+                    options.backend = BackendKind::Builtin { drive: Some(drive), version: WindowsVersion::Win10 };
+                }
+                "--pty" => {
+                    options.backend = BackendKind::Pty;
+                }
+                "--telnet" | "--tcp" => {
+                    let address = args.next().ok_or(format!("{} expects host:port", arg))?;
+                    options.backend = BackendKind::Tcp { address, telnet: arg == "--telnet" };
+                }
+                "--serial" => {
+                    serial.path = PathBuf::from(args.next().ok_or("--serial expects a device path")?);
+                    options.backend = BackendKind::Serial(serial.clone());
+                }
+                "--baud" => {
+                    let value = args.next().ok_or("--baud expects a baud rate")?;
+                    serial.baud = value.parse().map_err(|_| format!("--baud: invalid baud rate: {}", value))?;
+                }
+                "--data-bits" => {
+                    let value = args.next().ok_or("--data-bits expects 5 to 8")?;
+                    serial.data_bits = match value.parse() {
+                        Ok(bits @ 5..=8) => bits,
+                        _ => return Err(format!("--data-bits: expected 5 to 8, got {}", value)),
+                    };
+                }
+                "--parity" => {
+                    serial.parity = match args.next().as_deref() {
+                        Some("none") => Parity::None,
+                        Some("even") => Parity::Even,
+                        Some("odd") => Parity::Odd,
+                        _ => return Err("--parity expects none, even or odd".to_string()),
+                    };
+                }
+                "--stop-bits" => {
+                    serial.stop_bits = match args.next().as_deref() {
+                        Some("1") => 1,
+                        Some("2") => 2,
+                        _ => return Err("--stop-bits expects 1 or 2".to_string()),
+                    };
+                }
+                "--flow" => {
+                    serial.flow = match args.next().as_deref() {
+                        Some("none") => FlowControl::None,
+                        Some("hardware") | Some("rtscts") => FlowControl::Hardware,
+                        Some("software") | Some("xonxoff") => FlowControl::Software,
+                        _ => return Err("--flow expects none, hardware or software".to_string()),
+                    };
+                }
+                "--serial-echo" => {
+                    serial.local_echo = match args.next().as_deref() {
+                        Some("local") => true,
+                        Some("remote") => false,
+                        _ => return Err("--serial-echo expects local or remote".to_string()),
+                    };
+                }
+                "--serial-eol" => {
+                    serial.eol = match args.next().as_deref() {
+                        Some("cr") => LineEnding::Cr,
+                        Some("lf") => LineEnding::Lf,
+                        Some("crlf") => LineEnding::CrLf,
+                        _ => return Err("--serial-eol expects cr, lf or crlf".to_string()),
+                    };
+                }
+                _ => {
+                    options.command.push(arg);
+                    break;
+                }
+            }
+        }
+        options.command.extend(args);
+
+        if let BackendKind::Serial(ref mut config) = options.backend {
+            *config = serial;
+        }
+
+        // There's no cmd.exe to fall back on, only sh
+        if cfg!(not(target_os="windows")) && options.backend == BackendKind::Process && options.command.is_empty() {
+            options.backend = BackendKind::Builtin { drive: None, version: WindowsVersion::Win10 };
+        }
+        if let BackendKind::Builtin { ref mut version, .. } = options.backend {
+            if !options.command.is_empty() {
+                return Err("--builtin and --drive run their own commands, there can't be a command after them".to_string());
+            }
+            *version = options.windows.unwrap_or(WindowsVersion::Win10);
+        }
+
+        if let Some(path) = spill {
+            // Spilling to disk keeps the in memory part at the configured size
+            // (or the default one when the scrollback is unlimited)
+            let lines = match options.scrollback {
+                ScrollbackLimit::Lines(n) => n,
+                _ => DEFAULT_SCROLLBACK_LINES,
+            };
+            options.scrollback = ScrollbackLimit::Spill(lines, path);
+        }
+
+        Ok(options)
+    }
+}
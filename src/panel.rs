@@ -0,0 +1,177 @@
+use crate::font::Font;
+use crate::prank::{KeyChord, PrankContext, PrankModes};
+use crate::render::{Color, Rect, Renderer, MOD13_PAL};
+
+// Keys the panel understands, the frontend maps its own key events to these
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PanelKey {
+    Up,
+    Down,
+    // Toggles the selected mode or starts and finishes editing a parameter
+    Enter,
+    Backspace,
+    Escape,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Row {
+    Mode(String),
+    Param(String, String),
+}
+
+// Overlay for whoever runs the session: every prank mode, whether it's on, its key and
+// parameters. It has its own chord so the person at the keyboard doesn't stumble on it
+pub struct ControlPanel {
+    font: Font,
+    key: KeyChord,
+    open: bool,
+    selected: usize,
+    // Parameter value being typed
+    editing: Option<String>,
+    // Last error from setting a parameter, shown until the next key
+    error: Option<String>,
+}
+
+impl ControlPanel {
+    pub fn new(font: Font, key: KeyChord) -> Self {
+        Self { font, key, open: false, selected: 0, editing: None, error: None }
+    }
+
+    pub fn default_key() -> KeyChord {
+        KeyChord::parse("Ctrl+Alt+Shift+F12").expect("valid key")
+    }
+
+    pub fn get_key(&self) -> &KeyChord {
+        &self.key
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn is_editing(&self) -> bool {
+        self.editing.is_some()
+    }
+
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+        self.editing = None;
+        self.error = None;
+    }
+
+    fn rows(modes: &PrankModes) -> Vec<Row> {
+        let mut rows = vec![];
+        for (name, _, _, _) in modes.list() {
+            rows.push(Row::Mode(name.to_string()));
+            for (param, _) in modes.get_params(name).unwrap_or_default() {
+                rows.push(Row::Param(name.to_string(), param));
+            }
+        }
+        rows
+    }
+
+    pub fn key(&mut self, key: PanelKey, modes: &mut PrankModes, ctx: &mut PrankContext) {
+        let rows = Self::rows(modes);
+        self.selected = self.selected.min(rows.len().saturating_sub(1));
+        self.error = None;
+
+        if let Some(ref mut value) = self.editing {
+            match key {
+                PanelKey::Backspace => {
+                    value.pop();
+                }
+                PanelKey::Escape => self.editing = None,
+                PanelKey::Enter => {
+                    if let Some(Row::Param(mode, param)) = rows.get(self.selected) {
+                        self.error = modes.set_param(mode, param, value).err();
+                    }
+                    self.editing = None;
+                }
+                _ => (),
+            }
+            return;
+        }
+
+        match key {
+            PanelKey::Up => self.selected = self.selected.saturating_sub(1),
+            PanelKey::Down => self.selected = (self.selected + 1).min(rows.len().saturating_sub(1)),
+            PanelKey::Enter => match rows.get(self.selected) {
+                Some(Row::Mode(mode)) => {
+                    let active = !modes.is_active(mode);
+                    self.error = modes.set_active(mode, active, ctx).err();
+                }
+                Some(Row::Param(mode, param)) => {
+                    let params = modes.get_params(mode).unwrap_or_default();
+                    let value = params.into_iter().find(|p| &p.0 == param).map(|p| p.1).unwrap_or_default();
+                    self.editing = Some(value);
+                }
+                None => (),
+            },
+            PanelKey::Escape => self.open = false,
+            PanelKey::Backspace => (),
+        }
+    }
+
+    pub fn text_input(&mut self, text: &str) {
+        if let Some(ref mut value) = self.editing {
+            value.push_str(text);
+        }
+    }
+
+    // What the panel shows, the selected row is marked with `>`
+    pub fn get_lines(&self, modes: &PrankModes) -> Vec<String> {
+        let mut lines = vec![format!("Operator panel ({} closes)", self.key), String::new()];
+        let rows = Self::rows(modes);
+        for (i, row) in rows.iter().enumerate() {
+            let marker = if i == self.selected { '>' } else { ' ' };
+            let line = match row {
+                Row::Mode(name) => {
+                    let (_, description, active, key) = modes.list().find(|m| m.0 == name).expect("listed mode");
+                    let key = key.map(|k| k.to_string()).unwrap_or_else(|| "-".to_string());
+                    format!("{}[{}] {:<14} {:<14} {}", marker, if active { 'x' } else { ' ' }, name, key, description)
+                }
+                Row::Param(mode, param) => {
+                    let value = match self.editing {
+                        Some(ref value) if i == self.selected => format!("{}_", value),
+                        _ => modes.get_params(mode).unwrap_or_default().into_iter()
+                            .find(|p| &p.0 == param).map(|p| p.1).unwrap_or_default(),
+                    };
+                    format!("{}      {} = {}", marker, param, value)
+                }
+            };
+            lines.push(line);
+        }
+        lines.push(String::new());
+        lines.push(match self.error {
+            Some(ref e) => format!("Error: {}", e),
+            None => "Up/Down select, Enter toggles or edits, Esc closes".to_string(),
+        });
+        lines
+    }
+
+    // Draws it whether it's open or not, a mirror window shows it all the time
+    pub fn render<R: Renderer>(&self, renderer: &mut R, modes: &PrankModes) {
+        let lines = self.get_lines(modes);
+        let (gw, gh) = (self.font.glyph_size.0 as u32, self.font.glyph_size.1 as u32);
+        let (width, _) = renderer.get_size();
+        let columns = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0) as u32;
+        let box_width = ((columns + 2) * gw).min(width.saturating_sub(gw * 2));
+        let visible = (box_width / gw).saturating_sub(2) as usize;
+
+        let (x, y) = (gw as i32, gh as i32);
+        renderer.fill_rect(Rect::new(x - 1, y - 1, box_width + 2, (lines.len() as u32 + 2) * gh + 2), MOD13_PAL[15]);
+        renderer.fill_rect(Rect::new(x, y, box_width, (lines.len() as u32 + 2) * gh), MOD13_PAL[1]);
+        for (i, line) in lines.iter().enumerate() {
+            let line: String = line.chars().take(visible).collect();
+            let row_y = y + (i as u32 + 1) as i32 * gh as i32;
+            let color = if line.starts_with('>') {
+                renderer.fill_rect(Rect::new(x, row_y, box_width, gh), MOD13_PAL[3]);
+                Color::WHITE
+            }
+            else {
+                MOD13_PAL[7]
+            };
+            renderer.draw_cell_run(x + gw as i32, row_y, &line, color);
+        }
+    }
+}
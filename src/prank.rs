@@ -0,0 +1,629 @@
+use std::{fmt, time::Duration};
+
+use crate::cmd::Cmd;
+use crate::input_filter::{CharClass, InputFilter};
+use crate::progress::{parse_seconds, Progress, ProgressStyle};
+use crate::render::VisualCommandLine;
+use crate::screen::Screen;
+use crate::substitute::{Rule, Rules, Substituter};
+
+// What a mode can reach, the frontend builds one for every hook it calls
+pub struct PrankContext<'a> {
+    pub cmd: &'a mut Cmd,
+    pub visual_cmd: &'a mut VisualCommandLine,
+    // Typing is ignored until the window is clicked again
+    pub focus_lost: &'a mut bool,
+    // Set by modes, the frontend brings its window to the front when it sees it
+    pub raise_window: bool,
+}
+
+// A joke that can be switched on and off. Hooks are only called while it's active
+pub trait PrankMode {
+    // Short and without spaces, used for --bind and such
+    fn get_name(&self) -> &str;
+    fn get_description(&self) -> &str;
+
+    fn on_activate(&mut self, _ctx: &mut PrankContext) {}
+    fn on_deactivate(&mut self, _ctx: &mut PrankContext) {}
+
+    // Text typed in one go, before it reaches the command line. Modes can change it
+    fn on_input(&mut self, _input: &mut String, _ctx: &mut PrankContext) {}
+    // A line was sent to the child
+    fn on_line(&mut self, _line: &str, _ctx: &mut PrankContext) {}
+    // The child printed something
+    fn on_output(&mut self, _ctx: &mut PrankContext) {}
+    fn on_focus_lost(&mut self, _ctx: &mut PrankContext) {}
+
+    // Called every time around the main loop, `dt` in seconds
+    fn update(&mut self, _dt: f64, _ctx: &mut PrankContext) {}
+    // Keeps the main loop drawing every frame instead of waiting for events
+    fn is_animating(&self) -> bool {
+        false
+    }
+    // Modes that do one thing and stop say so here and get switched off
+    fn is_done(&self) -> bool {
+        false
+    }
+    // Right before the screen is drawn
+    fn render(&mut self, _screen: &mut Screen) {}
+
+    // (name, value) of what can be tuned while it runs, values are set back as text
+    fn get_params(&self) -> Vec<(String, String)> {
+        vec![]
+    }
+    fn set_param(&mut self, name: &str, _value: &str) -> Result<(), String> {
+        Err(format!("no such parameter: {}", name))
+    }
+
+    // What a profile keeps of it, the parameters unless there's more to it
+    fn get_state(&self) -> Vec<(String, String)> {
+        self.get_params()
+    }
+    fn set_state(&mut self, state: &[(String, String)]) -> Result<(), String> {
+        for (name, value) in state {
+            self.set_param(name, value)?;
+        }
+        Ok(())
+    }
+    // Does its thing once when switched on, a profile doesn't switch it back on
+    fn is_one_shot(&self) -> bool {
+        false
+    }
+}
+
+// A key with modifiers, written like `Ctrl+Shift+F5`. Key names are SDL's
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyChord {
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    // Lowercase
+    pub key: String,
+}
+
+impl KeyChord {
+    pub fn new(key: &str) -> Self {
+        Self { ctrl: false, alt: false, shift: false, key: key.to_lowercase() }
+    }
+
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut parts: Vec<&str> = s.split('+').map(str::trim).collect();
+        let key = parts.pop().filter(|k| !k.is_empty()).ok_or(format!("invalid key: {}", s))?;
+        let mut chord = Self::new(key);
+        for modifier in parts {
+            match modifier.to_lowercase().as_str() {
+                "ctrl" | "control" => chord.ctrl = true,
+                "alt" => chord.alt = true,
+                "shift" => chord.shift = true,
+                _ => return Err(format!("unknown modifier {} in {}", modifier, s)),
+            }
+        }
+        Ok(chord)
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.ctrl {
+            write!(f, "Ctrl+")?;
+        }
+        if self.alt {
+            write!(f, "Alt+")?;
+        }
+        if self.shift {
+            write!(f, "Shift+")?;
+        }
+        let mut chars = self.key.chars();
+        if self.key.len() <= 3 {
+            write!(f, "{}", self.key.to_uppercase())
+        }
+        else {
+            write!(f, "{}{}", chars.next().map(|c| c.to_uppercase().to_string()).unwrap_or_default(), chars.as_str())
+        }
+    }
+}
+
+struct Entry {
+    mode: Box<dyn PrankMode>,
+    active: bool,
+    key: Option<KeyChord>,
+}
+
+// Every mode the window knows about, in the order their hooks run
+#[derive(Default)]
+pub struct PrankModes {
+    entries: Vec<Entry>,
+    // Keys the window handles itself, with what they do
+    reserved: Vec<(KeyChord, String)>,
+}
+
+impl PrankModes {
+    pub fn new() -> Self {
+        Self { entries: vec![], reserved: vec![] }
+    }
+
+    // The jokes that used to be the F1-F6 flags, and the ones that came after. Their keys need
+    // Ctrl+Shift so whoever is typing doesn't hit them, the panel shows them all anyway
+    pub fn with_builtins(rules: Rules) -> Self {
+        let key = |name| Some(KeyChord { ctrl: true, shift: true, ..KeyChord::new(name) });
+        let mut modes = Self::new();
+        modes.register(Box::new(UnfocusAfterKey), key("F1"));
+        modes.register(Box::new(ColorRoll { roll: 0 }), key("F2"));
+        modes.register(Box::new(FilterMode::new("digit-limit", "Refuse digits past the limit on a line", InputFilter::Limit { class: CharClass::Digits, max: 3 })), key("F3"));
+        modes.register(Box::new(AlwaysOnTop), key("F4"));
+        modes.register(Box::new(Substitute { substituter: Substituter::new(rules) }), key("F5"));
+        modes.register(Box::new(ScrollUp), key("F6"));
+        modes.register(Box::new(ProgressMode::new(Progress::new(ProgressStyle::Bar, Duration::from_secs(10)))), key("F8"));
+        let filters = [
+            ("drop-keys", "Lose some of the keys typed", "drop"),
+            ("double-keys", "Type some keys twice", "double"),
+            ("lag", "Show typed keys late", "lag"),
+            ("swap-digits", "Mix up the order of digits", "swap-digits"),
+            ("rewrite", "Turn a typed word into another", "rewrite"),
+        ];
+        for (name, description, kind) in filters {
+            modes.register(Box::new(FilterMode::new(name, description, InputFilter::new(kind).expect("known filter"))), None);
+        }
+        modes
+    }
+
+    pub fn register(&mut self, mode: Box<dyn PrankMode>, key: Option<KeyChord>) {
+        self.entries.push(Entry { mode, active: false, key });
+    }
+
+    fn find(&self, name: &str) -> Result<usize, String> {
+        self.entries.iter().position(|e| e.mode.get_name() == name).ok_or(format!("no such mode: {}", name))
+    }
+
+    // Keeps modes off a key the window already uses, binding them to it is an error
+    pub fn reserve(&mut self, key: KeyChord, what: &str) {
+        self.reserved.push((key, what.to_string()));
+    }
+
+    // Moves the mode to `key`, taking it from whatever mode had it before
+    pub fn bind(&mut self, name: &str, key: KeyChord) -> Result<(), String> {
+        let index = self.find(name)?;
+        if let Some((_, what)) = self.reserved.iter().find(|r| r.0 == key) {
+            return Err(format!("{} is already used for {}", key, what));
+        }
+        for entry in self.entries.iter_mut().filter(|e| e.key.as_ref() == Some(&key)) {
+            entry.key = None;
+        }
+        self.entries[index].key = Some(key);
+        Ok(())
+    }
+
+    pub fn is_bound(&self, key: &KeyChord) -> bool {
+        self.entries.iter().any(|e| e.key.as_ref() == Some(key))
+    }
+
+    // (name, description, active, key) of every mode
+    pub fn list(&self) -> impl Iterator<Item = (&str, &str, bool, Option<&KeyChord>)> {
+        self.entries.iter().map(|e| (e.mode.get_name(), e.mode.get_description(), e.active, e.key.as_ref()))
+    }
+
+    pub fn get_params(&self, name: &str) -> Result<Vec<(String, String)>, String> {
+        Ok(self.entries[self.find(name)?].mode.get_params())
+    }
+
+    pub fn set_param(&mut self, name: &str, param: &str, value: &str) -> Result<(), String> {
+        let index = self.find(name)?;
+        self.entries[index].mode.set_param(param, value.trim())
+    }
+
+    pub fn get_state(&self, name: &str) -> Result<Vec<(String, String)>, String> {
+        Ok(self.entries[self.find(name)?].mode.get_state())
+    }
+
+    pub fn set_state(&mut self, name: &str, state: &[(String, String)]) -> Result<(), String> {
+        let index = self.find(name)?;
+        self.entries[index].mode.set_state(state)
+    }
+
+    pub fn is_one_shot(&self, name: &str) -> bool {
+        self.entries.iter().any(|e| e.mode.is_one_shot() && e.mode.get_name() == name)
+    }
+
+    pub fn is_active(&self, name: &str) -> bool {
+        self.entries.iter().any(|e| e.active && e.mode.get_name() == name)
+    }
+
+    pub fn set_active(&mut self, name: &str, active: bool, ctx: &mut PrankContext) -> Result<(), String> {
+        let index = self.find(name)?;
+        self.set_active_at(index, active, ctx);
+        Ok(())
+    }
+
+    fn set_active_at(&mut self, index: usize, active: bool, ctx: &mut PrankContext) {
+        let entry = &mut self.entries[index];
+        if entry.active != active {
+            entry.active = active;
+            if active {
+                entry.mode.on_activate(ctx);
+            }
+            else {
+                entry.mode.on_deactivate(ctx);
+            }
+        }
+    }
+
+    // Toggles the mode bound to `key`, returns if there was one
+    pub fn on_key(&mut self, key: &KeyChord, ctx: &mut PrankContext) -> bool {
+        match self.entries.iter().position(|e| e.key.as_ref() == Some(key)) {
+            Some(index) => {
+                let active = !self.entries[index].active;
+                self.set_active_at(index, active, ctx);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn active(&mut self) -> impl Iterator<Item = &mut Box<dyn PrankMode>> {
+        self.entries.iter_mut().filter(|e| e.active).map(|e| &mut e.mode)
+    }
+
+    pub fn on_input(&mut self, input: &mut String, ctx: &mut PrankContext) {
+        for mode in self.active() {
+            mode.on_input(input, ctx);
+        }
+    }
+
+    // Typing, the way the window does it: the modes see the text first and Cmd gets what's
+    // left of it. Nothing is typed while a mode has the focus away
+    pub fn type_text(&mut self, text: &str, ctx: &mut PrankContext) {
+        if *ctx.focus_lost {
+            return;
+        }
+        ctx.visual_cmd.lock_scroll();
+        let mut input = text.to_string();
+        self.on_input(&mut input, ctx);
+        for c in input.chars() {
+            ctx.cmd.put_stdin(c);
+        }
+    }
+
+    // Enter, the typed line goes to the child and the modes hear about it
+    pub fn enter(&mut self, ctx: &mut PrankContext) -> Option<String> {
+        if *ctx.focus_lost {
+            return None;
+        }
+        let line = ctx.cmd.enter();
+        self.on_line(&line, ctx);
+        Some(line)
+    }
+
+    pub fn on_line(&mut self, line: &str, ctx: &mut PrankContext) {
+        for mode in self.active() {
+            mode.on_line(line, ctx);
+        }
+    }
+
+    pub fn on_output(&mut self, ctx: &mut PrankContext) {
+        for mode in self.active() {
+            mode.on_output(ctx);
+        }
+    }
+
+    pub fn on_focus_lost(&mut self, ctx: &mut PrankContext) {
+        for mode in self.active() {
+            mode.on_focus_lost(ctx);
+        }
+    }
+
+    pub fn update(&mut self, dt: f64, ctx: &mut PrankContext) {
+        for mode in self.active() {
+            mode.update(dt, ctx);
+        }
+        for index in 0..self.entries.len() {
+            if self.entries[index].active && self.entries[index].mode.is_done() {
+                self.set_active_at(index, false, ctx);
+            }
+        }
+    }
+
+    pub fn is_animating(&self) -> bool {
+        self.entries.iter().any(|e| e.active && e.mode.is_animating())
+    }
+
+    pub fn render(&mut self, screen: &mut Screen) {
+        for mode in self.active() {
+            mode.render(screen);
+        }
+    }
+}
+
+// Typing once makes the window act like it lost focus
+pub struct UnfocusAfterKey;
+
+impl PrankMode for UnfocusAfterKey {
+    fn get_name(&self) -> &str {
+        "unfocus"
+    }
+
+    fn get_description(&self) -> &str {
+        "Ignore typing after the first key until the window is clicked"
+    }
+
+    fn on_input(&mut self, _input: &mut String, ctx: &mut PrankContext) {
+        *ctx.focus_lost = true;
+    }
+}
+
+// Every key press moves to the next background and text color
+pub struct ColorRoll {
+    roll: u8,
+}
+
+impl PrankMode for ColorRoll {
+    fn get_name(&self) -> &str {
+        "color-roll"
+    }
+
+    fn get_description(&self) -> &str {
+        "Change colors on every key press"
+    }
+
+    fn on_input(&mut self, _input: &mut String, _ctx: &mut PrankContext) {
+        self.roll = self.roll.wrapping_add(1);
+    }
+
+    fn render(&mut self, screen: &mut Screen) {
+        screen.color = ((self.roll % 8) << 4) | ((self.roll + 7) % 8);
+    }
+
+    fn get_params(&self) -> Vec<(String, String)> {
+        vec![("roll".to_string(), self.roll.to_string())]
+    }
+
+    fn set_param(&mut self, name: &str, value: &str) -> Result<(), String> {
+        match name {
+            "roll" => self.roll = value.parse().map_err(|_| format!("invalid roll: {}", value))?,
+            _ => return Err(format!("no such parameter: {}", name)),
+        }
+        Ok(())
+    }
+}
+
+// Puts an input filter in front of the command line while it's on
+pub struct FilterMode {
+    name: String,
+    description: String,
+    pub filter: InputFilter,
+    // Parameters are set without the Cmd at hand, they're passed on in the next update
+    changed: bool,
+}
+
+impl FilterMode {
+    pub fn new(name: &str, description: &str, filter: InputFilter) -> Self {
+        Self { name: name.to_string(), description: description.to_string(), filter, changed: false }
+    }
+}
+
+impl PrankMode for FilterMode {
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn get_description(&self) -> &str {
+        &self.description
+    }
+
+    fn on_activate(&mut self, ctx: &mut PrankContext) {
+        ctx.cmd.input_filters.set(&self.name, self.filter.clone());
+        self.changed = false;
+    }
+
+    fn on_deactivate(&mut self, ctx: &mut PrankContext) {
+        ctx.cmd.input_filters.remove(&self.name);
+    }
+
+    fn update(&mut self, _dt: f64, ctx: &mut PrankContext) {
+        if self.changed {
+            ctx.cmd.input_filters.set(&self.name, self.filter.clone());
+            self.changed = false;
+        }
+    }
+
+    fn get_params(&self) -> Vec<(String, String)> {
+        self.filter.get_params()
+    }
+
+    fn set_param(&mut self, name: &str, value: &str) -> Result<(), String> {
+        self.filter.set_param(name, value)?;
+        self.changed = true;
+        Ok(())
+    }
+}
+
+pub struct AlwaysOnTop;
+
+impl PrankMode for AlwaysOnTop {
+    fn get_name(&self) -> &str {
+        "always-on-top"
+    }
+
+    fn get_description(&self) -> &str {
+        "Jump back in front of other windows"
+    }
+
+    fn on_activate(&mut self, ctx: &mut PrankContext) {
+        ctx.raise_window = true;
+    }
+
+    fn on_focus_lost(&mut self, ctx: &mut PrankContext) {
+        ctx.raise_window = true;
+    }
+}
+
+// Shows what the child prints with words replaced, see `substitute`
+pub struct Substitute {
+    pub substituter: Substituter,
+}
+
+impl PrankMode for Substitute {
+    fn get_name(&self) -> &str {
+        "substitute"
+    }
+
+    fn get_description(&self) -> &str {
+        "Replace words in new output"
+    }
+
+    // Only what's printed from now on is rewritten, and only on screen
+    fn on_activate(&mut self, ctx: &mut PrankContext) {
+        self.substituter.start(&ctx.cmd.get_stdout_buffer().borrow());
+    }
+
+    fn on_deactivate(&mut self, _ctx: &mut PrankContext) {
+        self.substituter.stop();
+    }
+
+    fn on_line(&mut self, line: &str, _ctx: &mut PrankContext) {
+        self.substituter.set_command(line);
+    }
+
+    fn on_output(&mut self, ctx: &mut PrankContext) {
+        self.substituter.update(&ctx.cmd.get_stdout_buffer().borrow());
+    }
+
+    fn render(&mut self, screen: &mut Screen) {
+        screen.set_overlay(self.substituter.get_display());
+    }
+
+    // `default` is the built in set, anything else a rules file
+    fn get_params(&self) -> Vec<(String, String)> {
+        let rules = match self.substituter.rules.get_path() {
+            Some(path) => path.display().to_string(),
+            None => "default".to_string(),
+        };
+        vec![
+            ("rules".to_string(), rules),
+            ("count".to_string(), self.substituter.rules.len().to_string()),
+        ]
+    }
+
+    fn set_param(&mut self, name: &str, value: &str) -> Result<(), String> {
+        match name {
+            "rules" if value == "default" => self.substituter.rules = Rules::default(),
+            "rules" => self.substituter.rules = Rules::load(std::path::Path::new(value))?,
+            // Adds one instead of replacing them, it's not listed as its own parameter
+            "rule" => self.substituter.rules.push(Rule::parse(value)?),
+            _ => return Err(format!("no such parameter: {}", name)),
+        }
+        Ok(())
+    }
+
+    // The rules themselves since some might have been added, and the file to keep watching
+    fn get_state(&self) -> Vec<(String, String)> {
+        let mut state = vec![("text".to_string(), self.substituter.rules.to_text())];
+        if let Some(path) = self.substituter.rules.get_path() {
+            state.push(("path".to_string(), path.display().to_string()));
+        }
+        state
+    }
+
+    fn set_state(&mut self, state: &[(String, String)]) -> Result<(), String> {
+        let mut rules = None;
+        for (name, value) in state {
+            match name.as_str() {
+                "text" => rules = Some(Rules::parse(value)?),
+                "path" => match rules {
+                    Some(ref mut rules) => rules.set_path(std::path::Path::new(value)),
+                    None => return Err("path without text".to_string()),
+                },
+                _ => return Err(format!("no such parameter: {}", name)),
+            }
+        }
+        if let Some(rules) = rules {
+            self.substituter.rules = rules;
+        }
+        Ok(())
+    }
+}
+
+pub struct ScrollUp;
+
+impl PrankMode for ScrollUp {
+    fn get_name(&self) -> &str {
+        "scroll-up"
+    }
+
+    fn get_description(&self) -> &str {
+        "Keep scrolling up"
+    }
+
+    fn update(&mut self, _dt: f64, ctx: &mut PrankContext) {
+        ctx.visual_cmd.scroll_by(-1);
+    }
+
+    fn is_animating(&self) -> bool {
+        true
+    }
+}
+
+// Fakes a long operation, pressing the key again before it's over aborts it like Ctrl+C
+pub struct ProgressMode {
+    pub progress: Progress,
+    running: bool,
+}
+
+impl ProgressMode {
+    pub fn new(progress: Progress) -> Self {
+        Self { progress, running: false }
+    }
+}
+
+impl PrankMode for ProgressMode {
+    fn get_name(&self) -> &str {
+        "progress"
+    }
+
+    fn get_description(&self) -> &str {
+        "Show a progress animation"
+    }
+
+    fn on_activate(&mut self, ctx: &mut PrankContext) {
+        ctx.cmd.start_progress(&self.progress);
+        self.running = true;
+    }
+
+    fn on_deactivate(&mut self, ctx: &mut PrankContext) {
+        if self.running {
+            ctx.cmd.abort_response();
+            self.running = false;
+        }
+    }
+
+    fn update(&mut self, _dt: f64, ctx: &mut PrankContext) {
+        // Ctrl+C or a response typed in the meantime ends it as well
+        self.running = self.running && ctx.cmd.is_playing_response();
+    }
+
+    fn is_done(&self) -> bool {
+        !self.running
+    }
+
+    fn is_one_shot(&self) -> bool {
+        true
+    }
+
+    fn get_params(&self) -> Vec<(String, String)> {
+        vec![
+            ("style".to_string(), self.progress.style.get_name().to_string()),
+            ("seconds".to_string(), self.progress.duration.as_secs_f64().to_string()),
+            ("label".to_string(), self.progress.label.clone().unwrap_or_default()),
+        ]
+    }
+
+    fn set_param(&mut self, name: &str, value: &str) -> Result<(), String> {
+        match name {
+            "style" => self.progress.style = ProgressStyle::parse(value)?,
+            "seconds" => self.progress.duration = parse_seconds(value)?,
+            // Empty goes back to the style's own
+            "label" => self.progress.label = Some(value.to_string()).filter(|l| !l.is_empty()),
+            _ => return Err(format!("no such parameter: {}", name)),
+        }
+        Ok(())
+    }
+}
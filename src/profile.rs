@@ -0,0 +1,141 @@
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Value};
+
+use crate::cmd::Cmd;
+use crate::input_filter::InputFilter;
+use crate::prank::{PrankContext, PrankModes};
+
+// What a mode was doing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModeState {
+    pub name: String,
+    pub active: bool,
+    pub state: Vec<(String, String)>,
+}
+
+// Everything the operator set up in a session, saved when the window closes and put back
+// when it opens again. Stored as JSON like
+//
+//     {"color": "07",
+//      "modes": [{"name": "digit-limit", "active": true, "state": [["max", "3"], ["class", "digits"]]}],
+//      "filters": [{"name": "lag", "spec": "lag min=50 max=400"}]}
+//
+// where `filters` are the ones that were put in without a mode
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    pub color: u8,
+    pub modes: Vec<ModeState>,
+    pub filters: Vec<(String, InputFilter)>,
+}
+
+// `~/WinCmd/profiles/NAME.json`, or NAME itself when it's already a path
+pub fn get_profile_path(name: &str) -> Option<PathBuf> {
+    if name.contains(['/', '\\']) || name.ends_with(".json") {
+        return Some(PathBuf::from(name));
+    }
+    let mut dir = std::env::home_dir()?;
+    dir.push("WinCmd");
+    dir.push("profiles");
+    dir.push(format!("{}.json", name));
+    Some(dir)
+}
+
+fn get_str<'a>(value: &'a Value, key: &str) -> Result<&'a str, String> {
+    value[key].as_str().ok_or(format!("missing \"{}\"", key))
+}
+
+impl Profile {
+    pub fn capture(pranks: &PrankModes, cmd: &Cmd, color: u8) -> Self {
+        let modes = pranks.list()
+            .map(|(name, _, active, _)| ModeState {
+                name: name.to_string(),
+                active,
+                state: pranks.get_state(name).unwrap_or_default(),
+            })
+            .collect::<Vec<_>>();
+        let filters = cmd.input_filters.list()
+            .filter(|(name, _)| !modes.iter().any(|m| &m.name == name))
+            .map(|(name, filter)| (name.to_string(), filter.clone()))
+            .collect();
+        Self { color, modes, filters }
+    }
+
+    // Drops the saved modes and filters with these names. For what the command line set up,
+    // so the profile doesn't put the last session's back over it
+    pub fn leave_out(&mut self, names: &[String]) {
+        self.modes.retain(|m| !names.contains(&m.name));
+        self.filters.retain(|(name, _)| !names.contains(name));
+    }
+
+    // Puts it all back, modes that are gone or state that doesn't fit anymore are skipped
+    // and reported instead of stopping the rest
+    pub fn apply(&self, pranks: &mut PrankModes, ctx: &mut PrankContext, color: &mut u8) -> Vec<String> {
+        let mut errors = vec![];
+        *color = self.color;
+        for (name, filter) in &self.filters {
+            ctx.cmd.input_filters.set(name, filter.clone());
+        }
+        for mode in &self.modes {
+            if !pranks.list().any(|m| m.0 == mode.name) {
+                errors.push(format!("no such mode: {}", mode.name));
+                continue;
+            }
+            if let Err(e) = pranks.set_state(&mode.name, &mode.state) {
+                errors.push(format!("{}: {}", mode.name, e));
+            }
+            let active = mode.active && !pranks.is_one_shot(&mode.name);
+            pranks.set_active(&mode.name, active, ctx).expect("listed mode");
+        }
+        errors
+    }
+
+    pub fn to_json(&self) -> Value {
+        let modes: Vec<Value> = self.modes.iter()
+            .map(|m| json!({ "name": m.name, "active": m.active, "state": m.state }))
+            .collect();
+        let filters: Vec<Value> = self.filters.iter()
+            .map(|(name, filter)| json!({ "name": name, "spec": filter.to_string() }))
+            .collect();
+        json!({ "color": format!("{:02x}", self.color), "modes": modes, "filters": filters })
+    }
+
+    pub fn from_json(value: &Value) -> Result<Self, String> {
+        let color = get_str(value, "color")?;
+        let color = u8::from_str_radix(color, 16).map_err(|_| format!("invalid color: {}", color))?;
+        let mut modes = vec![];
+        for mode in value["modes"].as_array().into_iter().flatten() {
+            let mut state = vec![];
+            for pair in mode["state"].as_array().into_iter().flatten() {
+                match (pair[0].as_str(), pair[1].as_str()) {
+                    (Some(name), Some(value)) => state.push((name.to_string(), value.to_string())),
+                    _ => return Err(format!("invalid state: {}", pair)),
+                }
+            }
+            modes.push(ModeState {
+                name: get_str(mode, "name")?.to_string(),
+                active: mode["active"].as_bool().unwrap_or(false),
+                state,
+            });
+        }
+        let mut filters = vec![];
+        for filter in value["filters"].as_array().into_iter().flatten() {
+            filters.push((get_str(filter, "name")?.to_string(), InputFilter::parse(get_str(filter, "spec")?)?));
+        }
+        Ok(Self { color, modes, filters })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{:?}: {}", path, e))?;
+        let value: Value = serde_json::from_str(&text).map_err(|e| format!("{:?}: {}", path, e))?;
+        Self::from_json(&value).map_err(|e| format!("{:?}: {}", path, e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(|e| format!("{:?}: {}", dir, e))?;
+        }
+        let text = serde_json::to_string_pretty(&self.to_json()).map_err(|e| e.to_string())?;
+        std::fs::write(path, text + "\n").map_err(|e| format!("{:?}: {}", path, e))
+    }
+}
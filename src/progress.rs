@@ -0,0 +1,116 @@
+use std::time::Duration;
+
+use crate::responses::{Playback, Rng};
+
+// How often a frame is drawn
+const FRAME: Duration = Duration::from_millis(100);
+const BAR_WIDTH: usize = 40;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressStyle {
+    // [=========           ]  45%
+    Bar,
+    // Please wait... /
+    Spinner,
+    // Scanning file 1234 of 5678...
+    Scan,
+    // █████████░░░░░░░░░░░  45%
+    Blocks,
+}
+
+impl ProgressStyle {
+    pub const ALL: [ProgressStyle; 4] = [ProgressStyle::Bar, ProgressStyle::Spinner, ProgressStyle::Scan, ProgressStyle::Blocks];
+
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Self::ALL.iter().copied().find(|style| style.get_name() == s)
+            .ok_or(format!("unknown progress style: {} (bar, spinner, scan or blocks)", s))
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            ProgressStyle::Bar => "bar",
+            ProgressStyle::Spinner => "spinner",
+            ProgressStyle::Scan => "scan",
+            ProgressStyle::Blocks => "blocks",
+        }
+    }
+
+    fn default_label(&self) -> &'static str {
+        match self {
+            ProgressStyle::Spinner => "Please wait...",
+            ProgressStyle::Scan => "Scanning file",
+            _ => "",
+        }
+    }
+}
+
+// A long running operation that isn't. Every frame starts with \r and redraws the line
+// the one before it drew, like console programs do it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Progress {
+    pub style: ProgressStyle,
+    pub duration: Duration,
+    // Shown in front, None for the style's own
+    pub label: Option<String>,
+}
+
+impl Progress {
+    pub fn new(style: ProgressStyle, duration: Duration) -> Self {
+        Self { style, duration, label: None }
+    }
+
+    // `STYLE [SECONDS [LABEL]]` like `scan 10 Checking file`
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut parts = s.trim().splitn(3, ' ');
+        let style = ProgressStyle::parse(parts.next().unwrap_or_default())?;
+        let mut progress = Self::new(style, Duration::from_secs(5));
+        if let Some(seconds) = parts.next() {
+            progress.duration = parse_seconds(seconds)?;
+        }
+        progress.label = parts.next().map(str::to_string);
+        Ok(progress)
+    }
+
+    fn get_label(&self) -> &str {
+        self.label.as_deref().unwrap_or(self.style.default_label())
+    }
+
+    // Every frame and how long after the previous one it's drawn, the last one ends the line.
+    // They're made as they're shown, an hour of them is a lot of strings
+    pub fn frames(&self, rng: &mut Rng) -> impl Iterator<Item = (Duration, String)> {
+        let count = (self.duration.as_millis() / FRAME.as_millis()).max(1) as usize;
+        let total = rng.range(800, 12000) as usize;
+        let label = self.get_label();
+        let prefix = if label.is_empty() { String::new() } else { format!("{} ", label) };
+        let style = self.style;
+
+        (0..=count).map(move |i| {
+            let done = i * 100 / count;
+            let filled = i * BAR_WIDTH / count;
+            let frame = match style {
+                ProgressStyle::Bar => format!("{}[{}{}] {:>3}%", prefix, "=".repeat(filled), " ".repeat(BAR_WIDTH - filled), done),
+                ProgressStyle::Blocks => format!("{}{}{} {:>3}%", prefix, "█".repeat(filled), "░".repeat(BAR_WIDTH - filled), done),
+                ProgressStyle::Spinner if i == count => format!("{}done.", prefix),
+                ProgressStyle::Spinner => format!("{}{}", prefix, ['|', '/', '-', '\\'][i % 4]),
+                ProgressStyle::Scan => format!("{}{} of {}...", prefix, (i * total / count).max(1), total),
+            };
+            let delay = if i == 0 { Duration::ZERO } else { FRAME };
+            let end = if i == count { "\n" } else { "" };
+            (delay, format!("\r{}{}", frame, end))
+        })
+    }
+
+    pub fn playback(&self, rng: &mut Rng) -> Playback {
+        Playback::from_steps(self.frames(rng))
+    }
+}
+
+// Nobody watches a progress bar for longer, and it keeps the number of frames sane
+const MAX_SECONDS: f64 = 3600.0;
+
+pub fn parse_seconds(s: &str) -> Result<Duration, String> {
+    s.trim().parse::<f64>().ok()
+        .filter(|s| (0.0..=MAX_SECONDS).contains(s))
+        .and_then(|s| Duration::try_from_secs_f64(s).ok())
+        .ok_or(format!("invalid number of seconds: {} (0 to {})", s, MAX_SECONDS))
+}
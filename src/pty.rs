@@ -0,0 +1,139 @@
+use std::{
+    fs::File,
+    io::{Read, Write},
+    os::unix::{io::{AsRawFd, FromRawFd, RawFd}, process::CommandExt},
+    process::{Child, Command, Stdio},
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
+};
+
+use crate::backend::{Backend, Signal};
+use crate::subprocess::{child_non_blocking_stream, send_signal, WakeupSlot};
+use crate::wakeup::Wakeup;
+
+// Reading the master side fails with EIO once every process has closed the other side,
+// that's just the end of the output
+struct Master(File);
+
+impl Read for Master {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self.0.read(buf) {
+            Err(e) if e.raw_os_error() == Some(libc::EIO) => Ok(0),
+            result => result,
+        }
+    }
+}
+
+// Runs the child on a pseudo terminal so it sees a real tty (line editing, colors, resizes)
+pub struct Pty {
+    child: Child,
+    master: File,
+    output: Arc<Mutex<Vec<u8>>>,
+    reader: JoinHandle<()>,
+    wakeup: WakeupSlot,
+}
+
+fn set_size(fd: RawFd, cols: u32, rows: u32) -> std::io::Result<()> {
+    let size = libc::winsize { ws_col: cols as u16, ws_row: rows as u16, ws_xpixel: 0, ws_ypixel: 0 };
+    if unsafe { libc::ioctl(fd, libc::TIOCSWINSZ, &size) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+// Backspace sends ^H like it does everywhere else, the tty is told that's what erases
+fn set_erase(fd: RawFd) -> std::io::Result<()> {
+    // SAFETY: tcgetattr and tcsetattr only touch the termios they're given
+    unsafe {
+        let mut termios: libc::termios = std::mem::zeroed();
+        if libc::tcgetattr(fd, &mut termios) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        termios.c_cc[libc::VERASE] = 0x08;
+        if libc::tcsetattr(fd, libc::TCSANOW, &termios) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+impl Pty {
+    // The first element is the program to run, the rest are its arguments
+    pub fn spawn(command: &[String], size: (u32, u32)) -> std::io::Result<Self> {
+        let (program, args) = command.split_first().ok_or(std::io::ErrorKind::InvalidInput)?;
+
+        let (mut master, mut slave) = (0, 0);
+        if unsafe { libc::openpty(&mut master, &mut slave, std::ptr::null_mut(), std::ptr::null(), std::ptr::null()) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let (master, slave) = unsafe { (File::from_raw_fd(master), File::from_raw_fd(slave)) };
+        set_size(slave.as_raw_fd(), size.0, size.1)?;
+        set_erase(slave.as_raw_fd())?;
+
+        let mut command = Command::new(program);
+        command.args(args)
+            .env("TERM", "xterm")
+            .stdin(Stdio::from(slave.try_clone()?))
+            .stdout(Stdio::from(slave.try_clone()?))
+            .stderr(Stdio::from(slave));
+        unsafe {
+            // A new session with the pty as its controlling terminal, so Ctrl+C reaches the whole job
+            command.pre_exec(|| {
+                if libc::setsid() == -1 || libc::ioctl(0, libc::TIOCSCTTY as _, 0) == -1 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        let child = command.spawn()?;
+        // The child has its own copies of the slave now, ours are closed with `command`
+        drop(command);
+
+        let wakeup = Arc::new(Mutex::new(None));
+        let (output, reader) = child_non_blocking_stream(Master(master.try_clone()?), &wakeup);
+        Ok(Self { child, master, output, reader, wakeup })
+    }
+}
+
+impl Backend for Pty {
+    fn write_input(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        self.master.write_all(bytes)?;
+        self.master.flush()
+    }
+
+    fn read_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut *self.output.lock().expect("Mutex lock poisoned"))
+    }
+
+    fn resize(&mut self, cols: u32, rows: u32) {
+        if let Err(e) = set_size(self.master.as_raw_fd(), cols, rows) {
+            eprintln!("{}: Could not resize pty: {}", line!(), e);
+        }
+    }
+
+    // Goes to the whole process group like it would from a terminal
+    fn signal(&mut self, signal: Signal) -> std::io::Result<()> {
+        let signal = match signal {
+            Signal::Interrupt => libc::SIGINT,
+            Signal::Terminate => libc::SIGTERM,
+            Signal::Kill => return send_signal(&mut self.child, signal),
+        };
+        if unsafe { libc::kill(-(self.child.id() as libc::pid_t), signal) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    // The tty echoes and edits the line itself, so it gets every key as it's typed
+    fn is_remote_echo(&self) -> bool {
+        true
+    }
+
+    fn is_exited(&mut self) -> bool {
+        self.child.try_wait().map(|e| e.is_some()).unwrap_or(true) && self.reader.is_finished()
+    }
+
+    fn set_wakeup(&mut self, wakeup: Option<Wakeup>) {
+        *self.wakeup.lock().expect("Mutex lock poisoned") = wakeup;
+    }
+}
@@ -0,0 +1,296 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{IpAddr, TcpListener},
+    path::PathBuf,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+};
+
+use serde_json::{json, Value};
+
+use crate::input_filter::InputFilter;
+use crate::prank::{PrankContext, PrankModes};
+use crate::progress::{parse_seconds, Progress, ProgressStyle};
+use crate::screen::Screen;
+use crate::wakeup::Wakeup;
+
+// Where the control socket listens, `tcp:PORT` or `tcp:HOST:PORT` for TCP and a path otherwise
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlAddress {
+    Unix(PathBuf),
+    Tcp(String),
+}
+
+impl ControlAddress {
+    // Anyone who can connect gets to type into the shell, so TCP only listens on this machine
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let address = match s.strip_prefix("tcp:") {
+            Some(port) if port.chars().all(|c| c.is_ascii_digit()) => return Ok(Self::Tcp(format!("127.0.0.1:{}", port))),
+            Some(address) => address,
+            None => return Ok(Self::Unix(PathBuf::from(s))),
+        };
+        let host = address.rsplit_once(':').map_or(address, |(host, _)| host);
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if host != "localhost" && !host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback()) {
+            return Err(format!("{} isn't this machine, the control socket only listens on localhost", host));
+        }
+        Ok(Self::Tcp(address.to_string()))
+    }
+}
+
+// A request from a client, waiting on the main thread for its reply
+pub struct RemoteRequest {
+    pub request: Value,
+    reply: Sender<Value>,
+}
+
+impl RemoteRequest {
+    pub fn respond(self, reply: Value) {
+        // The client might have gone away already
+        let _ = self.reply.send(reply);
+    }
+}
+
+// Lets other programs drive the session. Every line a client sends is a JSON request and
+// gets one JSON line back, see `handle` for what's understood
+pub struct RemoteControl {
+    requests: Receiver<RemoteRequest>,
+    address: String,
+    // Removed again when the window closes
+    socket_path: Option<PathBuf>,
+}
+
+fn serve<R: Read, W: Write>(reader: R, mut writer: W, requests: Sender<RemoteRequest>, wakeup: Option<Wakeup>) {
+    for line in BufReader::new(reader).lines() {
+        let line = match line {
+            Ok(line) if line.trim().is_empty() => continue,
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        let reply = match serde_json::from_str(&line) {
+            Ok(request) => {
+                let (reply, replied) = mpsc::channel();
+                if requests.send(RemoteRequest { request, reply }).is_err() {
+                    break;
+                }
+                if let Some(ref wakeup) = wakeup {
+                    wakeup.wake();
+                }
+                match replied.recv() {
+                    Ok(reply) => reply,
+                    Err(_) => break,
+                }
+            }
+            Err(e) => json!({ "ok": false, "error": format!("invalid request: {}", e) }),
+        };
+        if writeln!(writer, "{}", reply).and_then(|_| writer.flush()).is_err() {
+            break;
+        }
+    }
+}
+
+impl RemoteControl {
+    pub fn bind(address: &ControlAddress, wakeup: Option<Wakeup>) -> std::io::Result<Self> {
+        let (sender, requests) = mpsc::channel();
+        match address {
+            ControlAddress::Tcp(address) => {
+                let listener = TcpListener::bind(address)?;
+                let address = listener.local_addr()?.to_string();
+                thread::spawn(move || {
+                    for stream in listener.incoming().flatten() {
+                        let (sender, wakeup) = (sender.clone(), wakeup.clone());
+                        match stream.try_clone() {
+                            Ok(writer) => {
+                                thread::spawn(move || serve(stream, writer, sender, wakeup));
+                            }
+                            Err(e) => eprintln!("{}: Could not accept control connection: {}", line!(), e),
+                        }
+                    }
+                });
+                Ok(Self { requests, address, socket_path: None })
+            }
+            #[cfg(unix)]
+            ControlAddress::Unix(path) => {
+                use std::os::unix::{fs::FileTypeExt, net::{UnixListener, UnixStream}};
+                // A socket nobody answers on is left over from a window that didn't get to clean
+                // up. Anything that isn't a socket is someone's file and stays where it is
+                if let Ok(metadata) = std::fs::symlink_metadata(path) {
+                    if !metadata.file_type().is_socket() {
+                        return Err(std::io::Error::new(std::io::ErrorKind::AlreadyExists, format!("{} exists and is not a socket", path.display())));
+                    }
+                    if UnixStream::connect(path).is_err() {
+                        std::fs::remove_file(path)?;
+                    }
+                }
+                let listener = UnixListener::bind(path)?;
+                thread::spawn(move || {
+                    for stream in listener.incoming().flatten() {
+                        let (sender, wakeup) = (sender.clone(), wakeup.clone());
+                        match stream.try_clone() {
+                            Ok(writer) => {
+                                thread::spawn(move || serve(stream, writer, sender, wakeup));
+                            }
+                            Err(e) => eprintln!("{}: Could not accept control connection: {}", line!(), e),
+                        }
+                    }
+                });
+                Ok(Self { requests, address: path.display().to_string(), socket_path: Some(path.clone()) })
+            }
+            #[cfg(not(unix))]
+            ControlAddress::Unix(_) => Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "only tcp: control addresses are supported here")),
+        }
+    }
+
+    // What clients connect to, with the actual port when 0 was asked for
+    pub fn get_address(&self) -> &str {
+        &self.address
+    }
+
+    pub fn poll(&self) -> Vec<RemoteRequest> {
+        self.requests.try_iter().collect()
+    }
+}
+
+impl Drop for RemoteControl {
+    fn drop(&mut self) {
+        if let Some(ref path) = self.socket_path {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+fn get_str<'a>(request: &'a Value, key: &str) -> Result<&'a str, String> {
+    request[key].as_str().ok_or(format!("missing \"{}\"", key))
+}
+
+// Requests look like {"cmd": "...", ...}:
+//
+//     output {text}                        shows text as if the child printed it
+//     type {text}                          types it, a newline presses Enter
+//     mode {name, active?}                 switches a prank mode, toggles it without `active`
+//     param {mode, name, value}            sets a mode parameter
+//     modes                                lists modes, whether they're on, keys and parameters
+//     color {value}                        background and text color as two hex digits like 1f
+//     substitute {rule?, rules?, active?}  adds a rule, loads a rules file (or "default"), switches it
+//     screen {lines?}                      the text on screen, only the last `lines` if given
+//     progress {style?, seconds?, label?}  plays a progress animation, bar for 5 seconds by default
+//     progress {abort: true}               stops it (or a scripted response) like Ctrl+C
+//     filter {spec, name?}                 puts an input filter like "lag min=50 max=400" in front of
+//                                          typing, replacing the one with the same name (the kind by default)
+//     filter {name, remove: true}          takes it out again
+//     filters                              lists input filters in the order keys go through them
+//
+// Replies are {"ok": true, ...} or {"ok": false, "error": "..."}
+pub fn handle(request: &Value, pranks: &mut PrankModes, ctx: &mut PrankContext, color: &mut u8) -> Value {
+    match handle_request(request, pranks, ctx, color) {
+        Ok(Value::Null) => json!({ "ok": true }),
+        Ok(Value::Object(mut reply)) => {
+            reply.insert("ok".to_string(), Value::Bool(true));
+            Value::Object(reply)
+        }
+        Ok(reply) => json!({ "ok": true, "result": reply }),
+        Err(e) => json!({ "ok": false, "error": e }),
+    }
+}
+
+fn handle_request(request: &Value, pranks: &mut PrankModes, ctx: &mut PrankContext, color: &mut u8) -> Result<Value, String> {
+    match get_str(request, "cmd")? {
+        "output" => ctx.cmd.write_text(get_str(request, "text")?),
+        // Key by key like it was typed in the window
+        "type" => {
+            for c in get_str(request, "text")?.chars() {
+                if c == '\n' {
+                    pranks.enter(ctx);
+                }
+                else {
+                    pranks.type_text(c.encode_utf8(&mut [0; 4]), ctx);
+                }
+            }
+        }
+        "mode" => {
+            let name = get_str(request, "name")?;
+            let active = request["active"].as_bool().unwrap_or(!pranks.is_active(name));
+            pranks.set_active(name, active, ctx)?;
+        }
+        "param" => pranks.set_param(get_str(request, "mode")?, get_str(request, "name")?, get_str(request, "value")?)?,
+        "modes" => {
+            let mut modes = vec![];
+            for (name, description, active, key) in pranks.list() {
+                let params: serde_json::Map<String, Value> = pranks.get_params(name)?.into_iter()
+                    .map(|(k, v)| (k, Value::String(v)))
+                    .collect();
+                modes.push(json!({
+                    "name": name,
+                    "description": description,
+                    "active": active,
+                    "key": key.map(|k| k.to_string()),
+                    "params": params,
+                }));
+            }
+            return Ok(json!({ "modes": modes }));
+        }
+        "color" => {
+            let value = get_str(request, "value")?;
+            *color = u8::from_str_radix(value, 16).ok().filter(|_| value.len() == 2)
+                .ok_or(format!("invalid color: {}", value))?;
+        }
+        "substitute" => {
+            if let Some(rules) = request["rules"].as_str() {
+                pranks.set_param("substitute", "rules", rules)?;
+            }
+            if let Some(rule) = request["rule"].as_str() {
+                pranks.set_param("substitute", "rule", rule)?;
+            }
+            if let Some(active) = request["active"].as_bool() {
+                pranks.set_active("substitute", active, ctx)?;
+            }
+        }
+        "progress" if request["abort"].as_bool() == Some(true) => {
+            if !ctx.cmd.abort_response() {
+                return Err("nothing is playing".to_string());
+            }
+        }
+        "progress" => {
+            let style = request["style"].as_str().map_or(Ok(ProgressStyle::Bar), ProgressStyle::parse)?;
+            let mut progress = Progress::new(style, std::time::Duration::from_secs(5));
+            if let Some(seconds) = request["seconds"].as_f64() {
+                progress.duration = parse_seconds(&seconds.to_string())?;
+            }
+            progress.label = request["label"].as_str().map(str::to_string);
+            ctx.cmd.start_progress(&progress);
+        }
+        "filter" if request["remove"].as_bool() == Some(true) => {
+            let name = get_str(request, "name")?;
+            if !ctx.cmd.input_filters.remove(name) {
+                return Err(format!("no such input filter: {}", name));
+            }
+        }
+        "filter" => {
+            let filter = InputFilter::parse(get_str(request, "spec")?)?;
+            let name = request["name"].as_str().unwrap_or(filter.get_kind()).to_string();
+            ctx.cmd.input_filters.set(&name, filter);
+        }
+        "filters" => {
+            let filters: Vec<Value> = ctx.cmd.input_filters.list()
+                .map(|(name, filter)| json!({ "name": name, "spec": filter.to_string() }))
+                .collect();
+            return Ok(json!({ "filters": filters }));
+        }
+        // What's shown, with whatever the modes draw over the output
+        "screen" => {
+            let mut screen = Screen::from_buffer(ctx.cmd.get_stdout_buffer(), *color);
+            pranks.render(&mut screen);
+            let text = screen.get_text();
+            let text = match request["lines"].as_u64() {
+                Some(n) => {
+                    let lines: Vec<&str> = text.split('\n').collect();
+                    lines[lines.len().saturating_sub(n as usize)..].join("\n")
+                }
+                None => text.to_string(),
+            };
+            return Ok(json!({ "text": text }));
+        }
+        other => return Err(format!("unknown command: {}", other)),
+    }
+    Ok(Value::Null)
+}
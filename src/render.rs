@@ -1,116 +1,137 @@
-use sdl2::pixels::Color;
-
+use crate::font::Font;
+use crate::layout::LineIndex;
 use crate::screen::Screen;
+use crate::search::{Search, SearchMode};
 
-pub trait SdlFontRendererExt {
-    fn render_font_string_wrapped(
-        &mut self,
-        x: i32, y: i32,
-        right_bound: u32,
-        texture: &mut sdl2::render::Texture,
-        font: &crate::font::Font,
-        string: &str
-    ) -> Result<(i32, i32), String>;
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
 }
 
-impl SdlFontRendererExt for sdl2::render::WindowCanvas {
-    fn render_font_string_wrapped(&mut self, x: i32, y: i32, right_bound: u32, texture: &mut sdl2::render::Texture, font: &crate::font::Font, string: &str) -> Result<(i32, i32), String> {
-        let previous_color_mod = texture.color_mod();
-        let previous_alpha = texture.alpha_mod();
-        let canvas_color = self.draw_color();
-
-        texture.set_alpha_mod(canvas_color.a);
-        texture.set_color_mod(canvas_color.r, canvas_color.g, canvas_color.b);
-
-        let mut mutx = x;
-        let mut muty = y;
-
-        for glyph in string.chars() {
-            // Check if we should wrap over the screen
-            match glyph {
-                '\r' => (),
-                '\n' => {
-                    muty += font.glyph_size.1 as i32;
-                    mutx = x;
-                },
-                '\t' => {
-                    mutx += font.glyph_size.0 as i32 * 4;
-                },
-                _ => {
-                    let rect = font.get_glyph_rect_sdl(glyph);
-                    let dest = sdl2::rect::Rect::new(mutx, muty, rect.width(), rect.height());
-                    self.copy(texture, Some(rect), Some(dest))?;
-                    mutx += font.glyph_size.0 as i32;
-                }
-            }
-            if (mutx+font.glyph_size.0 as i32) > (right_bound as i32) {
-                muty += font.glyph_size.1 as i32;
-                mutx = x;
-            }
-        }
+impl Color {
+    pub const BLACK: Color = Color::rgb(0, 0, 0);
+    pub const WHITE: Color = Color::rgb(0xff, 0xff, 0xff);
 
-        texture.set_alpha_mod(previous_alpha);
-        texture.set_color_mod(previous_color_mod.0, previous_color_mod.1, previous_color_mod.2);
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub w: u32,
+    pub h: u32,
+}
 
-        Ok((mutx, muty))
+impl Rect {
+    pub fn new(x: i32, y: i32, w: u32, h: u32) -> Self {
+        Self { x, y, w, h }
     }
+
+    pub fn contains_point(&self, point: (i32, i32)) -> bool {
+        point.0 >= self.x && point.0 < self.x + self.w as i32 &&
+        point.1 >= self.y && point.1 < self.y + self.h as i32
+    }
+}
+
+// Everything `VisualCommandLine` needs to draw itself, so the same logic
+// can drive a window, an off-screen framebuffer or a plain text dump
+pub trait Renderer {
+    // Size of the output in pixels
+    fn get_size(&self) -> (u32, u32);
+    fn fill_rect(&mut self, rect: Rect, color: Color);
+    // Draws the glyphs one cell apart starting at `x`, `y` without any wrapping
+    fn draw_cell_run(&mut self, x: i32, y: i32, glyphs: &str, color: Color);
+    fn draw_cursor(&mut self, rect: Rect, color: Color) {
+        self.fill_rect(rect, color);
+    }
+    fn present(&mut self);
 }
 
-const MOD13_PAL: [Color; 16] = [
-    Color::RGB(0, 0, 0),
-    Color::RGB(0, 0, 0xaa),
-    Color::RGB(0, 0xaa, 0),
-    Color::RGB(0, 0xaa, 0xaa),
-    Color::RGB(0xaa, 0, 0),
-    Color::RGB(0xaa, 0, 0xaa),
-    Color::RGB(0xaa, 0x55, 0),
-    Color::RGB(0xaa, 0xaa, 0xaa),
-    Color::RGB(0x55, 0x55, 0x55),
-    Color::RGB(0x55, 0x55, 0xff),
-    Color::RGB(0x55, 0xff, 0x55),
-    Color::RGB(0x55, 0xff, 0xff),
-    Color::RGB(0xff, 0x55, 0x55),
-    Color::RGB(0xff, 0x55, 0xff),
-    Color::RGB(0xff, 0xff, 0x55),
-    Color::RGB(0xff, 0xff, 0xff)
+pub const MOD13_PAL: [Color; 16] = [
+    Color::rgb(0, 0, 0),
+    Color::rgb(0, 0, 0xaa),
+    Color::rgb(0, 0xaa, 0),
+    Color::rgb(0, 0xaa, 0xaa),
+    Color::rgb(0xaa, 0, 0),
+    Color::rgb(0xaa, 0, 0xaa),
+    Color::rgb(0xaa, 0x55, 0),
+    Color::rgb(0xaa, 0xaa, 0xaa),
+    Color::rgb(0x55, 0x55, 0x55),
+    Color::rgb(0x55, 0x55, 0xff),
+    Color::rgb(0x55, 0xff, 0x55),
+    Color::rgb(0x55, 0xff, 0xff),
+    Color::rgb(0xff, 0x55, 0x55),
+    Color::rgb(0xff, 0x55, 0xff),
+    Color::rgb(0xff, 0xff, 0x55),
+    Color::rgb(0xff, 0xff, 0xff)
 ];
 
+// What the scrollbar and caret look like
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ConsoleStyle {
+    // Classic scrollbar with arrows and an underline caret
+    #[default]
+    Win10,
+    // Thin scrollbar that only grows its track when the mouse is on it, bar caret
+    Win11,
+}
+
 enum ScrollbarState {
     Blurred,
     Hovered,
     Pressed
 }
 
-pub struct VisualCommandLine<'a> {
+pub struct VisualCommandLine {
     scroll_locked: bool,
     scrollbar_state: ScrollbarState,
-    font: crate::font::Font,
-    font_texture: sdl2::render::Texture<'a>,
-    ticks: usize,
+    font: Font,
+    lines: LineIndex,
+    caret_visible: bool,
     last_pos: (u32, u32),
-    scroll: u32
+    scroll: u32,
+    style: ConsoleStyle,
 }
 
-impl<'a> VisualCommandLine<'a> {
-    pub fn set_font_texture(&mut self, tex: sdl2::render::Texture<'a>) {
-        self.font_texture = tex;
+impl VisualCommandLine {
+    pub fn new(font: Font) -> Self {
+        Self { caret_visible: true, lines: LineIndex::new(&font), font, scroll: 0, scrollbar_state: ScrollbarState::Blurred, last_pos: (0, 0), scroll_locked: true, style: ConsoleStyle::default() }
     }
 
-    pub fn new(font_texture: sdl2::render::Texture<'a>, font: crate::font::Font) -> Self {
-        Self { ticks: 0, font_texture, font, scroll: 0, scrollbar_state: ScrollbarState::Blurred, last_pos: (0, 0), scroll_locked: true }
+    pub fn set_style(&mut self, style: ConsoleStyle) {
+        self.style = style;
+    }
+
+    pub fn get_style(&self) -> ConsoleStyle {
+        self.style
     }
 
     fn is_caret_rendered(&self) -> bool {
-        (self.ticks / 10) % 2 == 0
+        self.caret_visible
+    }
+
+    // The frontend decides when, cmd.exe toggles it every ~500ms
+    pub fn blink(&mut self) {
+        self.caret_visible = !self.caret_visible;
+    }
+
+    // Typing keeps the caret visible like a real console does
+    pub fn show_caret(&mut self) {
+        self.caret_visible = true;
     }
 
-    pub fn mouse_press(&mut self, canvas: &sdl2::render::WindowCanvas, mouse_pos: (i32, i32)) {
-        let height = canvas.window().size().1 as i32;
-        let width = canvas.window().size().0 as i32;
-        let top_half_rect = sdl2::rect::Rect::new(width-16, 0, 16, 16);
-        let bottom_half_rect = sdl2::rect::Rect::new(width-16, height-16, 16, 16);
+    pub fn mouse_press<R: Renderer>(&mut self, renderer: &R, mouse_pos: (i32, i32)) {
+        let height = renderer.get_size().1 as i32;
+        let width = renderer.get_size().0 as i32;
+        let top_half_rect = Rect::new(width-16, 0, 16, 16);
+        let bottom_half_rect = Rect::new(width-16, height-16, 16, 16);
         let overflow_height = self.last_pos.1;
-        let scroll_rect = self.get_scrollbar_thumb_rect(canvas, overflow_height as u32);
+        let scroll_rect = self.get_scrollbar_thumb_rect(renderer, overflow_height as u32);
 
         if scroll_rect.contains_point((mouse_pos.0, mouse_pos.1)) {
             self.scrollbar_state = ScrollbarState::Pressed;
@@ -126,10 +147,10 @@ impl<'a> VisualCommandLine<'a> {
         }
     }
 
-    pub fn mouse_move(&mut self, canvas: &sdl2::render::WindowCanvas, mouse_pos: (i32, i32), mouse_delta_y: i32) -> bool {
+    pub fn mouse_move<R: Renderer>(&mut self, renderer: &R, mouse_pos: (i32, i32), mouse_delta_y: i32) -> bool {
         let overflow_height = self.last_pos.1;
-        let scroll_rect = self.get_scrollbar_thumb_rect(canvas, overflow_height as u32);
-        let height = canvas.window().size().1 as i32;
+        let scroll_rect = self.get_scrollbar_thumb_rect(renderer, overflow_height as u32);
+        let height = renderer.get_size().1 as i32;
         let thumb_height = height*height/(overflow_height as i32).max(1);
 
         if let ScrollbarState::Pressed = self.scrollbar_state {
@@ -141,7 +162,7 @@ impl<'a> VisualCommandLine<'a> {
             self.scrollbar_state = ScrollbarState::Hovered;
         }
         else {
-             self.scrollbar_state = ScrollbarState::Blurred;       
+             self.scrollbar_state = ScrollbarState::Blurred;
         }
         return false;
     }
@@ -150,28 +171,28 @@ impl<'a> VisualCommandLine<'a> {
         self.scroll_locked = true;
     }
 
-    pub fn mouse_release(&mut self, canvas: &sdl2::render::WindowCanvas, mouse_pos: (i32, i32)) {
+    pub fn mouse_release<R: Renderer>(&mut self, renderer: &R, mouse_pos: (i32, i32)) {
         self.scrollbar_state = ScrollbarState::Blurred;
     }
 
-    pub fn get_scrollbar_thumb_rect(&self, canvas: &sdl2::render::WindowCanvas, overflow_height: u32) -> sdl2::rect::Rect {
+    pub fn get_scrollbar_thumb_rect<R: Renderer>(&self, renderer: &R, overflow_height: u32) -> Rect {
         // We subtract 17 because of lower arrow
-        let mut height = canvas.window().size().1.max(17*2);
+        let mut height = renderer.get_size().1.max(17*2);
         if height > overflow_height {
-            return sdl2::rect::Rect::new(0, 0, 0, 0);
+            return Rect::new(0, 0, 0, 0);
         }
 
         let scroll = self.scroll.min(overflow_height-height);
-        let starting_at = canvas.window().size().0-15;
+        let starting_at = renderer.get_size().0-15;
         let thumb_height = (height-17*2)*height/(overflow_height);
-        sdl2::rect::Rect::new(starting_at as i32, (scroll*(height-17*2)/overflow_height) as i32 + 17, 14, thumb_height)
+        Rect::new(starting_at as i32, (scroll*(height-17*2)/overflow_height) as i32 + 17, 14, thumb_height)
     }
 
-    fn render_embedded_bitmap(&self, canvas: &mut sdl2::render::WindowCanvas, buf: &[&str], sx: i32, sy: i32) {
+    fn render_embedded_bitmap<R: Renderer>(&self, renderer: &mut R, buf: &[&str], sx: i32, sy: i32, color: Color) {
         for (y, row) in buf.iter().enumerate() {
             for (x, chr) in (*row).chars().enumerate() {
                 if chr == '#' {
-                    canvas.draw_point((sx+x as i32, sy+y as i32)).unwrap();
+                    renderer.fill_rect(Rect::new(sx+x as i32, sy+y as i32, 1, 1), color);
                 }
             }
         }
@@ -182,80 +203,248 @@ impl<'a> VisualCommandLine<'a> {
         self.scroll = (self.scroll as i32 + n).max(0) as u32;
     }
 
-    pub fn render_scrollbar(&self, canvas: &mut sdl2::render::WindowCanvas) {
+    pub fn render_scrollbar<R: Renderer>(&self, renderer: &mut R) {
         let overflow_height = self.last_pos.1;
-        let height = canvas.window().size().1.max(17*2);
+        let height = renderer.get_size().1.max(17*2);
         // No reason to render scroll bar if overflow_height is less than height
         if height > overflow_height {
             return;
         }
-        
-        let starting_at = canvas.window().size().0-16;
 
-        // Windows 10-style scrollbar (hardcoded)
+        let starting_at = renderer.get_size().0-16;
+
+        if self.style == ConsoleStyle::Win11 {
+            self.render_thin_scrollbar(renderer, starting_at as i32, height, overflow_height);
+            return;
+        }
+
+        // Windows 10-style scrollbar
         // Here we render the thin white line between scrollbar and cmd
-        canvas.set_draw_color(Color::WHITE);
-        canvas.fill_rect(sdl2::rect::Rect::new(starting_at as i32, 0, 1, height)).unwrap();
+        renderer.fill_rect(Rect::new(starting_at as i32, 0, 1, height), Color::WHITE);
 
         // Scrollbar background
-        canvas.set_draw_color(Color::RGB(0xf0, 0xf0, 0xf0));
-        canvas.fill_rect(sdl2::rect::Rect::new(starting_at as i32 + 1, 0, 15, height)).unwrap();
+        renderer.fill_rect(Rect::new(starting_at as i32 + 1, 0, 15, height), Color::rgb(0xf0, 0xf0, 0xf0));
 
         // Scrollbar thumb
-        match self.scrollbar_state {
-            ScrollbarState::Blurred => 
-                canvas.set_draw_color(Color::RGB(0xcd, 0xcd, 0xcd)),
-            ScrollbarState::Hovered => 
-                canvas.set_draw_color(Color::RGB(0xab, 0xab, 0xab)),
-            ScrollbarState::Pressed => 
-                canvas.set_draw_color(Color::RGB(0x9a, 0x9a, 0x9a)),
-        }
-        canvas.fill_rect(self.get_scrollbar_thumb_rect(canvas, overflow_height)).unwrap();
+        let thumb_color = match self.scrollbar_state {
+            ScrollbarState::Blurred => Color::rgb(0xcd, 0xcd, 0xcd),
+            ScrollbarState::Hovered => Color::rgb(0xab, 0xab, 0xab),
+            ScrollbarState::Pressed => Color::rgb(0x9a, 0x9a, 0x9a),
+        };
+        renderer.fill_rect(self.get_scrollbar_thumb_rect(renderer, overflow_height), thumb_color);
 
         // Render top arrow
-        canvas.set_draw_color(Color::RGB(0x60, 0x60, 0x60));
+        let arrow_color = Color::rgb(0x60, 0x60, 0x60);
         let mut arrow_b = vec!["   #   ",
                            "  ###  ",
                            " ##### ",
                            "### ###",
                            "##   ##",
                            "#     #"];
- 
-        self.render_embedded_bitmap(canvas, &arrow_b, starting_at as i32 + 4, 6);
-        
+
+        self.render_embedded_bitmap(renderer, &arrow_b, starting_at as i32 + 4, 6, arrow_color);
+
         // This is the worst hack
         arrow_b.reverse();
 
-        self.render_embedded_bitmap(canvas, &arrow_b, starting_at as i32 + 4, height as i32-17+6);       
+        self.render_embedded_bitmap(renderer, &arrow_b, starting_at as i32 + 4, height as i32-17+6, arrow_color);
+    }
+
+    // Windows 11 only shows a thin line for the thumb until the mouse gets to it
+    fn render_thin_scrollbar<R: Renderer>(&self, renderer: &mut R, starting_at: i32, height: u32, overflow_height: u32) {
+        let thumb = self.get_scrollbar_thumb_rect(renderer, overflow_height);
+        let (track, thumb_x, thumb_width, thumb_color) = match self.scrollbar_state {
+            ScrollbarState::Blurred => (false, starting_at + 12, 2, Color::rgb(0x85, 0x85, 0x85)),
+            ScrollbarState::Hovered => (true, starting_at + 5, 6, Color::rgb(0x85, 0x85, 0x85)),
+            ScrollbarState::Pressed => (true, starting_at + 5, 6, Color::rgb(0x60, 0x60, 0x60)),
+        };
+        if track {
+            renderer.fill_rect(Rect::new(starting_at, 0, 16, height), Color::rgb(0xf9, 0xf9, 0xf9));
+            let arrow_color = Color::rgb(0x85, 0x85, 0x85);
+            let mut arrow = vec!["  #  ",
+                                 " ### ",
+                                 "#####"];
+            self.render_embedded_bitmap(renderer, &arrow, starting_at + 6, 7, arrow_color);
+            arrow.reverse();
+            self.render_embedded_bitmap(renderer, &arrow, starting_at + 6, height as i32-17+7, arrow_color);
+        }
+        // Rounded off by leaving out the corners
+        if thumb.h > 2 {
+            renderer.fill_rect(Rect::new(thumb_x + 1, thumb.y, thumb_width - 2, 1), thumb_color);
+            renderer.fill_rect(Rect::new(thumb_x, thumb.y + 1, thumb_width, thumb.h - 2), thumb_color);
+            renderer.fill_rect(Rect::new(thumb_x + 1, thumb.y + thumb.h as i32 - 1, thumb_width - 2, 1), thumb_color);
+        }
     }
 
-    pub fn render(&mut self, canvas: &mut sdl2::render::WindowCanvas, cmd: &Screen) {
-        let (width, height) = canvas.window().size();
+    fn get_text_right_bound(&self, wsize: (u32, u32)) -> u32 {
+        wsize.0-if self.last_pos.1 > wsize.1 { 16 } else { 0 }
+    }
+
+    // Lays the string out and draws it in runs of glyphs that sit next to each other,
+    // rows outside of the output are skipped
+    fn render_string_wrapped<R: Renderer>(&self, renderer: &mut R, x: i32, y: i32, right_bound: u32, string: &str, color: Color) -> (i32, i32) {
+        let height = renderer.get_size().1 as i32;
+        let (gw, gh) = (self.font.glyph_size.0 as i32, self.font.glyph_size.1 as i32);
+        let mut run = String::new();
+        let mut run_start = (0, 0);
+        let mut run_end = (0, 0);
+
+        let end = self.font.layout_string_wrapped(x, y, right_bound, string, |_, glyph, gx, gy| {
+            if gy + gh <= 0 || gy >= height {
+                return;
+            }
+            if !run.is_empty() && (gx, gy) != run_end {
+                renderer.draw_cell_run(run_start.0, run_start.1, &run, color);
+                run.clear();
+            }
+            if run.is_empty() {
+                run_start = (gx, gy);
+            }
+            run.push(glyph);
+            run_end = (gx + gw, gy);
+        });
+        if !run.is_empty() {
+            renderer.draw_cell_run(run_start.0, run_start.1, &run, color);
+        }
+
+        end
+    }
+
+    // Scrolls so the glyph at `offset` in the screen text ends up in the middle of the window
+    pub fn scroll_to_offset(&mut self, wsize: (u32, u32), cmd: &Screen, offset: usize) {
+        self.lines.update(cmd, self.get_text_right_bound(wsize));
+        let y = self.lines.get_row_at_offset(self.lines.get_base() + offset) as i32 * self.font.glyph_size.1 as i32;
+
+        self.scroll_locked = false;
+        self.scroll = (y - wsize.1 as i32/2).max(0) as u32;
+    }
+
+    // Fills the background behind every search hit and returns the glyphs
+    // that have to be drawn over it once the text is rendered
+    fn render_search_highlights<R: Renderer>(&self, renderer: &mut R, cmd: &Screen, search: &Search) -> Vec<(char, i32, i32)> {
+        let (width, height) = renderer.get_size();
+        let right_bound = self.get_text_right_bound((width, height));
+        let matches = search.get_matches();
+        let current = search.get_current();
+        let (gw, gh) = (self.font.glyph_size.0 as u32, self.font.glyph_size.1 as u32);
+        let mut glyphs = vec![];
+
+        let rows = self.lines.get_visible_rows(self.scroll, height);
+        let top = rows.start as i32 * gh as i32 - self.scroll as i32;
+        let base = if rows.is_empty() { 0 } else { self.lines.get_row_start(rows.start) - self.lines.get_base() };
+        let screen_text = cmd.get_text();
+        let text = self.lines.get_rows_text(&screen_text, rows);
+        let mut idx = matches.partition_point(|m| m.1 <= base);
+
+        self.font.layout_string_wrapped(0, top, right_bound, text, |offset, glyph, x, y| {
+            let offset = base + offset;
+            while idx < matches.len() && matches[idx].1 <= offset {
+                idx += 1;
+            }
+            if idx == matches.len() || offset < matches[idx].0 {
+                return;
+            }
+            // Everything outside of the window is skipped
+            if y + gh as i32 <= 0 || y >= height as i32 {
+                return;
+            }
+            let color = if Some(matches[idx]) == current {
+                MOD13_PAL[10]
+            }
+            else {
+                MOD13_PAL[14]
+            };
+            renderer.fill_rect(Rect::new(x, y, gw, gh), color);
+            glyphs.push((glyph, x, y));
+        });
+
+        glyphs
+    }
+
+    fn render_search_bar<R: Renderer>(&self, renderer: &mut R, search: &Search) {
+        let (width, height) = renderer.get_size();
+        let gw = self.font.glyph_size.0 as u32;
+        let gh = self.font.glyph_size.1 as u32;
+        let bar_width = (gw*48).min(self.get_text_right_bound((width, height)));
+        let bar_x = (self.get_text_right_bound((width, height)) - bar_width) as i32;
+        let columns = (bar_width/gw) as usize;
+
+        let label = match search.get_mode() {
+            SearchMode::Plain => "Find: ",
+            SearchMode::Regex => "Regex: ",
+        };
+        let status = search.status();
+
+        // Only the tail of the query is shown if it doesn't fit
+        let room = columns.saturating_sub(label.len() + status.len() + 3);
+        let query = search.get_query();
+        let skip = query.chars().count().saturating_sub(room);
+        let query = query.chars().skip(skip).collect::<String>();
+
+        let border = Color::rgb(0x60, 0x60, 0x60);
+        renderer.fill_rect(Rect::new(bar_x, 0, bar_width, gh + 2), border);
+        renderer.fill_rect(Rect::new(bar_x + 1, 1, bar_width - 2, gh), Color::rgb(0xf0, 0xf0, 0xf0));
+
+        let (caret_x, _) = self.render_string_wrapped(renderer, bar_x + 1, 1, width, &format!("{}{}", label, query), Color::BLACK);
+        if self.is_caret_rendered() {
+            renderer.draw_cursor(Rect::new(caret_x, 1+gh as i32-6, gw, 3), Color::BLACK);
+        }
+        let status_x = bar_x + 1 + ((columns - 1 - status.len().min(columns - 1)) as u32*gw) as i32;
+        self.render_string_wrapped(renderer, status_x, 1, width, &status, Color::BLACK);
+    }
+
+    pub fn render<R: Renderer>(&mut self, renderer: &mut R, cmd: &Screen, search: Option<&Search>) {
+        let (width, height) = renderer.get_size();
         // Background
-        canvas.set_draw_color(MOD13_PAL[((cmd.color>>4)&0xF) as usize]);
-        canvas.clear();
+        renderer.fill_rect(Rect::new(0, 0, width, height), MOD13_PAL[((cmd.color>>4)&0xF) as usize]);
+
+        let right_bound = self.get_text_right_bound((width, height));
+        self.lines.update(cmd, right_bound);
+
+        let highlighted = match search {
+            Some(search) => self.render_search_highlights(renderer, cmd, search),
+            None => vec![],
+        };
 
         // Foreground color
-        canvas.set_draw_color(MOD13_PAL[(cmd.color&0xF) as usize]);
- 
-        let last_pos = canvas.render_font_string_wrapped(0, -(self.scroll as i32), canvas.window().size().0-if self.last_pos.1 > height { 16 } else { 0 }, &mut self.font_texture, &self.font, cmd.get_text()).unwrap();
+        let foreground = MOD13_PAL[(cmd.color&0xF) as usize];
 
+        // Only the rows inside the window are laid out again
+        let rows = self.lines.get_visible_rows(self.scroll, height);
+        let top = rows.start as i32 * self.font.glyph_size.1 as i32 - self.scroll as i32;
+        self.render_string_wrapped(renderer, 0, top, right_bound, self.lines.get_rows_text(&cmd.get_text(), rows), foreground);
 
         // Render the caret
         if self.is_caret_rendered() {
-            canvas.fill_rect(sdl2::rect::Rect::new(self.last_pos.0 as i32, last_pos.1 as i32+(16-6), 8, 3)).unwrap();
+            let (x, y) = self.lines.get_end();
+            let y = y - self.scroll as i32;
+            let caret = match self.style {
+                ConsoleStyle::Win10 => Rect::new(x, y + (16-6), 8, 3),
+                ConsoleStyle::Win11 => Rect::new(x, y, 1, 16),
+            };
+            renderer.draw_cursor(caret, foreground);
+        }
+
+        // Hits are redrawn in black so they stay readable over the highlight
+        for (glyph, x, y) in highlighted {
+            let mut buf = [0; 4];
+            renderer.draw_cell_run(x, y, glyph.encode_utf8(&mut buf), Color::BLACK);
+        }
+
+        self.render_scrollbar(renderer);
+
+        if let Some(search) = search {
+            self.render_search_bar(renderer, search);
         }
-        
-        self.render_scrollbar(canvas);
     }
 
     pub fn update(&mut self, wsize: (u32, u32), cmd: &Screen) {
-        self.ticks += 1;
-        let (width, height) = wsize;
+        let height = wsize.1;
+
+        self.lines.update(cmd, self.get_text_right_bound(wsize));
+        let last_pos = self.lines.get_end();
 
-        let last_pos = self.font.get_size_from_string(0, 0, (width as i32)-if self.last_pos.1 > height { 16 } else { 0 }, cmd.get_text());
 
-       
         self.last_pos = (last_pos.0.max(0) as u32, last_pos.1.max(0) as u32);
 
         if self.scroll_locked {
@@ -265,4 +454,3 @@ impl<'a> VisualCommandLine<'a> {
         self.scroll = self.scroll.min((self.last_pos.1 as i32-height as i32+16).max(0) as u32);
     }
 }
-
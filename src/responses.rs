@@ -0,0 +1,310 @@
+use std::{path::Path, time::{Duration, Instant, SystemTime, UNIX_EPOCH}};
+
+use regex::{Captures, Regex, RegexBuilder};
+
+use crate::progress::Progress;
+use crate::transcript::format_timestamp;
+
+// Canned output for commands that never reach the child. A file of them looks like
+//
+//     == netstat( -an)?
+//     @wait 400
+//     @line 60
+//     Active Connections
+//     ...
+//
+// `==` starts a response with a regular expression that has to match the whole typed line
+// (case doesn't matter). The `@` lines right after it set delays in milliseconds: `wait` before
+// anything is shown, `line` between lines and `char` between characters (typing). Everything
+// up to the next `==` is the output, where these get replaced:
+//
+//     {time} {date}      local time as HH:MM:SS and date as YYYY-MM-DD
+//     {user} {host}      from USERNAME/USER and COMPUTERNAME/HOSTNAME
+//     {random A-B}       a number between A and B
+//     {1} {2}...         groups from the pattern
+//     {pause MS}         waits before going on
+//     {progress STYLE SECONDS LABEL}
+//                        a progress animation, see `Progress::parse`
+//
+// Lines starting with # before the first response are comments
+pub struct Response {
+    pattern: Regex,
+    wait: Duration,
+    line_delay: Duration,
+    char_delay: Duration,
+    body: String,
+}
+
+#[derive(Default)]
+pub struct Responses {
+    responses: Vec<Response>,
+}
+
+fn parse_millis(s: &str) -> Option<Duration> {
+    s.trim().parse().ok().map(Duration::from_millis)
+}
+
+// Not meant to be unpredictable, just different every time
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed | 1)
+    }
+
+    pub fn from_time() -> Self {
+        Self::new(SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(0))
+    }
+
+    // xorshift64
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    pub fn range(&mut self, from: i64, to: i64) -> i64 {
+        let (from, to) = (from.min(to), from.max(to));
+        from + (self.next_u64() % ((to - from) as u64 + 1)) as i64
+    }
+}
+
+// Seconds east of UTC
+#[cfg(unix)]
+pub fn local_offset(secs: i64) -> i64 {
+    // SAFETY: localtime_r only writes to the tm it's given
+    unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        let time = secs as libc::time_t;
+        if libc::localtime_r(&time, &mut tm).is_null() {
+            return 0;
+        }
+        tm.tm_gmtoff as i64
+    }
+}
+
+#[cfg(not(unix))]
+pub fn local_offset(_secs: i64) -> i64 {
+    0
+}
+
+// `YYYY-MM-DD HH:MM:SS` in local time
+pub fn local_timestamp() -> String {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0);
+    let local = UNIX_EPOCH + Duration::from_secs((secs + local_offset(secs)).max(0) as u64);
+    format_timestamp(local).trim_end_matches('Z').to_string()
+}
+
+fn env_any(names: &[&str]) -> String {
+    names.iter().find_map(|n| std::env::var(n).ok()).unwrap_or_default()
+}
+
+enum Piece {
+    Text(String),
+    Pause(Duration),
+    // Already timed, like the frames of a progress animation
+    Steps(Box<dyn Iterator<Item = (Duration, String)>>),
+}
+
+// Fills in a template, pauses are kept apart so playback can wait on them
+fn expand(body: &str, caps: &Captures, rng: &mut Rng) -> Vec<Piece> {
+    let mut pieces = vec![];
+    let mut text = String::new();
+    let mut rest = body;
+    while let Some(start) = rest.find('{') {
+        // Without a closing brace the rest is just text, it's all added after the loop
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+        text.push_str(&rest[..start]);
+        let field = &rest[start + 1..end];
+        let (name, arg) = field.split_once(' ').unwrap_or((field, ""));
+        let timestamp = local_timestamp();
+        match name {
+            "time" => text.push_str(&timestamp[11..]),
+            "date" => text.push_str(&timestamp[..10]),
+            "user" => text.push_str(&env_any(&["USERNAME", "USER"])),
+            "host" => text.push_str(&env_any(&["COMPUTERNAME", "HOSTNAME"])),
+            "random" => match arg.split_once('-').and_then(|(a, b)| Some((a.trim().parse().ok()?, b.trim().parse().ok()?))) {
+                Some((from, to)) => text.push_str(&rng.range(from, to).to_string()),
+                None => text.push_str(&rest[start..=end]),
+            },
+            "pause" => match parse_millis(arg) {
+                Some(pause) => {
+                    pieces.push(Piece::Text(std::mem::take(&mut text)));
+                    pieces.push(Piece::Pause(pause));
+                }
+                None => text.push_str(&rest[start..=end]),
+            },
+            "progress" => match Progress::parse(arg) {
+                Ok(progress) => {
+                    pieces.push(Piece::Text(std::mem::take(&mut text)));
+                    pieces.push(Piece::Steps(Box::new(progress.frames(rng))));
+                }
+                Err(_) => text.push_str(&rest[start..=end]),
+            },
+            _ => match name.parse::<usize>().ok().and_then(|n| caps.get(n)) {
+                Some(group) => text.push_str(group.as_str()),
+                // Anything else is left alone so braces in the output don't need escaping
+                None => text.push_str(&rest[start..=end]),
+            },
+        }
+        rest = &rest[end + 1..];
+    }
+    text.push_str(rest);
+    pieces.push(Piece::Text(text));
+    pieces
+}
+
+impl Responses {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut responses: Vec<Response> = vec![];
+        let mut in_options = false;
+        for (n, line) in text.lines().enumerate() {
+            if let Some(pattern) = line.strip_prefix("==") {
+                let pattern = RegexBuilder::new(&format!("^(?:{})$", pattern.trim()))
+                    .case_insensitive(true)
+                    .build()
+                    .map_err(|e| format!("line {}: {}", n + 1, e))?;
+                responses.push(Response {
+                    pattern,
+                    wait: Duration::ZERO,
+                    line_delay: Duration::ZERO,
+                    char_delay: Duration::ZERO,
+                    body: String::new(),
+                });
+                in_options = true;
+                continue;
+            }
+            let response = match responses.last_mut() {
+                Some(response) => response,
+                None if line.trim().is_empty() || line.starts_with('#') => continue,
+                None => return Err(format!("line {}: expected == and a pattern", n + 1)),
+            };
+            if in_options {
+                if let Some(option) = line.strip_prefix('@') {
+                    let (name, value) = option.split_once(' ').unwrap_or((option, ""));
+                    let value = parse_millis(value).ok_or(format!("line {}: expected milliseconds", n + 1))?;
+                    match name {
+                        "wait" => response.wait = value,
+                        "line" => response.line_delay = value,
+                        "char" => response.char_delay = value,
+                        _ => return Err(format!("line {}: unknown option @{}", n + 1, name)),
+                    }
+                    continue;
+                }
+                in_options = false;
+            }
+            response.body.push_str(line);
+            response.body.push('\n');
+        }
+        Ok(Self { responses })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{:?}: {}", path, e))?;
+        Self::parse(&text).map_err(|e| format!("{:?}: {}", path, e))
+    }
+
+    pub fn len(&self) -> usize {
+        self.responses.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.responses.is_empty()
+    }
+
+    // Playback for the first response matching the typed line (without the newline)
+    pub fn find(&self, line: &str, rng: &mut Rng) -> Option<Playback> {
+        let line = line.trim();
+        self.responses.iter().find_map(|r| {
+            let caps = r.pattern.captures(line)?;
+            Some(Playback::new(r, expand(&r.body, &caps, rng)))
+        })
+    }
+}
+
+// Output of a response handed out as time passes
+pub struct Playback {
+    // The next bit and the ones after it, each with how long after the previous one it's shown.
+    // The rest is made as it's needed
+    front: Option<(Duration, String)>,
+    rest: Box<dyn Iterator<Item = (Duration, String)>>,
+    next: Instant,
+}
+
+impl Playback {
+    fn new(response: &Response, pieces: Vec<Piece>) -> Self {
+        let mut steps: Box<dyn Iterator<Item = (Duration, String)>> = Box::new(std::iter::empty());
+        // Text steps not chained on yet
+        let mut text_steps = vec![];
+        let mut delay = response.wait;
+        for piece in pieces {
+            let text = match piece {
+                Piece::Pause(pause) => {
+                    delay += pause;
+                    continue;
+                }
+                Piece::Steps(timed) => {
+                    // What's waited for so far goes before the first of them
+                    let wait = std::mem::take(&mut delay);
+                    let timed = timed.enumerate().map(move |(i, (step_delay, text))| (if i == 0 { wait + step_delay } else { step_delay }, text));
+                    steps = Box::new(steps.chain(std::mem::take(&mut text_steps)).chain(timed));
+                    continue;
+                }
+                Piece::Text(text) => text,
+            };
+            let chunks: Vec<String> = if !response.char_delay.is_zero() {
+                text.chars().map(String::from).collect()
+            }
+            else if !response.line_delay.is_zero() {
+                text.split_inclusive('\n').map(str::to_string).collect()
+            }
+            else {
+                vec![text]
+            };
+            for chunk in chunks.into_iter().filter(|c| !c.is_empty()) {
+                let step_delay = if chunk.ends_with('\n') { response.line_delay } else { response.char_delay };
+                text_steps.push((delay, chunk));
+                delay = step_delay;
+            }
+        }
+        Self::from_steps(steps.chain(text_steps))
+    }
+
+    // Text and how long after the previous one it's shown
+    pub fn from_steps(steps: impl IntoIterator<Item = (Duration, String)> + 'static) -> Self {
+        let mut rest = Box::new(steps.into_iter());
+        let front = rest.next();
+        let first = front.as_ref().map_or(Duration::ZERO, |s| s.0);
+        Self { front, rest, next: Instant::now() + first }
+    }
+
+    // Everything that's due by `now`
+    pub fn take_due(&mut self, now: Instant) -> String {
+        let mut text = String::new();
+        while self.next <= now {
+            let (_, chunk) = match self.front.take() {
+                Some(step) => step,
+                None => break,
+            };
+            text.push_str(&chunk);
+            self.front = self.rest.next();
+            if let Some((delay, _)) = &self.front {
+                self.next += *delay;
+            }
+        }
+        text
+    }
+
+    // When the next bit is due, None once it's all out
+    pub fn get_deadline(&self) -> Option<Instant> {
+        self.front.as_ref().map(|_| self.next)
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.front.is_none()
+    }
+}
@@ -1,21 +1,45 @@
+use std::cell::Ref;
+
+use crate::scrollback::{Scrollback, ScrollbackLimit, SharedScrollback};
+
+// What's drawn, the text isn't copied out of Cmd but read from the same scrollback it writes to.
+// An overlay is shown instead of it for as long as it's set, without touching what Cmd wrote
 pub struct Screen {
-    text: String,
+    buffer: SharedScrollback,
+    overlay: Option<SharedScrollback>,
     pub color: u8
 }
 
 impl Screen {
     pub fn new(color: u8) -> Self {
+        Self::from_buffer(Scrollback::new_shared(ScrollbackLimit::Unlimited), color)
+    }
+
+    pub fn from_buffer(buffer: SharedScrollback, color: u8) -> Self {
         Self {
             color,
-            text: "".to_string()
+            buffer,
+            overlay: None,
         }
     }
 
     pub fn set_text(&mut self, string: String) {
-        self.text = string.to_string()
+        let mut buffer = self.buffer.borrow_mut();
+        let base = buffer.get_base();
+        buffer.replace_from(base, &string);
+    }
+
+    // Readers keep working across changing it since marks of one scrollback don't match another
+    pub fn set_overlay(&mut self, overlay: Option<SharedScrollback>) {
+        self.overlay = overlay;
+    }
+
+    pub fn get_text(&self) -> Ref<'_, str> {
+        Ref::map(self.get_buffer(), |b| b.as_str())
     }
 
-    pub fn get_text(&self) -> &str {
-        &self.text
+    // What's shown
+    pub fn get_buffer(&self) -> Ref<'_, Scrollback> {
+        self.overlay.as_ref().unwrap_or(&self.buffer).borrow()
     }
 }
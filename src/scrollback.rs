@@ -0,0 +1,273 @@
+use std::{cell::RefCell, collections::VecDeque, fs::{File, OpenOptions}, io::Write, path::PathBuf, rc::Rc, sync::atomic::{AtomicU64, Ordering}};
+
+// Same as the default screen buffer size of the Windows console
+pub const DEFAULT_SCROLLBACK_LINES: usize = 9001;
+
+// Lines can be endless (a progress bar redrawn with \r forever), so there's a cap on bytes too
+pub const DEFAULT_SCROLLBACK_BYTES: usize = 8 << 20;
+
+// How many edits that aren't appends are remembered for `get_changed_since`
+const EDIT_HISTORY: usize = 256;
+
+// Tells scrollbacks apart so a mark taken on one means everything changed on another
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+// Cmd writes into it and the screen draws straight out of it
+pub type SharedScrollback = Rc<RefCell<Scrollback>>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScrollbackLimit {
+    Lines(usize),
+    Unlimited,
+    // Keeps the given amount of lines in memory, everything older is appended to the file
+    Spill(usize, PathBuf),
+}
+
+impl Default for ScrollbackLimit {
+    fn default() -> Self {
+        ScrollbackLimit::Lines(DEFAULT_SCROLLBACK_LINES)
+    }
+}
+
+// Output history that is trimmed in whole lines, unless a single line is over the byte cap.
+// `line_starts` is a ring of absolute offsets (counted from the very first byte
+// ever pushed) of every line except the first one, so trimming the front
+// never has to touch the offsets that are left. Trimming only moves `head` past the
+// dropped text, the space is given back once there's as much of it as there is text kept,
+// so every byte is moved at most about once however often it's trimmed
+pub struct Scrollback {
+    id: u64,
+    text: String,
+    // Where the kept text starts in `text`
+    head: usize,
+    line_starts: VecDeque<usize>,
+    base: usize,
+    limit: ScrollbackLimit,
+    max_bytes: usize,
+    spill: Option<File>,
+    revision: u64,
+    // (revision, absolute offset) of everything that changed text instead of appending to it
+    edits: VecDeque<(u64, usize)>,
+    forgotten: u64,
+}
+
+// What a reader of the scrollback has seen so far, to find out what changed since
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChangeMark {
+    id: u64,
+    revision: u64,
+    end: usize,
+}
+
+impl Scrollback {
+    pub fn new(limit: ScrollbackLimit) -> Self {
+        Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            text: "".to_string(),
+            head: 0,
+            line_starts: VecDeque::new(),
+            base: 0,
+            limit,
+            max_bytes: DEFAULT_SCROLLBACK_BYTES,
+            spill: None,
+            revision: 0,
+            edits: VecDeque::new(),
+            forgotten: 0,
+        }
+    }
+
+    pub fn new_shared(limit: ScrollbackLimit) -> SharedScrollback {
+        Rc::new(RefCell::new(Self::new(limit)))
+    }
+
+    pub fn get_limit(&self) -> &ScrollbackLimit {
+        &self.limit
+    }
+
+    pub fn set_limit(&mut self, limit: ScrollbackLimit) {
+        self.limit = limit;
+        self.spill = None;
+        self.trim();
+    }
+
+    // Doesn't apply to unlimited scrollback
+    pub fn set_max_bytes(&mut self, max_bytes: usize) {
+        self.max_bytes = max_bytes.max(1);
+        self.trim();
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.text[self.head..]
+    }
+
+    fn len(&self) -> usize {
+        self.text.len() - self.head
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len() + 1
+    }
+
+    // Absolute offset of the first byte still kept
+    pub fn get_base(&self) -> usize {
+        self.base
+    }
+
+    pub fn get_end(&self) -> usize {
+        self.base + self.len()
+    }
+
+    // Absolute offset of the line `offset` is on
+    pub fn get_line_start(&self, offset: usize) -> usize {
+        let line = self.line_starts.partition_point(|&start| start <= offset);
+        if line == 0 { self.base } else { self.line_starts[line - 1] }
+    }
+
+    // Text from the absolute `offset` to the end
+    pub fn get_from(&self, offset: usize) -> &str {
+        &self.text[self.head + offset.max(self.base) - self.base..]
+    }
+
+    pub fn mark(&self) -> ChangeMark {
+        ChangeMark { id: self.id, revision: self.revision, end: self.get_end() }
+    }
+
+    // Absolute offset everything after has to be looked at again since `mark` was taken,
+    // None if nothing changed
+    pub fn get_changed_since(&self, mark: &ChangeMark) -> Option<usize> {
+        if mark.id != self.id {
+            return Some(self.base);
+        }
+        if mark.revision == self.revision {
+            return None;
+        }
+        if self.forgotten > mark.revision {
+            return Some(self.base);
+        }
+        let from = self.edits.iter()
+            .filter(|e| e.0 > mark.revision)
+            .map(|e| e.1)
+            .fold(mark.end, usize::min);
+        Some(from.max(self.base))
+    }
+
+    fn edited(&mut self, from: usize) {
+        self.revision += 1;
+        self.edits.push_back((self.revision, from));
+        if self.edits.len() > EDIT_HISTORY {
+            if let Some((revision, _)) = self.edits.pop_front() {
+                self.forgotten = revision;
+            }
+        }
+    }
+
+    fn push_char(&mut self, c: char) {
+        self.text.push(c);
+        if c == '\n' {
+            self.line_starts.push_back(self.get_end());
+        }
+    }
+
+    pub fn push(&mut self, c: char) {
+        self.push_char(c);
+        self.revision += 1;
+    }
+
+    pub fn push_str(&mut self, s: &str) {
+        for c in s.chars() {
+            self.push_char(c);
+        }
+        self.revision += 1;
+    }
+
+    pub fn pop(&mut self) -> Option<char> {
+        if self.len() == 0 {
+            return None;
+        }
+        let c = self.text.pop()?;
+        if c == '\n' {
+            self.line_starts.pop_back();
+        }
+        self.edited(self.get_end());
+        Some(c)
+    }
+
+    // Replaces everything from the absolute `offset` on, only what actually differs counts as changed
+    pub fn replace_from(&mut self, offset: usize, s: &str) {
+        let old = self.get_from(offset);
+        let same = old.bytes().zip(s.bytes()).take_while(|(a, b)| a == b).count();
+        let same = (0..=same).rev().find(|&i| s.is_char_boundary(i)).unwrap_or(0);
+        let cut = offset.max(self.base) + same;
+
+        if cut < self.get_end() {
+            self.text.truncate(self.head + cut - self.base);
+            while self.line_starts.back().is_some_and(|&start| start > cut) {
+                self.line_starts.pop_back();
+            }
+            self.edited(cut);
+        }
+        self.push_str(&s[same..]);
+    }
+
+    pub fn clear(&mut self) {
+        self.base += self.len();
+        self.text.clear();
+        self.head = 0;
+        self.line_starts.clear();
+        self.revision += 1;
+    }
+
+    // Drops the oldest lines once there are noticeably more than the limit allows,
+    // the slack keeps the cost of trimming amortized over many lines
+    pub fn trim(&mut self) {
+        let keep = match self.limit {
+            ScrollbackLimit::Lines(n) | ScrollbackLimit::Spill(n, _) => n.max(1),
+            ScrollbackLimit::Unlimited => return,
+        };
+
+        let mut cut = 0;
+        if self.line_count() > keep + keep / 8 {
+            cut = self.line_starts[self.line_count() - keep - 1] - self.base;
+        }
+        // Whole lines while that's enough, the middle of one that alone is too long
+        if self.len() - cut > self.max_bytes + self.max_bytes / 8 {
+            let at = self.line_starts.partition_point(|&start| self.get_end() - start > self.max_bytes);
+            let mut bytes_cut = match self.line_starts.get(at) {
+                Some(&start) => start - self.base,
+                None => self.len() - self.max_bytes,
+            };
+            while !self.text.is_char_boundary(self.head + bytes_cut) {
+                bytes_cut += 1;
+            }
+            cut = cut.max(bytes_cut);
+        }
+        if cut == 0 {
+            return;
+        }
+
+        if let ScrollbackLimit::Spill(_, ref path) = self.limit {
+            if self.spill.is_none() {
+                match OpenOptions::new().create(true).append(true).open(path) {
+                    Ok(file) => self.spill = Some(file),
+                    Err(e) => eprintln!("{}: Could not open scrollback spill file {:?}: {}", line!(), path, e),
+                }
+            }
+            if let Some(ref mut file) = self.spill {
+                if let Err(e) = file.write_all(&self.text.as_bytes()[self.head..self.head + cut]) {
+                    eprintln!("{}: Could not spill scrollback: {}", line!(), e);
+                }
+            }
+        }
+
+        self.head += cut;
+        self.base += cut;
+        while self.line_starts.front().is_some_and(|&start| start <= self.base) {
+            self.line_starts.pop_front();
+        }
+        if self.head > self.len() {
+            self.text.drain(..self.head);
+            self.head = 0;
+        }
+        self.revision += 1;
+    }
+}
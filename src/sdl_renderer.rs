@@ -0,0 +1,60 @@
+use sdl2::render::{Canvas, RenderTarget, Texture};
+
+use wcmd::font::Font;
+use wcmd::render::{Color, Rect, Renderer};
+
+fn to_sdl_rect(rect: Rect) -> sdl2::rect::Rect {
+    sdl2::rect::Rect::new(rect.x, rect.y, rect.w, rect.h)
+}
+
+fn to_sdl_color(color: Color) -> sdl2::pixels::Color {
+    sdl2::pixels::Color::RGB(color.r, color.g, color.b)
+}
+
+pub fn get_glyph_rect_sdl(font: &Font, chr: char) -> sdl2::rect::Rect {
+    let rect = font.get_glyph_rect(chr);
+    sdl2::rect::Rect::new(rect.0 as i32, rect.1 as i32, rect.2 as u32, rect.3 as u32)
+}
+
+// Draws with an SDL canvas, glyphs are copied from the font texture with its color mod set
+pub struct SdlRenderer<'a, T: RenderTarget> {
+    pub canvas: Canvas<T>,
+    font_texture: Texture<'a>,
+    font: Font,
+}
+
+impl<'a, T: RenderTarget> SdlRenderer<'a, T> {
+    pub fn new(canvas: Canvas<T>, font_texture: Texture<'a>, font: Font) -> Self {
+        Self { canvas, font_texture, font }
+    }
+
+    pub fn set_font_texture(&mut self, tex: Texture<'a>) {
+        self.font_texture = tex;
+    }
+}
+
+impl<'a, T: RenderTarget> Renderer for SdlRenderer<'a, T> {
+    fn get_size(&self) -> (u32, u32) {
+        self.canvas.output_size().unwrap()
+    }
+
+    fn fill_rect(&mut self, rect: Rect, color: Color) {
+        self.canvas.set_draw_color(to_sdl_color(color));
+        self.canvas.fill_rect(to_sdl_rect(rect)).unwrap();
+    }
+
+    fn draw_cell_run(&mut self, x: i32, y: i32, glyphs: &str, color: Color) {
+        self.font_texture.set_color_mod(color.r, color.g, color.b);
+        let mut gx = x;
+        for glyph in glyphs.chars() {
+            let rect = get_glyph_rect_sdl(&self.font, glyph);
+            let dest = sdl2::rect::Rect::new(gx, y, rect.width(), rect.height());
+            self.canvas.copy(&self.font_texture, Some(rect), Some(dest)).unwrap();
+            gx += self.font.glyph_size.0 as i32;
+        }
+    }
+
+    fn present(&mut self) {
+        self.canvas.present();
+    }
+}
@@ -0,0 +1,188 @@
+use regex::Regex;
+
+use crate::scrollback::{ChangeMark, Scrollback};
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum SearchMode {
+    Plain,
+    Regex
+}
+
+// Scrollback search state, matches are byte ranges into the screen text
+pub struct Search {
+    query: String,
+    mode: SearchMode,
+    matches: Vec<(usize, usize)>,
+    current: Option<usize>,
+    error: Option<String>,
+    // Absolute offset of the scrollback's first byte when the matches were found
+    base: usize,
+    mark: Option<ChangeMark>,
+}
+
+impl Default for Search {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Search {
+    pub fn new() -> Self {
+        Self {
+            query: "".to_string(),
+            mode: SearchMode::Plain,
+            matches: vec![],
+            current: None,
+            error: None,
+            base: 0,
+            mark: None,
+        }
+    }
+
+    pub fn get_query(&self) -> &str {
+        self.query.as_str()
+    }
+
+    pub fn get_mode(&self) -> SearchMode {
+        self.mode
+    }
+
+    pub fn get_matches(&self) -> &[(usize, usize)] {
+        self.matches.as_slice()
+    }
+
+    pub fn get_current(&self) -> Option<(usize, usize)> {
+        self.current.map(|i| self.matches[i])
+    }
+
+    pub fn push(&mut self, c: char, buffer: &Scrollback) {
+        self.query.push(c);
+        self.current = None;
+        self.update(buffer);
+    }
+
+    pub fn pop(&mut self, buffer: &Scrollback) {
+        if self.query.pop().is_some() {
+            self.current = None;
+            self.update(buffer);
+        }
+    }
+
+    pub fn toggle_mode(&mut self, buffer: &Scrollback) {
+        self.mode = match self.mode {
+            SearchMode::Plain => SearchMode::Regex,
+            SearchMode::Regex => SearchMode::Plain,
+        };
+        self.current = None;
+        self.update(buffer);
+    }
+
+    // Searches all of it again, for when the query changed
+    pub fn update(&mut self, buffer: &Scrollback) {
+        self.matches.clear();
+        self.base = buffer.get_base();
+        self.mark = Some(buffer.mark());
+        self.search_from(buffer.as_str(), 0);
+    }
+
+    // Catches up with whatever changed since the matches were found
+    pub fn update_changed(&mut self, buffer: &Scrollback) {
+        match self.mark.map(|mark| buffer.get_changed_since(&mark)) {
+            Some(Some(from)) if from > buffer.get_base() => self.update_from(buffer, from),
+            Some(None) => (),
+            _ => self.update(buffer),
+        }
+    }
+
+    // Catches up with output that changed from the absolute offset `from` on. Only the
+    // lines from there are searched again, matches that were trimmed off the front are dropped
+    pub fn update_from(&mut self, buffer: &Scrollback, from: usize) {
+        let trimmed = buffer.get_base().saturating_sub(self.base);
+        self.base = buffer.get_base();
+        self.mark = Some(buffer.mark());
+        let dropped = self.matches.partition_point(|m| m.0 < trimmed);
+        self.matches.drain(..dropped);
+        for m in &mut self.matches {
+            *m = (m.0 - trimmed, m.1 - trimmed);
+        }
+        self.current = match self.current {
+            Some(i) if i >= dropped => Some(i - dropped),
+            // The one that was selected is gone, the first one left is the closest
+            Some(_) => Some(0),
+            None => None,
+        };
+
+        // Whole lines so matches are found the same way they are when searching everything
+        let mut start = buffer.get_line_start(from.max(self.base)) - self.base;
+        while self.matches.last().is_some_and(|m| m.1 > start) {
+            start = start.min(self.matches.pop().map_or(start, |m| m.0));
+        }
+        self.search_from(buffer.as_str(), start);
+    }
+
+    fn search_from(&mut self, text: &str, start: usize) {
+        self.error = None;
+
+        if !self.query.is_empty() {
+            match self.mode {
+                SearchMode::Plain => {
+                    self.matches.extend(text[start..].match_indices(self.query.as_str()).map(|(i, m)| (start + i, start + i + m.len())));
+                }
+                SearchMode::Regex => {
+                    match Regex::new(&self.query) {
+                        // Empty matches can't be highlighted nor jumped to so we skip them.
+                        // Searching from `start` in the whole text keeps anchors working
+                        Ok(re) => {
+                            let mut at = start;
+                            while let Some(m) = re.find_at(text, at) {
+                                if m.start() != m.end() {
+                                    self.matches.push((m.start(), m.end()));
+                                }
+                                at = match text[m.end()..].chars().next() {
+                                    _ if m.end() > m.start() => m.end(),
+                                    Some(c) => m.end() + c.len_utf8(),
+                                    None => break,
+                                };
+                            }
+                        }
+                        Err(e) => self.error = Some(e.to_string().lines().last().unwrap_or("invalid regex").trim().to_string()),
+                    }
+                }
+            }
+        }
+
+        self.current = if self.matches.is_empty() {
+            None
+        }
+        else {
+            // Fresh queries start from the most recent output
+            Some(self.current.unwrap_or(self.matches.len() - 1).min(self.matches.len() - 1))
+        };
+    }
+
+    pub fn next_match(&mut self) -> Option<(usize, usize)> {
+        if !self.matches.is_empty() {
+            self.current = Some(self.current.map_or(0, |i| (i + 1) % self.matches.len()));
+        }
+        self.get_current()
+    }
+
+    pub fn prev_match(&mut self) -> Option<(usize, usize)> {
+        if !self.matches.is_empty() {
+            let len = self.matches.len();
+            self.current = Some(self.current.map_or(len - 1, |i| (i + len - 1) % len));
+        }
+        self.get_current()
+    }
+
+    pub fn status(&self) -> String {
+        if let Some(ref e) = self.error {
+            return e.clone();
+        }
+        match self.current {
+            Some(i) => format!("{}/{}", i + 1, self.matches.len()),
+            None if self.query.is_empty() => "".to_string(),
+            None => "No matches".to_string(),
+        }
+    }
+}
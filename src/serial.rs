@@ -0,0 +1,255 @@
+use std::path::PathBuf;
+
+#[cfg(unix)]
+pub use self::unix::Serial;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Parity {
+    None,
+    Even,
+    Odd,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlowControl {
+    None,
+    // RTS/CTS
+    Hardware,
+    // XON/XOFF
+    Software,
+}
+
+// What Enter sends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    Cr,
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    pub fn as_bytes(&self) -> &'static [u8] {
+        match self {
+            LineEnding::Cr => b"\r",
+            LineEnding::Lf => b"\n",
+            LineEnding::CrLf => b"\r\n",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SerialConfig {
+    pub path: PathBuf,
+    pub baud: u32,
+    pub data_bits: u8,
+    pub parity: Parity,
+    pub stop_bits: u8,
+    pub flow: FlowControl,
+    // Typed lines are shown here and sent when Enter is pressed, for devices that don't echo
+    pub local_echo: bool,
+    pub eol: LineEnding,
+}
+
+impl SerialConfig {
+    // 8N1 without flow control, what most boards talk. Their shells echo
+    // every key and want a carriage return like a terminal sends
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            baud: 115200,
+            data_bits: 8,
+            parity: Parity::None,
+            stop_bits: 1,
+            flow: FlowControl::None,
+            local_echo: false,
+            eol: LineEnding::Cr,
+        }
+    }
+}
+
+// Only implemented with termios for now
+#[cfg(unix)]
+mod unix {
+    use std::{
+        fs::{File, OpenOptions},
+        io::{Read, Write},
+        os::unix::{fs::OpenOptionsExt, io::AsRawFd},
+        sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex},
+        thread::{self, JoinHandle},
+    };
+
+    use super::{FlowControl, LineEnding, Parity, SerialConfig};
+    use crate::backend::{Backend, Signal};
+    use crate::wakeup::Wakeup;
+
+    fn baud_constant(baud: u32) -> Option<libc::speed_t> {
+        Some(match baud {
+            1200 => libc::B1200,
+            2400 => libc::B2400,
+            4800 => libc::B4800,
+            9600 => libc::B9600,
+            19200 => libc::B19200,
+            38400 => libc::B38400,
+            57600 => libc::B57600,
+            115200 => libc::B115200,
+            230400 => libc::B230400,
+            #[cfg(target_os = "linux")]
+            460800 => libc::B460800,
+            #[cfg(target_os = "linux")]
+            921600 => libc::B921600,
+            _ => return None,
+        })
+    }
+
+    fn configure(file: &File, config: &SerialConfig) -> std::io::Result<()> {
+        let invalid = |what: String| std::io::Error::new(std::io::ErrorKind::InvalidInput, what);
+        let speed = baud_constant(config.baud).ok_or_else(|| invalid(format!("unsupported baud rate {}", config.baud)))?;
+        let size = match config.data_bits {
+            5 => libc::CS5,
+            6 => libc::CS6,
+            7 => libc::CS7,
+            8 => libc::CS8,
+            bits => return Err(invalid(format!("unsupported number of data bits {}", bits))),
+        };
+
+        let fd = file.as_raw_fd();
+        unsafe {
+            let mut termios: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(fd, &mut termios) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            libc::cfmakeraw(&mut termios);
+            libc::cfsetispeed(&mut termios, speed);
+            libc::cfsetospeed(&mut termios, speed);
+
+            termios.c_cflag &= !(libc::CSIZE | libc::PARENB | libc::PARODD | libc::CSTOPB | libc::CRTSCTS);
+            termios.c_cflag |= size | libc::CLOCAL | libc::CREAD;
+            match config.parity {
+                Parity::None => (),
+                Parity::Even => termios.c_cflag |= libc::PARENB,
+                Parity::Odd => termios.c_cflag |= libc::PARENB | libc::PARODD,
+            }
+            if config.stop_bits == 2 {
+                termios.c_cflag |= libc::CSTOPB;
+            }
+            termios.c_iflag &= !(libc::IXON | libc::IXOFF | libc::IXANY);
+            match config.flow {
+                FlowControl::None => (),
+                FlowControl::Hardware => termios.c_cflag |= libc::CRTSCTS,
+                FlowControl::Software => termios.c_iflag |= libc::IXON | libc::IXOFF,
+            }
+
+            // Reads give up after a tenth of a second so the reader can notice it should stop
+            termios.c_cc[libc::VMIN] = 0;
+            termios.c_cc[libc::VTIME] = 1;
+
+            if libc::tcsetattr(fd, libc::TCSANOW, &termios) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    // A serial device used like a child process, it "exits" when the device goes away or it's killed
+    pub struct Serial {
+        port: File,
+        output: Arc<Mutex<Vec<u8>>>,
+        reader: Option<JoinHandle<()>>,
+        stop: Arc<AtomicBool>,
+        wakeup: Arc<Mutex<Option<Wakeup>>>,
+        local_echo: bool,
+        eol: LineEnding,
+    }
+
+    impl Serial {
+        pub fn open(config: &SerialConfig) -> std::io::Result<Self> {
+            let port = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .custom_flags(libc::O_NOCTTY)
+                .open(&config.path)
+                .map_err(|e| std::io::Error::new(e.kind(), format!("{:?}: {}", config.path, e)))?;
+            configure(&port, config)?;
+
+            let output = Arc::new(Mutex::new(Vec::new()));
+            let stop = Arc::new(AtomicBool::new(false));
+            let wakeup: Arc<Mutex<Option<Wakeup>>> = Arc::new(Mutex::new(None));
+            let reader = {
+                let mut port = port.try_clone()?;
+                let (output, stop, wakeup) = (output.clone(), stop.clone(), wakeup.clone());
+                thread::spawn(move || {
+                    let mut buf = [0; 4096];
+                    while !stop.load(Ordering::Acquire) {
+                        match port.read(&mut buf) {
+                            // Timed out
+                            Ok(0) => continue,
+                            Ok(n) => output.lock().expect("Mutex lock poisoned").extend_from_slice(&buf[..n]),
+                            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                            Err(e) => {
+                                eprintln!("{}: Serial port error: {}", line!(), e);
+                                break;
+                            }
+                        }
+                        if let Some(ref wakeup) = *wakeup.lock().expect("Mutex lock poisoned") {
+                            wakeup.wake();
+                        }
+                    }
+                    if let Some(ref wakeup) = *wakeup.lock().expect("Mutex lock poisoned") {
+                        wakeup.wake();
+                    }
+                })
+            };
+
+            Ok(Self { port, output, reader: Some(reader), stop, wakeup, local_echo: config.local_echo, eol: config.eol })
+        }
+    }
+
+    impl Backend for Serial {
+        // Enter comes as \n, the device gets its own line ending
+        fn write_input(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+            for (i, line) in bytes.split(|&b| b == b'\n').enumerate() {
+                if i > 0 {
+                    self.port.write_all(self.eol.as_bytes())?;
+                }
+                self.port.write_all(line)?;
+            }
+            self.port.flush()
+        }
+
+        fn read_output(&mut self) -> Vec<u8> {
+            std::mem::take(&mut *self.output.lock().expect("Mutex lock poisoned"))
+        }
+
+        // Ctrl+C is just a byte on the wire, the rest closes the port
+        fn signal(&mut self, signal: Signal) -> std::io::Result<()> {
+            match signal {
+                Signal::Interrupt => self.write_input(b"\x03"),
+                Signal::Terminate | Signal::Kill => {
+                    self.stop.store(true, Ordering::Release);
+                    Ok(())
+                }
+            }
+        }
+
+        fn is_exited(&mut self) -> bool {
+            self.reader.as_ref().is_none_or(|r| r.is_finished())
+        }
+
+        fn set_wakeup(&mut self, wakeup: Option<Wakeup>) {
+            *self.wakeup.lock().expect("Mutex lock poisoned") = wakeup;
+        }
+
+        fn is_remote_echo(&self) -> bool {
+            !self.local_echo
+        }
+    }
+
+    impl Drop for Serial {
+        fn drop(&mut self) {
+            self.stop.store(true, Ordering::Release);
+            if let Some(reader) = self.reader.take() {
+                let _ = reader.join();
+            }
+        }
+    }
+}
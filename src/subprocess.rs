@@ -1,15 +1,23 @@
-use std::{env::Args, io::Write, path::PathBuf, process::{Child, ChildStderr, ChildStdin, ChildStdout}};
+use std::{io::Write, path::PathBuf, process::{Child, ChildStderr, ChildStdin, ChildStdout}};
 use std::{
     io::Read,
     process::{Command, Stdio},
     sync::{Arc, Mutex},
-    thread,
+    thread::{self, JoinHandle},
 };
 
+use crate::backend::{Backend, Signal};
+use crate::wakeup::Wakeup;
+
+// Set later than the reader threads are started, so they look it up every time
+pub(crate) type WakeupSlot = Arc<Mutex<Option<Wakeup>>>;
+
 pub struct SubProcess {
     child: Child,
     stdout: Arc<Mutex<Vec<u8>>>,
     stderr: Arc<Mutex<Vec<u8>>>,
+    readers: Vec<JoinHandle<()>>,
+    wakeup: WakeupSlot,
 }
 
 fn handle_byte<S: Read + Send + 'static>(stream: &mut S, vec: &Arc<Mutex<Vec<u8>>>) -> bool {
@@ -32,15 +40,21 @@ fn handle_byte<S: Read + Send + 'static>(stream: &mut S, vec: &Arc<Mutex<Vec<u8>
 
 // Credit to
 // https://www.javaer101.com/es/article/20362830.html
-fn child_non_blocking_stream<S: Read + Send + 'static>(mut stream: S) -> Arc<Mutex<Vec<u8>>> {
+pub(crate) fn child_non_blocking_stream<S: Read + Send + 'static>(mut stream: S, wakeup: &WakeupSlot) -> (Arc<Mutex<Vec<u8>>>, JoinHandle<()>) {
     let res = Arc::new(Mutex::new(Vec::new()));
     let vec = res.clone();
-    thread::spawn(move || loop {
-        if handle_byte(&mut stream, &vec) {
+    let wakeup = wakeup.clone();
+    let reader = thread::spawn(move || loop {
+        let done = handle_byte(&mut stream, &vec);
+        // Also wake on EOF so the frontend notices the child going away
+        if let Some(wakeup) = &*wakeup.lock().expect("Mutex lock poisoned") {
+            wakeup.wake();
+        }
+        if done {
             break;
         }
     });
-    res
+    (res, reader)
 }
 
 #[cfg(target_os="windows")]
@@ -69,68 +83,81 @@ fn subcommand(cmd: &str) -> Option<Child> {
         .ok()
 }
 
+#[cfg(target_os="windows")]
 fn subcommand_from_args(cmd: &str, args: &[String]) -> Option<Child> {
-    if cfg!(target_os = "windows") {
-        use std::os::windows::process::CommandExt;
-        const DONT_CREATE_WINDOW: u32 = 0x08000000;
-        Command::new(cmd)
-            .creation_flags(DONT_CREATE_WINDOW)
-            .args(args)
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()
-            .ok()
-    }
-    else {
-        return None;
-    }
+    use std::os::windows::process::CommandExt;
+    const DONT_CREATE_WINDOW: u32 = 0x08000000;
+    Command::new(cmd)
+        .creation_flags(DONT_CREATE_WINDOW)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .ok()
+}
+
+#[cfg(not(target_os="windows"))]
+fn subcommand_from_args(cmd: &str, args: &[String]) -> Option<Child> {
+    Command::new(cmd)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .ok()
 }
 
 impl SubProcess {
-    pub fn from_args(mut args: Args) -> Option<Self> {
-        args.next();
-        let mut cmd = args.next().unwrap_or("".to_string());
+    // The first element is the program to run, the rest are its arguments
+    pub fn from_args(command: &[String]) -> Option<Self> {
+        let (cmd, args) = command.split_first()?;
+        let mut cmd = cmd.clone();
         if cmd == "cmd" { 
             cmd = "real_cmd.exe".to_string();
         }
         else if cmd.to_ascii_lowercase().contains("c:\\windows\\system32\\cmd") {
             cmd = "real_cmd.exe".to_string()
         }
-        let args = args.collect::<Vec<_>>();
         dbg!(&cmd, &args);
-        let mut child = subcommand_from_args(&cmd, args.as_slice())?;
-        Some(Self {
-            stderr: child_non_blocking_stream(child.stderr.take()?),
-            stdout: child_non_blocking_stream(child.stdout.take()?),
-            child: child,
-        })
-    }
-
-    pub fn from_cmd(cmd: &str) -> Option<Self> {
-        let r = subcommand(cmd);
-        let mut child = r?;
-        Some(Self {
-            stderr: child_non_blocking_stream(child.stderr.take()?),
-            stdout: child_non_blocking_stream(child.stdout.take()?),
-            child: child,
-        })
+        Self::from_child(subcommand_from_args(&cmd, args)?)
     }
 
-    pub fn kill(&mut self) {
-        self.child.kill();
+    // Like from_args but started in `dir` with `vars` as its whole environment
+    pub fn from_args_in<'a>(command: &[String], dir: &std::path::Path, vars: impl Iterator<Item = (&'a str, &'a str)>) -> Option<Self> {
+        let (cmd, args) = command.split_first()?;
+        let mut command = Command::new(cmd);
+        command.args(args)
+            .current_dir(dir)
+            .env_clear()
+            .envs(vars)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        #[cfg(target_os="windows")]
+        {
+            use std::os::windows::process::CommandExt;
+            const DONT_CREATE_WINDOW: u32 = 0x08000000;
+            command.creation_flags(DONT_CREATE_WINDOW);
+        }
+        Self::from_child(command.spawn().ok()?)
     }
 
-    pub fn is_dead(&mut self) -> bool {
-        self.child.try_wait().map(|e| e.is_some()).unwrap_or(true)
+    fn from_child(mut child: Child) -> Option<Self> {
+        let wakeup = Arc::new(Mutex::new(None));
+        let (stderr, stderr_reader) = child_non_blocking_stream(child.stderr.take()?, &wakeup);
+        let (stdout, stdout_reader) = child_non_blocking_stream(child.stdout.take()?, &wakeup);
+        Some(Self {
+            stderr,
+            stdout,
+            readers: vec![stderr_reader, stdout_reader],
+            child,
+            wakeup,
+        })
     }
 
-    pub fn write_stdin(&mut self, byte: &[u8]) {
-        let s = self.child.stdin.take();
-        if let Some(mut stdin) = s {
-            stdin.write(&byte);
-            self.child.stdin = Some(stdin);
-        }
+    pub fn from_cmd(cmd: &str) -> Option<Self> {
+        Self::from_child(subcommand(cmd)?)
     }
 
     pub fn get_bytes_stdout(&mut self) -> Vec<u8> {
@@ -145,3 +172,59 @@ impl SubProcess {
         delta
     }
 }
+
+#[cfg(unix)]
+pub(crate) fn send_signal(child: &mut Child, signal: Signal) -> std::io::Result<()> {
+    let signal = match signal {
+        Signal::Interrupt => libc::SIGINT,
+        Signal::Terminate => libc::SIGTERM,
+        Signal::Kill => return child.kill(),
+    };
+    if unsafe { libc::kill(child.id() as libc::pid_t, signal) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+// There's no console to send Ctrl+C through, so only killing works
+#[cfg(not(unix))]
+pub(crate) fn send_signal(child: &mut Child, signal: Signal) -> std::io::Result<()> {
+    match signal {
+        Signal::Interrupt => Err(std::io::ErrorKind::Unsupported.into()),
+        Signal::Terminate | Signal::Kill => child.kill(),
+    }
+}
+
+impl Backend for SubProcess {
+    fn write_input(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        match self.child.stdin {
+            Some(ref mut stdin) => {
+                stdin.write_all(bytes)?;
+                stdin.flush()
+            }
+            None => Err(std::io::ErrorKind::BrokenPipe.into()),
+        }
+    }
+
+    // Errors come first like they always did
+    fn read_output(&mut self) -> Vec<u8> {
+        let mut output = self.get_bytes_stderr();
+        output.extend(self.get_bytes_stdout());
+        output
+    }
+
+    fn signal(&mut self, signal: Signal) -> std::io::Result<()> {
+        send_signal(&mut self.child, signal)
+    }
+
+    // The child only counts as dead once everything it wrote has been read
+    fn is_exited(&mut self) -> bool {
+        self.child.try_wait().map(|e| e.is_some()).unwrap_or(true)
+            && self.readers.iter().all(|r| r.is_finished())
+    }
+
+    // Called from the reader threads whenever there's new output
+    fn set_wakeup(&mut self, wakeup: Option<Wakeup>) {
+        *self.wakeup.lock().expect("Mutex lock poisoned") = wakeup;
+    }
+}
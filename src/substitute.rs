@@ -0,0 +1,345 @@
+use std::{path::{Path, PathBuf}, time::SystemTime};
+
+use regex::{Captures, Regex, RegexBuilder};
+
+use crate::scrollback::{ChangeMark, Scrollback, ScrollbackLimit, SharedScrollback};
+
+// What the substitute key always did, used when there's no rules file
+pub const DEFAULT_RULES: &str = "\
+Foreign => Trusted
+ESTABLISHED => SECURE
+[case] refund => airplane
+";
+
+// One line of a rules file:
+//
+//     [options] pattern => replacement
+//
+// Options are optional and comma separated: `regex` makes the pattern a regular
+// expression (the replacement can use $1 and such), `case` matches regardless of case
+// and makes the replacement look like what it replaced (REFUND, Refund, refund),
+// `cmd=name` only applies it to the output of commands with that name (repeatable).
+// Empty lines and lines starting with # are skipped
+pub struct Rule {
+    pattern: Regex,
+    replacement: String,
+    expand: bool,
+    preserve_case: bool,
+    commands: Vec<String>,
+    // The line it was parsed from, to write it out again
+    source: String,
+}
+
+// The first word of a command line, lowercase and without .exe so `NETSTAT.EXE -an` is `netstat`
+fn command_name(line: &str) -> String {
+    let name = line.split_whitespace().next().unwrap_or("").to_lowercase();
+    name.strip_suffix(".exe").map(str::to_string).unwrap_or(name)
+}
+
+// Makes `replacement` follow the case of `matched`
+fn match_case(matched: &str, replacement: &str) -> String {
+    let mut letters = matched.chars().filter(|c| c.is_alphabetic());
+    let first = match letters.next() {
+        Some(c) => c,
+        None => return replacement.to_string(),
+    };
+    let rest: Vec<char> = letters.collect();
+    if first.is_uppercase() && rest.iter().all(|c| c.is_uppercase()) && !rest.is_empty() {
+        replacement.to_uppercase()
+    }
+    else if rest.iter().all(|c| c.is_lowercase()) {
+        let lower = replacement.to_lowercase();
+        if first.is_uppercase() {
+            let mut chars = lower.chars();
+            chars.next().map(|c| c.to_uppercase().chain(chars).collect()).unwrap_or_default()
+        }
+        else {
+            lower
+        }
+    }
+    else {
+        replacement.to_string()
+    }
+}
+
+impl Rule {
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let source = line.trim().to_string();
+        let mut line = source.as_str();
+        let (mut regex, mut preserve_case, mut commands) = (false, false, vec![]);
+        if let Some(rest) = line.strip_prefix('[') {
+            let end = rest.find(']').ok_or("missing ] after the options")?;
+            for option in rest[..end].split(',').map(str::trim).filter(|o| !o.is_empty()) {
+                match option {
+                    "regex" => regex = true,
+                    "case" => preserve_case = true,
+                    _ => match option.strip_prefix("cmd=") {
+                        Some(name) => commands.push(command_name(name)),
+                        None => return Err(format!("unknown option: {}", option)),
+                    },
+                }
+            }
+            line = &rest[end + 1..];
+        }
+
+        let (pattern, replacement) = line.split_once("=>").ok_or("expected pattern => replacement")?;
+        let (pattern, replacement) = (pattern.trim(), replacement.trim());
+        if pattern.is_empty() {
+            return Err("empty pattern".to_string());
+        }
+        let escaped = if regex { pattern.to_string() } else { regex::escape(pattern) };
+        let pattern = RegexBuilder::new(&escaped)
+            .case_insensitive(preserve_case)
+            .build()
+            .map_err(|e| e.to_string())?;
+
+        Ok(Self { pattern, replacement: replacement.to_string(), expand: regex, preserve_case, commands, source: source.clone() })
+    }
+
+    pub fn get_source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn applies_to(&self, command: &str) -> bool {
+        self.commands.is_empty() || self.commands.iter().any(|c| c == command)
+    }
+
+    pub fn apply(&self, text: &str) -> String {
+        self.pattern.replace_all(text, |caps: &Captures| {
+            let mut replacement = String::new();
+            if self.expand {
+                caps.expand(&self.replacement, &mut replacement);
+            }
+            else {
+                replacement.push_str(&self.replacement);
+            }
+            if self.preserve_case { match_case(&caps[0], &replacement) } else { replacement }
+        }).into_owned()
+    }
+}
+
+pub struct Rules {
+    rules: Vec<Rule>,
+    // Where they were loaded from and when it was last changed, to reload it when it changes again
+    path: Option<PathBuf>,
+    modified: Option<SystemTime>,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self::parse(DEFAULT_RULES).expect("default rules are valid")
+    }
+}
+
+impl Rules {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut rules = vec![];
+        for (n, line) in text.lines().enumerate() {
+            if line.trim().is_empty() || line.trim_start().starts_with('#') {
+                continue;
+            }
+            rules.push(Rule::parse(line).map_err(|e| format!("line {}: {}", n + 1, e))?);
+        }
+        Ok(Self { rules, path: None, modified: None })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let modified = std::fs::metadata(path).and_then(|m| m.modified()).ok();
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{:?}: {}", path, e))?;
+        let mut rules = Self::parse(&text).map_err(|e| format!("{:?}: {}", path, e))?;
+        rules.path = Some(path.to_path_buf());
+        rules.modified = modified;
+        Ok(rules)
+    }
+
+    // Added rules are gone again if the file is reloaded
+    pub fn push(&mut self, rule: Rule) {
+        self.rules.push(rule);
+    }
+
+    pub fn get_path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    // Watches `path` for changes from now on, without reading it
+    pub fn set_path(&mut self, path: &Path) {
+        self.modified = std::fs::metadata(path).and_then(|m| m.modified()).ok();
+        self.path = Some(path.to_path_buf());
+    }
+
+    // Parses back into the same rules, added ones included
+    pub fn to_text(&self) -> String {
+        self.rules.iter().map(|r| format!("{}\n", r.get_source())).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    // Reads the file again if it changed since it was loaded, returns if the rules changed.
+    // A broken file keeps the old rules so a half saved edit doesn't drop them all
+    pub fn reload_if_changed(&mut self) -> bool {
+        let path = match self.path {
+            Some(ref path) => path.clone(),
+            None => return false,
+        };
+        let modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok();
+        if modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+        match Self::load(&path) {
+            Ok(rules) => {
+                *self = rules;
+                true
+            }
+            Err(e) => {
+                eprintln!("{}: Could not reload substitutions: {}", line!(), e);
+                false
+            }
+        }
+    }
+
+    pub fn apply(&self, text: &str, command: &str) -> String {
+        let mut text = text.to_string();
+        for rule in self.rules.iter().filter(|r| r.applies_to(command)) {
+            text = rule.apply(&text);
+        }
+        text
+    }
+}
+
+// Keeps a copy of the output with the rules applied to what arrived after `start`, the
+// output itself is never changed so stopping shows it like it was. Finished lines are never
+// looked at again so nothing gets replaced twice, the line still being written is kept as it
+// originally was and replaced as a whole every time so words split between reads are still found
+pub struct Substituter {
+    pub rules: Rules,
+    command: String,
+    display: Option<SharedScrollback>,
+    mark: Option<ChangeMark>,
+    // Absolute offset in the output rules apply from
+    from: usize,
+    // Absolute offset of the unfinished line in the output, its text and where it starts in the copy
+    line_start: usize,
+    line: String,
+    display_line_start: usize,
+    written_end: usize,
+}
+
+impl Substituter {
+    pub fn new(rules: Rules) -> Self {
+        Self {
+            rules,
+            command: String::new(),
+            display: None,
+            mark: None,
+            from: 0,
+            line_start: 0,
+            line: String::new(),
+            display_line_start: 0,
+            written_end: 0,
+        }
+    }
+
+    // The line that was just sent to the child, scoped rules apply to what it prints
+    pub fn set_command(&mut self, line: &str) {
+        self.command = command_name(line);
+    }
+
+    pub fn get_command(&self) -> &str {
+        &self.command
+    }
+
+    // What to show instead of the output, None until it's started
+    pub fn get_display(&self) -> Option<SharedScrollback> {
+        self.display.clone()
+    }
+
+    // Copies what's in the buffer now as it is, whatever comes after gets replaced
+    pub fn start(&mut self, buffer: &Scrollback) {
+        self.from = buffer.get_end();
+        self.display = Some(Scrollback::new_shared(ScrollbackLimit::Unlimited));
+        self.copy(buffer);
+    }
+
+    pub fn stop(&mut self) {
+        self.display = None;
+        self.mark = None;
+    }
+
+    // Starts the copy over from what's in the buffer, only what's after `from` is replaced
+    fn copy(&mut self, buffer: &Scrollback) {
+        let display = match self.display {
+            Some(ref display) => display.clone(),
+            None => return,
+        };
+        // Spilling is for the output itself
+        let limit = match buffer.get_limit() {
+            ScrollbackLimit::Spill(lines, _) => ScrollbackLimit::Lines(*lines),
+            limit => limit.clone(),
+        };
+        let mut display = display.borrow_mut();
+        *display = Scrollback::new(limit);
+
+        let start = self.from.max(buffer.get_base());
+        display.push_str(&buffer.as_str()[..start - buffer.get_base()]);
+        self.line_start = start;
+        self.line = buffer.get_from(start).to_string();
+        self.display_line_start = display.get_end();
+        self.write(&mut display);
+        self.written_end = buffer.get_end();
+        self.mark = Some(buffer.mark());
+    }
+
+    // Applies the rules to everything written to the buffer since the last call
+    pub fn update(&mut self, buffer: &Scrollback) {
+        self.rules.reload_if_changed();
+        let (display, mark) = match (&self.display, self.mark) {
+            (Some(display), Some(mark)) => (display.clone(), mark),
+            _ => return self.start(buffer),
+        };
+
+        let from = match buffer.get_changed_since(&mark) {
+            Some(from) => from,
+            None => return,
+        };
+        if self.line_start < buffer.get_base() || from < self.line_start {
+            // Something changed what's already done (clearing, trimming, backspace over a line break)
+            return self.copy(buffer);
+        }
+        if from == self.written_end {
+            self.line.push_str(buffer.get_from(from));
+        }
+        else {
+            // The unfinished line was changed (backspace, carriage return), it's taken as it is now
+            self.line = buffer.get_from(self.line_start).to_string();
+        }
+
+        self.write(&mut display.borrow_mut());
+        self.written_end = buffer.get_end();
+        self.mark = Some(buffer.mark());
+    }
+
+    // Writes the unfinished line to the copy with the rules applied, what's done in it is left behind
+    fn write(&mut self, display: &mut Scrollback) {
+        let (done, rest) = match self.line.rfind('\n') {
+            Some(at) => self.line.split_at(at + 1),
+            None => ("", self.line.as_str()),
+        };
+        let done_len = done.len();
+        let done = self.rules.apply(done, &self.command);
+        let rest_original = rest.to_string();
+        let rest = self.rules.apply(rest, &self.command);
+        display.replace_from(self.display_line_start, &format!("{}{}", done, rest));
+        display.trim();
+
+        self.line_start += done_len;
+        self.display_line_start += done.len();
+        self.line = rest_original;
+    }
+}
@@ -0,0 +1,276 @@
+use std::{
+    io::{Read, Write},
+    net::{Shutdown, TcpStream},
+    sync::{Arc, Mutex},
+    thread::{self, JoinHandle},
+};
+
+use crate::backend::{Backend, Signal};
+use crate::wakeup::Wakeup;
+
+// https://www.rfc-editor.org/rfc/rfc854 and friends
+const IAC: u8 = 255;
+const DONT: u8 = 254;
+const DO: u8 = 253;
+const WONT: u8 = 252;
+const WILL: u8 = 251;
+const SB: u8 = 250;
+const IP: u8 = 244;
+const SE: u8 = 240;
+
+const ECHO: u8 = 1;
+const SUPPRESS_GO_AHEAD: u8 = 3;
+const NAWS: u8 = 31;
+
+enum Parse {
+    Data,
+    // Last byte was a carriage return, a NUL after it is dropped
+    Return,
+    Iac,
+    Option(u8),
+    Sub,
+    SubIac,
+}
+
+// Telnet option negotiation without the socket, bytes from the server go in
+// and what to show and what to answer come out
+pub struct Negotiator {
+    parse: Parse,
+    remote_echo: bool,
+    suppress_go_ahead: bool,
+    naws: bool,
+    size: (u32, u32),
+}
+
+impl Negotiator {
+    pub fn new(size: (u32, u32)) -> Self {
+        Self {
+            parse: Parse::Data,
+            remote_echo: false,
+            suppress_go_ahead: false,
+            naws: false,
+            size,
+        }
+    }
+
+    // The server echoes and wants every key as it's typed
+    pub fn is_remote_echo(&self) -> bool {
+        self.remote_echo
+    }
+
+    pub fn is_suppressing_go_ahead(&self) -> bool {
+        self.suppress_go_ahead
+    }
+
+    // Returns what should be sent to the server so it knows the new size
+    pub fn resize(&mut self, cols: u32, rows: u32) -> Vec<u8> {
+        self.size = (cols, rows);
+        if self.naws { self.window_size() } else { vec![] }
+    }
+
+    fn window_size(&self) -> Vec<u8> {
+        let mut reply = vec![IAC, SB, NAWS];
+        for value in [self.size.0.min(0xffff) as u16, self.size.1.min(0xffff) as u16] {
+            for byte in value.to_be_bytes() {
+                // 255 has to be doubled inside subnegotiation too
+                if byte == IAC {
+                    reply.push(IAC);
+                }
+                reply.push(byte);
+            }
+        }
+        reply.extend([IAC, SE]);
+        reply
+    }
+
+    fn negotiate(&mut self, verb: u8, option: u8, reply: &mut Vec<u8>) {
+        match (verb, option) {
+            (DO, NAWS) => {
+                if !self.naws {
+                    self.naws = true;
+                    reply.extend([IAC, WILL, NAWS]);
+                }
+                reply.extend(self.window_size());
+            }
+            (DO, SUPPRESS_GO_AHEAD) if !self.suppress_go_ahead => {
+                self.suppress_go_ahead = true;
+                reply.extend([IAC, WILL, SUPPRESS_GO_AHEAD]);
+            }
+            (WILL, SUPPRESS_GO_AHEAD) => reply.extend([IAC, DO, SUPPRESS_GO_AHEAD]),
+            (WILL, ECHO) if !self.remote_echo => {
+                self.remote_echo = true;
+                reply.extend([IAC, DO, ECHO]);
+            }
+            (WONT, ECHO) if self.remote_echo => {
+                self.remote_echo = false;
+                reply.extend([IAC, DONT, ECHO]);
+            }
+            // Already agreed on, answering again would loop
+            (DO, SUPPRESS_GO_AHEAD) | (WILL, ECHO) => (),
+            (DONT, NAWS) => self.naws = false,
+            // Nothing else is supported
+            (DO, _) => reply.extend([IAC, WONT, option]),
+            (WILL, _) => reply.extend([IAC, DONT, option]),
+            _ => (),
+        }
+    }
+
+    // Splits what the server sent into data to show and the answers to its negotiation
+    pub fn receive(&mut self, bytes: &[u8], data: &mut Vec<u8>, reply: &mut Vec<u8>) {
+        for &b in bytes {
+            self.parse = match std::mem::replace(&mut self.parse, Parse::Data) {
+                Parse::Data | Parse::Return if b == IAC => Parse::Iac,
+                Parse::Return if b == 0 => Parse::Data,
+                Parse::Data | Parse::Return => {
+                    data.push(b);
+                    if b == b'\r' { Parse::Return } else { Parse::Data }
+                }
+                Parse::Iac => match b {
+                    IAC => {
+                        data.push(IAC);
+                        Parse::Data
+                    }
+                    DO | DONT | WILL | WONT => Parse::Option(b),
+                    SB => Parse::Sub,
+                    // Go ahead, no-op and the rest don't mean anything to us
+                    _ => Parse::Data,
+                },
+                Parse::Option(verb) => {
+                    self.negotiate(verb, b, reply);
+                    Parse::Data
+                }
+                // Subnegotiations from the server aren't used
+                Parse::Sub => if b == IAC { Parse::SubIac } else { Parse::Sub },
+                Parse::SubIac => if b == SE { Parse::Data } else { Parse::Sub },
+            };
+        }
+    }
+
+    // Escapes data for the wire, newlines are sent as CR LF
+    pub fn send(&self, bytes: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(bytes.len());
+        for &b in bytes {
+            match b {
+                IAC => out.extend([IAC, IAC]),
+                b'\n' => out.extend(b"\r\n"),
+                _ => out.push(b),
+            }
+        }
+        out
+    }
+}
+
+// A TCP connection as the session, optionally speaking telnet
+pub struct Tcp {
+    writer: Arc<Mutex<TcpStream>>,
+    negotiator: Option<Arc<Mutex<Negotiator>>>,
+    output: Arc<Mutex<Vec<u8>>>,
+    reader: JoinHandle<()>,
+    wakeup: Arc<Mutex<Option<Wakeup>>>,
+}
+
+impl Tcp {
+    pub fn connect(address: &str, telnet: bool, size: (u32, u32)) -> std::io::Result<Self> {
+        let stream = TcpStream::connect(address)
+            .map_err(|e| std::io::Error::new(e.kind(), format!("{}: {}", address, e)))?;
+        stream.set_nodelay(true)?;
+
+        let writer = Arc::new(Mutex::new(stream.try_clone()?));
+        let negotiator = if telnet { Some(Arc::new(Mutex::new(Negotiator::new(size)))) } else { None };
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let wakeup: Arc<Mutex<Option<Wakeup>>> = Arc::new(Mutex::new(None));
+
+        let reader = {
+            let mut stream = stream;
+            let (writer, negotiator, output, wakeup) = (writer.clone(), negotiator.clone(), output.clone(), wakeup.clone());
+            thread::spawn(move || {
+                let mut buf = [0; 4096];
+                loop {
+                    let n = match stream.read(&mut buf) {
+                        Ok(0) => break,
+                        Ok(n) => n,
+                        Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                        Err(e) => {
+                            eprintln!("{}: Connection error: {}", line!(), e);
+                            break;
+                        }
+                    };
+                    match negotiator {
+                        Some(ref negotiator) => {
+                            let mut reply = vec![];
+                            let mut data = output.lock().expect("Mutex lock poisoned");
+                            negotiator.lock().expect("Mutex lock poisoned").receive(&buf[..n], &mut data, &mut reply);
+                            drop(data);
+                            if !reply.is_empty() {
+                                if let Err(e) = writer.lock().expect("Mutex lock poisoned").write_all(&reply) {
+                                    eprintln!("{}: Could not answer telnet negotiation: {}", line!(), e);
+                                }
+                            }
+                        }
+                        None => output.lock().expect("Mutex lock poisoned").extend_from_slice(&buf[..n]),
+                    }
+                    if let Some(ref wakeup) = *wakeup.lock().expect("Mutex lock poisoned") {
+                        wakeup.wake();
+                    }
+                }
+                if let Some(ref wakeup) = *wakeup.lock().expect("Mutex lock poisoned") {
+                    wakeup.wake();
+                }
+            })
+        };
+
+        Ok(Self { writer, negotiator, output, reader, wakeup })
+    }
+
+    fn write_raw(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        let mut writer = self.writer.lock().expect("Mutex lock poisoned");
+        writer.write_all(bytes)?;
+        writer.flush()
+    }
+}
+
+impl Backend for Tcp {
+    fn write_input(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        let bytes = match self.negotiator {
+            Some(ref negotiator) => negotiator.lock().expect("Mutex lock poisoned").send(bytes),
+            None => bytes.to_vec(),
+        };
+        self.write_raw(&bytes)
+    }
+
+    fn read_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut *self.output.lock().expect("Mutex lock poisoned"))
+    }
+
+    fn resize(&mut self, cols: u32, rows: u32) {
+        let update = match self.negotiator {
+            Some(ref negotiator) => negotiator.lock().expect("Mutex lock poisoned").resize(cols, rows),
+            None => return,
+        };
+        if !update.is_empty() {
+            if let Err(e) = self.write_raw(&update) {
+                eprintln!("{}: Could not send window size: {}", line!(), e);
+            }
+        }
+    }
+
+    fn signal(&mut self, signal: Signal) -> std::io::Result<()> {
+        match signal {
+            Signal::Interrupt if self.negotiator.is_some() => self.write_raw(&[IAC, IP]),
+            Signal::Interrupt => self.write_raw(b"\x03"),
+            Signal::Terminate | Signal::Kill => self.writer.lock().expect("Mutex lock poisoned").shutdown(Shutdown::Both),
+        }
+    }
+
+    fn is_exited(&mut self) -> bool {
+        self.reader.is_finished()
+    }
+
+    fn is_remote_echo(&self) -> bool {
+        self.negotiator.as_ref().is_some_and(|n| n.lock().expect("Mutex lock poisoned").is_remote_echo())
+    }
+
+    fn set_wakeup(&mut self, wakeup: Option<Wakeup>) {
+        *self.wakeup.lock().expect("Mutex lock poisoned") = wakeup;
+    }
+}
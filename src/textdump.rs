@@ -0,0 +1,79 @@
+use crate::font::Font;
+use crate::render::{Color, Rect, Renderer};
+
+// Renders into a grid of characters, anything that isn't a glyph is ignored
+// apart from full clears and the cursor position
+pub struct TextRenderer {
+    cols: u32,
+    rows: u32,
+    glyph_size: (u32, u32),
+    cells: Vec<char>,
+    cursor: Option<(u32, u32)>,
+}
+
+impl TextRenderer {
+    // `size` is in character cells
+    pub fn new(size: (u32, u32), font: &Font) -> Self {
+        Self {
+            cols: size.0,
+            rows: size.1,
+            glyph_size: (font.glyph_size.0 as u32, font.glyph_size.1 as u32),
+            cells: vec![' '; (size.0 * size.1) as usize],
+            cursor: None,
+        }
+    }
+
+    fn get_cell(&self, x: i32, y: i32) -> Option<(u32, u32)> {
+        if x < 0 || y < 0 {
+            return None;
+        }
+        let (col, row) = (x as u32 / self.glyph_size.0, y as u32 / self.glyph_size.1);
+        if col < self.cols && row < self.rows {
+            Some((col, row))
+        }
+        else {
+            None
+        }
+    }
+
+    pub fn get_cursor(&self) -> Option<(u32, u32)> {
+        self.cursor
+    }
+
+    // One line per row with the trailing blanks trimmed
+    pub fn get_text(&self) -> String {
+        self.cells
+            .chunks(self.cols as usize)
+            .map(|row| row.iter().collect::<String>().trim_end().to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+impl Renderer for TextRenderer {
+    fn get_size(&self) -> (u32, u32) {
+        (self.cols * self.glyph_size.0, self.rows * self.glyph_size.1)
+    }
+
+    fn fill_rect(&mut self, rect: Rect, _: Color) {
+        let (width, height) = self.get_size();
+        if rect.x <= 0 && rect.y <= 0 && rect.w >= width && rect.h >= height {
+            self.cells.iter_mut().for_each(|c| *c = ' ');
+            self.cursor = None;
+        }
+    }
+
+    fn draw_cell_run(&mut self, x: i32, y: i32, glyphs: &str, _: Color) {
+        for (i, glyph) in glyphs.chars().enumerate() {
+            if let Some((col, row)) = self.get_cell(x + (i as u32 * self.glyph_size.0) as i32, y) {
+                self.cells[(row * self.cols + col) as usize] = glyph;
+            }
+        }
+    }
+
+    fn draw_cursor(&mut self, rect: Rect, _: Color) {
+        self.cursor = self.get_cell(rect.x, rect.y);
+    }
+
+    fn present(&mut self) {}
+}
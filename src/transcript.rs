@@ -0,0 +1,201 @@
+use std::{fs::{File, OpenOptions}, io::Write, path::{Path, PathBuf}, time::{SystemTime, UNIX_EPOCH}};
+
+// How many rotated logs (`session.log.1`, `session.log.2`...) are kept around
+const ROTATE_KEEP: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TranscriptFormat {
+    // Output decoded the same way it's shown, with escape sequences and control characters stripped
+    Plain,
+    // Output bytes exactly as the child wrote them
+    Raw,
+}
+
+#[derive(Debug, Clone)]
+pub struct TranscriptConfig {
+    pub path: Option<PathBuf>,
+    pub format: TranscriptFormat,
+    // Zero disables rotation
+    pub max_size: u64,
+}
+
+impl Default for TranscriptConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            format: TranscriptFormat::Plain,
+            max_size: 0,
+        }
+    }
+}
+
+impl TranscriptConfig {
+    // Sessions without an explicit path get a new timestamped file in the working directory
+    pub fn get_path(&self) -> PathBuf {
+        self.path.clone().unwrap_or_else(|| {
+            PathBuf::from(format!("wcmd-{}.log", format_timestamp(SystemTime::now()).replace(':', "").replace(' ', "-")))
+        })
+    }
+}
+
+enum EscapeState {
+    None,
+    Escape,
+    Csi,
+    Osc,
+    OscEscape,
+}
+
+pub struct Transcript {
+    path: PathBuf,
+    file: File,
+    format: TranscriptFormat,
+    max_size: u64,
+    written: u64,
+    escape: EscapeState,
+    at_line_start: bool,
+}
+
+// Year, month, day, hour, minute and second of a time in seconds since the epoch
+pub fn civil_time(secs: i64) -> (i64, u32, u32, u32, u32, u32) {
+    let (days, rem) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+
+    // Civil from days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month as u32, day as u32, (rem / 3600) as u32, (rem / 60 % 60) as u32, (rem % 60) as u32)
+}
+
+// Formats as `YYYY-MM-DD HH:MM:SSZ` in UTC
+pub fn format_timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (year, month, day, hour, minute, second) = civil_time(secs as i64);
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}Z", year, month, day, hour, minute, second)
+}
+
+impl Transcript {
+    pub fn open(config: &TranscriptConfig) -> std::io::Result<Self> {
+        let path = config.get_path();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let written = file.metadata().map(|m| m.len()).unwrap_or(0);
+        Ok(Self {
+            path,
+            file,
+            format: config.format,
+            max_size: config.max_size,
+            written,
+            escape: EscapeState::None,
+            at_line_start: true,
+        })
+    }
+
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        if bytes.is_empty() {
+            return;
+        }
+        if self.max_size > 0 && self.written + bytes.len() as u64 > self.max_size && self.written > 0 {
+            if let Err(e) = self.rotate() {
+                eprintln!("{}: Could not rotate transcript {:?}: {}", line!(), self.path, e);
+            }
+        }
+        match self.file.write_all(bytes) {
+            Ok(_) => self.written += bytes.len() as u64,
+            Err(e) => eprintln!("{}: Could not write transcript {:?}: {}", line!(), self.path, e),
+        }
+    }
+
+    fn rotated_path(&self, n: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        self.file.flush()?;
+        for n in (1..ROTATE_KEEP).rev() {
+            let from = self.rotated_path(n);
+            if from.exists() {
+                std::fs::rename(&from, self.rotated_path(n + 1))?;
+            }
+        }
+        std::fs::rename(&self.path, self.rotated_path(1))?;
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.written = 0;
+        Ok(())
+    }
+
+    pub fn write_output(&mut self, bytes: &[u8]) {
+        match self.format {
+            TranscriptFormat::Raw => {
+                if let Some(&last) = bytes.last() {
+                    self.at_line_start = last == b'\n';
+                }
+                self.write(bytes);
+            }
+            TranscriptFormat::Plain => {
+                let text = self.strip(bytes);
+                self.write(text.as_bytes());
+            }
+        }
+    }
+
+    // Input lines are prefixed with the time they were sent at
+    pub fn write_input(&mut self, line: &str) {
+        let mut entry = String::new();
+        if !self.at_line_start {
+            entry.push('\n');
+        }
+        entry.push_str(&format!("[{}] > {}", format_timestamp(SystemTime::now()), line));
+        if !entry.ends_with('\n') {
+            entry.push('\n');
+        }
+        self.at_line_start = true;
+        self.write(entry.as_bytes());
+    }
+
+    // Drops ANSI escape sequences (which may be split between reads) and
+    // control characters, everything else is decoded like the screen does
+    fn strip(&mut self, bytes: &[u8]) -> String {
+        let mut text = String::new();
+        for &b in bytes {
+            match self.escape {
+                EscapeState::None => match b {
+                    0x1b => self.escape = EscapeState::Escape,
+                    b'\n' | b'\t' => text.push(b as char),
+                    0..=0x1f | 0x7f => (),
+                    _ => text.push(crate::cp437::cp437_to_unicode(b)),
+                },
+                EscapeState::Escape => match b {
+                    b'[' => self.escape = EscapeState::Csi,
+                    b']' => self.escape = EscapeState::Osc,
+                    _ => self.escape = EscapeState::None,
+                },
+                EscapeState::Csi => if (0x40..=0x7e).contains(&b) {
+                    self.escape = EscapeState::None;
+                },
+                EscapeState::Osc => match b {
+                    0x07 => self.escape = EscapeState::None,
+                    0x1b => self.escape = EscapeState::OscEscape,
+                    _ => (),
+                },
+                EscapeState::OscEscape => self.escape = EscapeState::None,
+            }
+        }
+        if let Some(last) = text.chars().last() {
+            self.at_line_start = last == '\n';
+        }
+        text
+    }
+}
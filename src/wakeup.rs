@@ -0,0 +1,29 @@
+use std::sync::{atomic::{AtomicBool, Ordering}, Arc};
+
+// Lets background threads wake up a frontend that's blocked waiting for events.
+// Wakes are coalesced, after the first one nothing is sent until `reset` is called
+#[derive(Clone)]
+pub struct Wakeup {
+    pending: Arc<AtomicBool>,
+    callback: Arc<dyn Fn() + Send + Sync>,
+}
+
+impl Wakeup {
+    pub fn new<F: Fn() + Send + Sync + 'static>(callback: F) -> Self {
+        Self {
+            pending: Arc::new(AtomicBool::new(false)),
+            callback: Arc::new(callback),
+        }
+    }
+
+    pub fn wake(&self) {
+        if !self.pending.swap(true, Ordering::AcqRel) {
+            (self.callback)();
+        }
+    }
+
+    // Has to be called before looking at whatever the threads produced, otherwise a wake could get lost
+    pub fn reset(&self) {
+        self.pending.store(false, Ordering::Release);
+    }
+}
@@ -0,0 +1,81 @@
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+use wcmd::asciicast::Player;
+use wcmd::cmd::Cmd;
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("wcmd-asciicast-{}-{}.cast", name, std::process::id()))
+}
+
+// Every event after the header as (code, data)
+fn read_events(path: &Path) -> Vec<(String, String)> {
+    std::fs::read_to_string(path).unwrap().lines().skip(1).map(|line| {
+        let event: Value = serde_json::from_str(line).unwrap();
+        (event[1].as_str().unwrap().to_string(), event[2].as_str().unwrap().to_string())
+    }).collect()
+}
+
+#[test]
+fn recorder_writes_what_a_terminal_expects() {
+    let path = temp_path("recorder");
+    let mut cmd = Cmd::new();
+    cmd.start_recording(&path).unwrap();
+    cmd.write_stdout("dir\nok\r\n");
+    cmd.put_stdin('x');
+    cmd.pop_stdin();
+    cmd.update();
+    cmd.clear();
+    cmd.put_stdout('!');
+    cmd.resize(100, 30);
+    cmd.write_stdout("\n");
+    cmd.stop_recording();
+
+    let header: Value = serde_json::from_str(std::fs::read_to_string(&path).unwrap().lines().next().unwrap()).unwrap();
+    assert_eq!(header["version"], 2);
+    assert_eq!(read_events(&path), vec![
+        ("o".to_string(), "dir\r\nok\r\nx\x08 \x08".to_string()),
+        ("o".to_string(), "\x1b[2J\x1b[H!".to_string()),
+        ("r".to_string(), "100x30".to_string()),
+        ("o".to_string(), "\r\n".to_string()),
+    ]);
+
+    // And plays back to the same screen
+    let mut played = Cmd::new();
+    let mut player = Player::load(&path).unwrap();
+    player.seek(player.get_duration(), &mut played);
+    assert_eq!(&*played.get_stdout(), "!\n");
+    assert_eq!(&*cmd.get_stdout(), "!\n");
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn seek_replays_from_the_start_when_going_back() {
+    let path = temp_path("seek");
+    std::fs::write(&path, concat!(
+        "{\"version\": 2, \"width\": 80, \"height\": 25}\n",
+        "[0.5, \"o\", \"one\\r\\n\"]\n",
+        "[1.0, \"r\", \"100x30\"]\n",
+        "[2.0, \"o\", \"\\u001b[2J\\u001b[Htwo\"]\n",
+        "[3.0, \"o\", \"\\b \\bO\"]\n",
+    )).unwrap();
+    let mut cmd = Cmd::new();
+    let mut player = Player::load(&path).unwrap();
+    assert_eq!(player.get_duration(), 3.0);
+
+    assert_eq!(player.seek(1.0, &mut cmd), Some((100, 30)));
+    assert_eq!(&*cmd.get_stdout(), "one\n");
+    assert_eq!(player.seek(2.5, &mut cmd), None);
+    assert_eq!(&*cmd.get_stdout(), "two");
+    assert_eq!(player.seek(10.0, &mut cmd), None);
+    assert_eq!(&*cmd.get_stdout(), "twO");
+    assert_eq!(player.get_time(), 3.0);
+
+    // Back before the resize goes back to the header's size
+    assert_eq!(player.seek(0.7, &mut cmd), Some((80, 25)));
+    assert_eq!(&*cmd.get_stdout(), "one\n");
+    assert_eq!(player.get_size(), (80, 25));
+    assert_eq!(player.seek(-1.0, &mut cmd), Some((80, 25)));
+    assert_eq!(&*cmd.get_stdout(), "");
+    std::fs::remove_file(&path).unwrap();
+}
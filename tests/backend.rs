@@ -0,0 +1,120 @@
+use std::time::{Duration, Instant};
+
+use wcmd::backend::{Backend, Loopback, Scripted, Signal};
+use wcmd::cmd::{Cmd, CmdEvent};
+use wcmd::subprocess::SubProcess;
+
+// Updates until the child exits or `timeout` passes, returns if it exited
+fn run_until_exit(cmd: &mut Cmd, timeout: Duration) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < timeout {
+        cmd.update();
+        if cmd.drain_events().iter().any(|e| matches!(e, CmdEvent::ChildExited)) {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    false
+}
+
+// What the window does when a line is typed and Enter is pressed
+fn type_line(cmd: &mut Cmd, line: &str) {
+    for c in line.chars() {
+        cmd.put_stdin(c);
+    }
+    if !cmd.is_remote_echo() {
+        cmd.put_stdout('\n');
+    }
+    cmd.flush_stdin();
+}
+
+#[test]
+fn loopback_echoes_input() {
+    let mut cmd = Cmd::new();
+    cmd.attach_child(Some(Loopback::new()));
+    type_line(&mut cmd, "ping");
+    cmd.update();
+    assert_eq!(&*cmd.get_stdout(), "ping\nping\n");
+
+    cmd.destroy_child();
+    assert!(run_until_exit(&mut cmd, Duration::from_secs(1)));
+}
+
+#[test]
+fn scripted_waits_for_input() {
+    let script = Scripted::new()
+        .output(b"C:\\>")
+        .input(b"dir\n")
+        .output(b" Volume in drive C has no label.\r\nC:\\>")
+        .input(b"exit\n")
+        .exit();
+    let log = script.clone();
+
+    let mut cmd = Cmd::new();
+    cmd.resize(100, 30);
+    cmd.attach_child(Some(script));
+    cmd.update();
+    assert_eq!(&*cmd.get_stdout(), "C:\\>");
+
+    // Nothing happens until the expected input shows up
+    cmd.update();
+    assert_eq!(&*cmd.get_stdout(), "C:\\>");
+    type_line(&mut cmd, "dir");
+    cmd.update();
+    assert_eq!(&*cmd.get_stdout(), "C:\\>dir\n Volume in drive C has no label.\r\nC:\\>");
+
+    type_line(&mut cmd, "exit");
+    assert!(run_until_exit(&mut cmd, Duration::from_secs(1)));
+
+    assert_eq!(log.get_log().input, b"dir\nexit\n");
+    assert_eq!(log.get_log().resizes, vec![(100, 30)]);
+}
+
+#[test]
+fn scripted_records_signals() {
+    let script = Scripted::new().output(b"working");
+    let log = script.clone();
+    let mut cmd = Cmd::new();
+    cmd.attach_child(Some(script));
+
+    cmd.signal_child(Signal::Interrupt);
+    cmd.update();
+    assert!(cmd.is_handling_subprocess());
+    cmd.destroy_child();
+    assert!(run_until_exit(&mut cmd, Duration::from_secs(1)));
+    assert_eq!(log.get_log().signals, vec![Signal::Interrupt, Signal::Kill]);
+}
+
+#[cfg(unix)]
+#[test]
+fn subprocess_can_be_interrupted() {
+    let mut child = SubProcess::from_args(&["sleep".to_string(), "10".to_string()]).unwrap();
+    child.signal(Signal::Interrupt).unwrap();
+    let mut cmd = Cmd::new();
+    cmd.attach_child(Some(child));
+    assert!(run_until_exit(&mut cmd, Duration::from_secs(5)));
+}
+
+#[cfg(unix)]
+#[test]
+fn pty_is_a_terminal() {
+    use wcmd::pty::Pty;
+
+    let command = ["sh".to_string(), "-c".to_string(), "stty size; test -t 0 && echo tty; read line; echo got $line".to_string()];
+    let mut cmd = Cmd::new();
+    cmd.resize(100, 30);
+    cmd.attach_child(Some(Pty::spawn(&command, (100, 30)).unwrap()));
+
+    let start = Instant::now();
+    while !cmd.get_stdout().contains("tty") && start.elapsed() < Duration::from_secs(5) {
+        cmd.update();
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    // The tty echoes the line, once
+    assert!(cmd.is_remote_echo());
+    type_line(&mut cmd, "hi");
+    assert!(run_until_exit(&mut cmd, Duration::from_secs(5)));
+
+    let output = cmd.get_stdout().replace('\r', "");
+    assert_eq!(output, "30 100\ntty\nhi\ngot hi\n");
+}
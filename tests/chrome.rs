@@ -0,0 +1,31 @@
+use wcmd::chrome::{Chrome, WindowsVersion};
+use wcmd::render::ConsoleStyle;
+
+#[test]
+fn versions_have_their_own_banner() {
+    assert_eq!(WindowsVersion::parse("8.1"), Ok(WindowsVersion::Win81));
+    assert!(WindowsVersion::parse("95").is_err());
+    assert_eq!(
+        WindowsVersion::Win10.get_banner(),
+        "Microsoft Windows [Version 10.0.19045.4291]\n(c) Microsoft Corporation. All rights reserved.\n\n"
+    );
+    assert!(WindowsVersion::Win7.get_banner().contains("Copyright (c) 2009"));
+    assert_eq!(WindowsVersion::Win11.get_style(), ConsoleStyle::Win11);
+    assert_eq!(WindowsVersion::Win7.get_style(), ConsoleStyle::Win10);
+}
+
+#[test]
+fn title_shows_the_running_command() {
+    let mut chrome = Chrome::new(true);
+    assert_eq!(chrome.get_title(), "Administrator: Command Prompt");
+    chrome.on_line("ping x ");
+    assert_eq!(chrome.get_title(), "Administrator: Command Prompt - ping x");
+    chrome.on_output("Reply from x");
+    assert_eq!(chrome.get_running(), Some("ping x"));
+    chrome.on_output("C:\\>");
+    assert_eq!(chrome.get_running(), None);
+
+    let mut chrome = Chrome::new(false);
+    chrome.set_title("Build");
+    assert_eq!(chrome.get_title(), "Build");
+}
@@ -0,0 +1,113 @@
+use std::time::Duration;
+
+use wcmd::cmd::{Cmd, CmdEvent};
+use wcmd::subprocess::SubProcess;
+
+#[test]
+fn output_changes_are_coalesced() {
+    let mut cmd = Cmd::new();
+    cmd.write_bytes(&vec![b'x'; 4096]);
+    cmd.write_bytes(b"\r\nC:\\>");
+
+    let events = cmd.drain_events();
+    assert_eq!(events.len(), 1);
+    match events[0] {
+        CmdEvent::StdoutChanged(ref change) => {
+            assert_eq!(change.range, 0..4096 + 6);
+            assert!(!change.cleared);
+        }
+        _ => panic!("expected a stdout change"),
+    }
+    assert!(cmd.drain_events().is_empty());
+}
+
+#[test]
+fn reports_only_the_new_range() {
+    let mut cmd = Cmd::new();
+    cmd.write_stdout("C:\\>");
+    cmd.drain_events();
+
+    cmd.put_stdin('d');
+    cmd.put_stdin('i');
+    cmd.pop_stdin();
+    match cmd.drain_events().pop_front() {
+        Some(CmdEvent::StdoutChanged(change)) => assert_eq!(change.range, 4..5),
+        _ => panic!("expected a stdout change"),
+    }
+
+    cmd.write_bytes(b"\x0cafter");
+    match cmd.drain_events().pop_front() {
+        Some(CmdEvent::StdoutChanged(change)) => {
+            assert!(change.cleared);
+            assert_eq!(&*cmd.get_stdout(), "after");
+            assert_eq!(change.range.len(), "after".len());
+        }
+        _ => panic!("expected a stdout change"),
+    }
+}
+
+#[test]
+fn title_and_bell() {
+    let mut cmd = Cmd::new();
+    cmd.write_bytes(b"\x1b]0;first\x07\x1b]2;Build\x1b\\done\x07\x07");
+
+    let events = cmd.drain_events();
+    assert_eq!(&*cmd.get_stdout(), "done");
+    assert!(matches!(events[0], CmdEvent::StdoutChanged(_)));
+    assert!(matches!(events[1], CmdEvent::TitleChanged(ref t) if t == "Build"));
+    assert!(matches!(events[2], CmdEvent::Bell));
+    assert_eq!(events.len(), 3);
+}
+
+#[test]
+fn other_escapes_are_still_shown() {
+    let mut cmd = Cmd::new();
+    cmd.write_bytes(b"\x1b[0m");
+    assert_eq!(&*cmd.get_stdout(), "\u{2190}[0m");
+}
+
+// Runs the command as the child until it exits, returns the output and every event on the way
+fn run_child(script: &str) -> (String, Vec<CmdEvent>) {
+    let mut cmd = Cmd::new();
+    cmd.attach_child(SubProcess::from_args(&["sh".to_string(), "-c".to_string(), script.to_string()]));
+    assert!(cmd.is_handling_subprocess());
+
+    let mut events = vec![];
+    loop {
+        cmd.update();
+        let drained = cmd.drain_events();
+        let exited = drained.iter().any(|e| matches!(e, CmdEvent::ChildExited));
+        events.extend(drained);
+        if exited {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    let output = cmd.get_stdout().to_string();
+    (output, events)
+}
+
+#[test]
+fn child_output_is_the_same_as_writing_it() {
+    // Split over several writes so it's drained in pieces
+    let (output, events) = run_child("printf 'C:\\\\>dir\\r\\n'; sleep 0.05; printf '\\033]0;Listing\\007 Volume in drive C'; sleep 0.05; printf '\\014 cleared\\033[0m'");
+
+    let mut expected = Cmd::new();
+    expected.write_bytes(b"C:\\>dir\r\n\x1b]0;Listing\x07 Volume in drive C\x0c cleared\x1b[0m");
+    assert_eq!(output, &*expected.get_stdout());
+
+    assert!(events.iter().any(|e| matches!(e, CmdEvent::TitleChanged(ref t) if t == "Listing")));
+    assert!(events.iter().any(|e| matches!(e, CmdEvent::StdoutChanged(ref c) if c.cleared)));
+    assert!(matches!(events.last(), Some(CmdEvent::ChildExited)));
+}
+
+#[test]
+fn nothing_is_lost_when_the_child_exits() {
+    let (output, _) = run_child("i=0; while [ $i -lt 2000 ]; do echo line $i; i=$((i+1)); done; echo done >&2");
+    // stderr is read on its own so it can land in the middle of a stdout line
+    assert!(output.contains("done\n"));
+    let output = output.replacen("done\n", "", 1);
+    assert_eq!(output.lines().count(), 2000);
+    assert!(output.starts_with("line 0\n"));
+    assert!(output.ends_with("line 1999\n"));
+}
@@ -0,0 +1,109 @@
+use std::time::{Duration, Instant};
+
+use wcmd::cmd::Cmd;
+use wcmd::font::Font;
+use wcmd::input_filter::{CharClass, InputFilter};
+use wcmd::prank::{PrankContext, PrankModes};
+use wcmd::render::VisualCommandLine;
+use wcmd::substitute::Rules;
+
+fn typed(filters: &[&str], text: &str) -> Cmd {
+    let mut cmd = Cmd::new();
+    for spec in filters {
+        let filter = InputFilter::parse(spec).unwrap();
+        cmd.input_filters.set(filter.get_kind(), filter);
+    }
+    for c in text.chars() {
+        cmd.put_stdin(c);
+    }
+    cmd
+}
+
+#[test]
+fn parses_filters() {
+    let filter = InputFilter::parse("limit class=letters max=2").unwrap();
+    assert_eq!(filter, InputFilter::Limit { class: CharClass::Letters, max: 2 });
+    assert_eq!(filter.to_string(), "limit max=2 class=letters");
+    assert_eq!(InputFilter::parse(&filter.to_string()).unwrap(), filter);
+    assert_eq!(InputFilter::parse("lag").unwrap(), InputFilter::Lag { min: 50, max: 400 });
+    assert_eq!(InputFilter::parse("drop chance=20%").unwrap(), InputFilter::Drop { chance: 20 });
+
+    assert_eq!(InputFilter::parse("teleport").unwrap_err(), "unknown input filter: teleport");
+    assert!(InputFilter::parse("drop chance=101").is_err());
+    assert!(InputFilter::parse("lag min").is_err());
+    assert_eq!(InputFilter::parse("limit max=many").unwrap_err(), "invalid number of digits: many");
+    assert_eq!(InputFilter::parse("double from=x").unwrap_err(), "no such parameter: from");
+}
+
+#[test]
+fn filters_change_what_is_typed() {
+    assert_eq!(typed(&["drop chance=100"], "dir").get_stdin(), "");
+    assert_eq!(typed(&["double chance=100"], "dir").get_stdin(), "ddiirr");
+    assert_eq!(typed(&["limit class=upper max=1"], "DiR /S").get_stdin(), "Di /");
+    assert_eq!(typed(&["swap-digits chance=100"], "12a3b4").get_stdin(), "21a3b");
+    // Each filter sees what the one before let through
+    assert_eq!(typed(&["double chance=100", "limit max=3"], "12").get_stdin(), "112");
+
+    let mut cmd = typed(&["rewrite from=exit to=exti"], "exit");
+    assert_eq!(cmd.get_stdin(), "exti");
+    assert_eq!(&*cmd.get_stdout(), "exti");
+    cmd.pop_stdin();
+    assert_eq!(cmd.get_stdin(), "ext");
+
+    // Enter doesn't wait for a held back digit
+    let mut cmd = typed(&["swap-digits chance=100"], "7");
+    assert_eq!(cmd.get_stdin(), "");
+    assert_eq!(cmd.flush_stdin(), "7\n");
+}
+
+#[test]
+fn lagging_keys_arrive_later_and_in_order() {
+    let mut cmd = typed(&["lag min=50 max=50"], "ab");
+    cmd.pop_stdin();
+    assert_eq!(cmd.get_stdin(), "");
+    let deadline = cmd.get_deadline().unwrap();
+    assert!(deadline > Instant::now());
+
+    std::thread::sleep(Duration::from_millis(160));
+    cmd.update();
+    assert_eq!(cmd.get_stdin(), "a");
+    assert_eq!(cmd.get_deadline(), None);
+
+    // Enter lets everything through at once
+    cmd.put_stdin('c');
+    assert_eq!(cmd.get_stdin(), "a");
+    assert_eq!(cmd.enter(), "ac\n");
+    // Echoed before the newline, not on the next line
+    assert_eq!(&*cmd.get_stdout(), "ac\n");
+}
+
+#[test]
+fn filter_modes_follow_their_parameters() {
+    let mut cmd = Cmd::new();
+    let mut visual_cmd = VisualCommandLine::new(Font::default());
+    let mut focus_lost = false;
+    let mut pranks = PrankModes::with_builtins(Rules::default());
+    macro_rules! ctx {
+        () => {
+            &mut PrankContext { cmd: &mut cmd, visual_cmd: &mut visual_cmd, focus_lost: &mut focus_lost, raise_window: false }
+        };
+    }
+
+    pranks.set_active("double-keys", true, ctx!()).unwrap();
+    assert_eq!(cmd.input_filters.get("double-keys"), Some(&InputFilter::Double { chance: 5 }));
+    pranks.set_param("double-keys", "chance", "100").unwrap();
+    pranks.update(0.0, ctx!());
+    cmd.put_stdin('x');
+    assert_eq!(cmd.get_stdin(), "xx");
+
+    pranks.set_param("digit-limit", "class", "letters").unwrap();
+    pranks.set_active("digit-limit", true, ctx!()).unwrap();
+    let names: Vec<&str> = cmd.input_filters.list().map(|f| f.0).collect();
+    assert_eq!(names, ["double-keys", "digit-limit"]);
+    cmd.put_stdin('y');
+    assert_eq!(cmd.get_stdin(), "xxy");
+
+    pranks.set_active("double-keys", false, ctx!()).unwrap();
+    pranks.set_active("digit-limit", false, ctx!()).unwrap();
+    assert!(cmd.input_filters.is_empty());
+}
@@ -0,0 +1,156 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use wcmd::backend::{self, BackendKind};
+use wcmd::chrome::WindowsVersion;
+use wcmd::cmd::{Cmd, CmdEvent};
+use wcmd::interpreter::Interpreter;
+use wcmd::options::Options;
+
+// A directory to be C:\ with a file and a folder in it
+fn make_drive(name: &str) -> PathBuf {
+    let drive = std::env::temp_dir().join(format!("wcmd-interpreter-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(drive.join("Users").join("Me")).unwrap();
+    std::fs::write(drive.join("Notes.txt"), "hello from C:\n").unwrap();
+    drive
+}
+
+fn start(drive: &Path) -> Cmd {
+    let mut cmd = Cmd::new();
+    cmd.attach_child(Some(Interpreter::new(drive.to_path_buf(), WindowsVersion::Win10).unwrap()));
+    cmd.update();
+    cmd
+}
+
+// What the window does when a line is typed and Enter is pressed, returns what was printed
+fn run(cmd: &mut Cmd, line: &str) -> String {
+    let start = cmd.get_stdout().len();
+    for c in line.chars() {
+        cmd.put_stdin(c);
+    }
+    cmd.put_stdout('\n');
+    cmd.flush_stdin();
+    cmd.update();
+    // After cls there is less than before
+    cmd.get_stdout().get(start..).unwrap_or_default().to_string()
+}
+
+#[test]
+fn builtins_work_like_cmd() {
+    let drive = make_drive("builtins");
+    let mut cmd = start(&drive);
+    assert_eq!(&*cmd.get_stdout(), "C:\\>");
+
+    assert_eq!(run(&mut cmd, "echo hi there"), "echo hi there\nhi there\n\nC:\\>");
+    assert_eq!(run(&mut cmd, ""), "\nC:\\>");
+    assert_eq!(run(&mut cmd, "echo."), "echo.\n\n\nC:\\>");
+    assert_eq!(run(&mut cmd, "ver"), "ver\n\nMicrosoft Windows [Version 10.0.19045.4291]\n\nC:\\>");
+
+    // Names are found whatever their case, and nothing is above C:\
+    assert_eq!(run(&mut cmd, "cd users\\me"), "cd users\\me\n\nC:\\Users\\Me>");
+    assert_eq!(run(&mut cmd, "cd.."), "cd..\n\nC:\\Users>");
+    assert!(run(&mut cmd, "cd ..\\..\\..").ends_with("\nC:\\>"));
+    assert!(run(&mut cmd, "cd nowhere").contains("The system cannot find the path specified."));
+    assert!(run(&mut cmd, "cd D:\\").contains("The system cannot find the path specified."));
+
+    assert_eq!(run(&mut cmd, "type NOTES.TXT"), "type NOTES.TXT\nhello from C:\n\nC:\\>");
+    assert!(run(&mut cmd, "type missing.txt").contains("The system cannot find the file specified."));
+
+    let listing = run(&mut cmd, "dir");
+    assert!(listing.contains(" Directory of C:\\\n"));
+    assert!(listing.contains("<DIR>          Users\n"));
+    assert!(listing.contains("             14 Notes.txt\n"));
+    assert!(listing.contains("               1 File(s)             14 bytes\n"));
+    assert!(listing.contains("               1 Dir(s) "));
+    // Free space is grouped in threes, 1,234,567
+    let free = listing.lines().find(|l| l.contains("Dir(s)")).unwrap().split_whitespace().nth(2).unwrap();
+    let groups: Vec<&str> = free.split(',').collect();
+    assert!((1..=3).contains(&groups[0].len()) && groups[1..].iter().all(|g| g.len() == 3), "{}", free);
+    assert_eq!(run(&mut cmd, "dir /b *.TXT"), "dir /b *.TXT\nNotes.txt\n\nC:\\>");
+    assert!(run(&mut cmd, "dir *.exe").contains("File Not Found"));
+    let listing = run(&mut cmd, "dir users");
+    assert!(listing.contains(" Directory of C:\\Users\n"));
+    assert!(listing.contains("<DIR>          .\n"));
+    assert!(listing.contains("<DIR>          ..\n"));
+
+    std::fs::remove_dir_all(&drive).unwrap();
+}
+
+#[test]
+fn variables_are_expanded() {
+    let drive = make_drive("variables");
+    let mut cmd = start(&drive);
+    run(&mut cmd, "set Greeting=hello");
+    assert_eq!(run(&mut cmd, "echo %GREETING% %nothing% 100%"), "echo %GREETING% %nothing% 100%\nhello %nothing% 100%\n\nC:\\>");
+    assert_eq!(run(&mut cmd, "set greet"), "set greet\nGreeting=hello\n\nC:\\>");
+    run(&mut cmd, "cd users");
+    assert!(run(&mut cmd, "echo %CD%").starts_with("echo %CD%\nC:\\Users\n"));
+
+    run(&mut cmd, "set greeting=");
+    assert!(run(&mut cmd, "set greeting").contains("Environment variable greeting not defined"));
+
+    // Echo off takes the prompt away
+    assert_eq!(run(&mut cmd, "echo off"), "echo off\n");
+    assert_eq!(run(&mut cmd, "echo"), "echo\nECHO is off.\n");
+
+    std::fs::remove_dir_all(&drive).unwrap();
+}
+
+#[test]
+fn title_color_cls_and_exit() {
+    let drive = make_drive("window");
+    let mut cmd = start(&drive);
+    run(&mut cmd, "title Build");
+    run(&mut cmd, "color 1f");
+    let events = cmd.drain_events();
+    assert!(events.iter().any(|e| matches!(e, CmdEvent::TitleChanged(ref t) if t == "Build")));
+    assert!(events.iter().any(|e| matches!(e, CmdEvent::ColorChanged(0x1f))));
+
+    // The same color for both can't be read
+    run(&mut cmd, "color 22");
+    assert!(!cmd.drain_events().iter().any(|e| matches!(e, CmdEvent::ColorChanged(_))));
+
+    run(&mut cmd, "cls");
+    assert_eq!(&*cmd.get_stdout(), "C:\\>");
+
+    run(&mut cmd, "exit");
+    cmd.update();
+    assert!(cmd.drain_events().iter().any(|e| matches!(e, CmdEvent::ChildExited)));
+
+    std::fs::remove_dir_all(&drive).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn other_commands_are_started_as_programs() {
+    let drive = make_drive("programs");
+    let mut cmd = start(&drive);
+    run(&mut cmd, "cd users");
+    run(&mut cmd, "set WCMD_TEST=passed");
+    run(&mut cmd, "sh -c \"pwd; echo $WCMD_TEST\"");
+    let start = Instant::now();
+    while !cmd.get_stdout().ends_with("C:\\Users>") && start.elapsed() < Duration::from_secs(5) {
+        cmd.update();
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    let expected = format!("{}\npassed\n\nC:\\Users>", drive.join("Users").display());
+    assert!(cmd.get_stdout().ends_with(&expected), "{:?}", &*cmd.get_stdout());
+
+    assert!(run(&mut cmd, "no-such-program-here").contains("'no-such-program-here' is not recognized as an internal or external command,\n"));
+
+    std::fs::remove_dir_all(&drive).unwrap();
+}
+
+#[test]
+fn chosen_from_the_command_line() {
+    let parse = |args: &[&str]| Options::from_args(args.iter().map(|s| s.to_string()));
+    let options = parse(&["wcmd", "--drive", "/tmp", "--windows", "7"]).unwrap();
+    assert_eq!(options.backend, BackendKind::Builtin { drive: Some(PathBuf::from("/tmp")), version: WindowsVersion::Win7 });
+    assert!(parse(&["wcmd", "--builtin", "ls"]).is_err());
+    if cfg!(not(target_os="windows")) {
+        assert_eq!(parse(&["wcmd"]).unwrap().backend, BackendKind::Builtin { drive: None, version: WindowsVersion::Win10 });
+    }
+    assert_eq!(parse(&["wcmd", "ls"]).unwrap().backend, BackendKind::Process);
+
+    assert!(backend::spawn(&BackendKind::Builtin { drive: Some(PathBuf::from("/no/such/dir")), version: WindowsVersion::Win10 }, &[], (80, 25)).is_err());
+}
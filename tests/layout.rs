@@ -0,0 +1,145 @@
+use wcmd::font::Font;
+use wcmd::layout::LineIndex;
+use wcmd::screen::Screen;
+use wcmd::scrollback::{Scrollback, ScrollbackLimit};
+
+fn rows(index: &LineIndex) -> Vec<usize> {
+    (0..index.get_row_count()).map(|r| index.get_row_start(r)).collect()
+}
+
+// An index that's kept up to date has to end up the same as one built from scratch
+fn assert_same_as_fresh(index: &LineIndex, screen: &Screen, right_bound: u32) {
+    let mut fresh = LineIndex::new(&Font::default());
+    fresh.update(screen, right_bound);
+    assert_eq!(rows(index), rows(&fresh), "{:?}", &*screen.get_text());
+    assert_eq!(index.get_end(), fresh.get_end(), "{:?}", &*screen.get_text());
+}
+
+#[test]
+fn wraps_and_breaks_lines() {
+    let mut screen = Screen::new(0x07);
+    screen.set_text("0123456789abc\r\n\tx\n".to_string());
+    let mut index = LineIndex::new(&Font::default());
+    // Room for 10 glyphs
+    index.update(&screen, 80);
+
+    assert_eq!(rows(&index), vec![0, 10, 15, 18]);
+    assert_eq!(index.get_end(), (0, 3 * 16));
+    assert_eq!(index.get_row_at_offset(10), 1);
+    assert_eq!(index.get_rows_text(&screen.get_text(), 1..2), "abc\r\n");
+}
+
+#[test]
+fn follows_appends_and_edits() {
+    let mut screen = Screen::new(0x07);
+    let mut index = LineIndex::new(&Font::default());
+    let mut text = String::new();
+    for i in 0..200 {
+        text.push_str(&format!("Line {} {}\r\n", i, "x".repeat(i % 23)));
+        screen.set_text(text.clone());
+        index.update(&screen, 80);
+        assert_same_as_fresh(&index, &screen, 80);
+    }
+
+    // Backspace
+    text.push_str("C:\\>dirr");
+    screen.set_text(text.clone());
+    index.update(&screen, 80);
+    text.pop();
+    screen.set_text(text.clone());
+    index.update(&screen, 80);
+    assert_same_as_fresh(&index, &screen, 80);
+
+    // Scrollback trimming drops whole lines from the front
+    let trimmed = text[text.find('\n').unwrap() + 1..].to_string();
+    screen.set_text(trimmed);
+    index.update(&screen, 80);
+    assert_same_as_fresh(&index, &screen, 80);
+
+    // Resizing lays everything out again
+    index.update(&screen, 160);
+    assert_same_as_fresh(&index, &screen, 160);
+}
+
+#[test]
+fn catches_up_after_many_changes() {
+    let mut screen = Screen::new(0x07);
+    let mut index = LineIndex::new(&Font::default());
+    screen.set_text("old text\n".repeat(10));
+    index.update(&screen, 80);
+
+    // More changes than the screen remembers
+    for i in 0..300 {
+        screen.set_text(format!("{}\n", i).repeat(10));
+    }
+    index.update(&screen, 80);
+    assert_same_as_fresh(&index, &screen, 80);
+}
+
+#[test]
+fn only_visible_rows() {
+    let mut screen = Screen::new(0x07);
+    screen.set_text("line\n".repeat(1000));
+    let mut index = LineIndex::new(&Font::default());
+    index.update(&screen, 640);
+
+    assert_eq!(index.get_visible_rows(0, 400), 0..25);
+    assert_eq!(index.get_visible_rows(8, 400), 0..26);
+    assert_eq!(index.get_visible_rows(16 * 990, 400), 990..1001);
+    assert_eq!(index.get_rows_text(&screen.get_text(), 999..1001), "line\n");
+}
+
+#[test]
+fn follows_shared_scrollback() {
+    let buffer = Scrollback::new_shared(ScrollbackLimit::Lines(16));
+    let screen = Screen::from_buffer(buffer.clone(), 0x07);
+    let mut index = LineIndex::new(&Font::default());
+
+    for i in 0..100 {
+        buffer.borrow_mut().push_str(&format!("Line {} {}\r\n", i, "x".repeat(i % 13)));
+        buffer.borrow_mut().trim();
+        index.update(&screen, 80);
+        assert_same_as_fresh(&index, &screen, 80);
+    }
+
+    let mark = buffer.borrow().mark();
+    let end = buffer.borrow().get_end();
+    buffer.borrow_mut().push_str("C:\\>");
+    assert_eq!(buffer.borrow().get_changed_since(&mark), Some(end));
+    buffer.borrow_mut().pop();
+    buffer.borrow_mut().pop();
+    // Still only what came after the mark
+    assert_eq!(buffer.borrow().get_changed_since(&mark), Some(end));
+    let mark = buffer.borrow().mark();
+    buffer.borrow_mut().pop();
+    assert_eq!(buffer.borrow().get_changed_since(&mark), Some(end + 1));
+    buffer.borrow_mut().clear();
+    index.update(&screen, 80);
+    assert_same_as_fresh(&index, &screen, 80);
+}
+
+#[test]
+fn overlay_is_shown_instead_and_laid_out_again() {
+    let mut screen = Screen::new(0x07);
+    screen.set_text("short\n".to_string());
+    let overlay = Scrollback::new_shared(ScrollbackLimit::Unlimited);
+    overlay.borrow_mut().push_str("a line long enough to wrap\n");
+    let mut index = LineIndex::new(&Font::default());
+    index.update(&screen, 80);
+
+    screen.set_overlay(Some(overlay.clone()));
+    assert_eq!(&*screen.get_text(), "a line long enough to wrap\n");
+    index.update(&screen, 80);
+    assert_same_as_fresh(&index, &screen, 80);
+
+    // What was under it is still there
+    screen.set_overlay(None);
+    index.update(&screen, 80);
+    assert_eq!(&*screen.get_text(), "short\n");
+    assert_same_as_fresh(&index, &screen, 80);
+
+    // Marks don't carry over from one scrollback to another
+    let mark = screen.get_buffer().mark();
+    assert_eq!(screen.get_buffer().get_changed_since(&mark), None);
+    assert_eq!(overlay.borrow().get_changed_since(&mark), Some(0));
+}
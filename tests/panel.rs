@@ -0,0 +1,88 @@
+use wcmd::cmd::Cmd;
+use wcmd::font::Font;
+use wcmd::panel::{ControlPanel, PanelKey};
+use wcmd::prank::{PrankContext, PrankModes};
+use wcmd::render::VisualCommandLine;
+use wcmd::substitute::Rules;
+use wcmd::textdump::TextRenderer;
+
+struct Session {
+    cmd: Cmd,
+    visual_cmd: VisualCommandLine,
+    focus_lost: bool,
+    pranks: PrankModes,
+    panel: ControlPanel,
+}
+
+impl Session {
+    fn new() -> Self {
+        Self {
+            cmd: Cmd::new(),
+            visual_cmd: VisualCommandLine::new(Font::default()),
+            focus_lost: false,
+            pranks: PrankModes::with_builtins(Rules::default()),
+            panel: ControlPanel::new(Font::default(), ControlPanel::default_key()),
+        }
+    }
+
+    fn press(&mut self, keys: &[PanelKey]) {
+        for &key in keys {
+            let mut ctx = PrankContext { cmd: &mut self.cmd, visual_cmd: &mut self.visual_cmd, focus_lost: &mut self.focus_lost, raise_window: false };
+            self.panel.key(key, &mut self.pranks, &mut ctx);
+        }
+    }
+}
+
+#[test]
+fn lists_modes_with_state_keys_and_params() {
+    let mut session = Session::new();
+    session.panel.toggle();
+    assert!(session.panel.is_open());
+
+    let lines = session.panel.get_lines(&session.pranks);
+    assert_eq!(lines[0], "Operator panel (Ctrl+Alt+Shift+F12 closes)");
+    assert!(lines[2].starts_with(">[ ] unfocus        Ctrl+Shift+F1"));
+    assert!(lines.iter().any(|l| l == "       max = 3"));
+    assert!(lines.iter().any(|l| l == "       rules = default"));
+
+    // Down to color-roll and switch it on
+    session.press(&[PanelKey::Down, PanelKey::Enter]);
+    assert!(session.pranks.is_active("color-roll"));
+    assert!(session.panel.get_lines(&session.pranks).iter().any(|l| l.starts_with(">[x] color-roll")));
+
+    session.press(&[PanelKey::Escape]);
+    assert!(!session.panel.is_open());
+}
+
+#[test]
+fn edits_parameters() {
+    let mut session = Session::new();
+    session.panel.toggle();
+    // unfocus, color-roll, roll, digit-limit, max
+    session.press(&[PanelKey::Down, PanelKey::Down, PanelKey::Down, PanelKey::Down, PanelKey::Enter]);
+    assert!(session.panel.is_editing());
+    session.press(&[PanelKey::Backspace]);
+    session.panel.text_input("5");
+    assert!(session.panel.get_lines(&session.pranks).iter().any(|l| l == ">      max = 5_"));
+    session.press(&[PanelKey::Enter]);
+    assert_eq!(session.pranks.get_params("digit-limit").unwrap(), [("max".to_string(), "5".to_string()), ("class".to_string(), "digits".to_string())]);
+
+    // Bad values are reported and leave the old one
+    session.press(&[PanelKey::Enter, PanelKey::Backspace]);
+    session.panel.text_input("lots");
+    session.press(&[PanelKey::Enter]);
+    assert_eq!(session.panel.get_lines(&session.pranks).last().unwrap(), "Error: invalid number of digits: lots");
+    assert_eq!(session.pranks.get_params("digit-limit").unwrap()[0].1, "5");
+}
+
+#[test]
+fn renders_over_the_screen() {
+    let session = Session::new();
+    let font = Font::default();
+    let mut renderer = TextRenderer::new((80, 25), &font);
+    session.panel.render(&mut renderer, &session.pranks);
+    let text = renderer.get_text();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines[2].trim_end(), "  Operator panel (Ctrl+Alt+Shift+F12 closes)");
+    assert!(lines[4].starts_with("  >[ ] unfocus"));
+}
@@ -0,0 +1,175 @@
+use wcmd::cmd::Cmd;
+use wcmd::font::Font;
+use wcmd::prank::{KeyChord, PrankContext, PrankMode, PrankModes};
+use wcmd::render::VisualCommandLine;
+use wcmd::screen::Screen;
+use wcmd::substitute::Rules;
+
+struct Window {
+    cmd: Cmd,
+    visual_cmd: VisualCommandLine,
+    focus_lost: bool,
+}
+
+impl Window {
+    fn new() -> Self {
+        Self { cmd: Cmd::new(), visual_cmd: VisualCommandLine::new(Font::default()), focus_lost: false }
+    }
+
+    fn ctx(&mut self) -> PrankContext<'_> {
+        PrankContext { cmd: &mut self.cmd, visual_cmd: &mut self.visual_cmd, focus_lost: &mut self.focus_lost, raise_window: false }
+    }
+
+    // What the window does with a TextInput event
+    fn type_text(&mut self, pranks: &mut PrankModes, text: &str) {
+        let mut input = text.to_string();
+        pranks.on_input(&mut input, &mut self.ctx());
+        for c in input.chars() {
+            self.cmd.put_stdin(c);
+        }
+    }
+}
+
+#[test]
+fn key_chords() {
+    let chord = KeyChord::parse("ctrl+Shift+f9").unwrap();
+    assert!(chord.ctrl && chord.shift && !chord.alt);
+    assert_eq!(chord, KeyChord::parse("Shift+Ctrl+F9").unwrap());
+    assert_eq!(chord.to_string(), "Ctrl+Shift+F9");
+    assert_eq!(KeyChord::new("Escape").to_string(), "Escape");
+    assert!(KeyChord::parse("Hyper+A").is_err());
+    assert!(KeyChord::parse("Ctrl+").is_err());
+}
+
+#[test]
+fn builtin_modes_toggle_on_their_keys() {
+    let mut window = Window::new();
+    let mut pranks = PrankModes::with_builtins(Rules::default());
+
+    assert!(pranks.on_key(&KeyChord::parse("Ctrl+Shift+F3").unwrap(), &mut window.ctx()));
+    assert!(pranks.is_active("digit-limit"));
+    window.type_text(&mut pranks, "12");
+    window.type_text(&mut pranks, "3a45");
+    assert_eq!(window.cmd.get_stdin(), "123a");
+
+    let mut ctx = window.ctx();
+    assert!(pranks.on_key(&KeyChord::parse("Ctrl+Shift+F4").unwrap(), &mut ctx));
+    assert!(ctx.raise_window);
+
+    assert!(!pranks.on_key(&KeyChord::new("F12"), &mut window.ctx()));
+    // Not without the modifiers, that's just typing
+    assert!(!pranks.on_key(&KeyChord::new("F3"), &mut window.ctx()));
+    assert!(pranks.on_key(&KeyChord::parse("Ctrl+Shift+F3").unwrap(), &mut window.ctx()));
+    window.type_text(&mut pranks, "6");
+    assert_eq!(window.cmd.get_stdin(), "123a6");
+}
+
+#[test]
+fn color_roll_and_unfocus() {
+    let mut window = Window::new();
+    let mut pranks = PrankModes::with_builtins(Rules::default());
+    let mut screen = Screen::new(0x07);
+
+    pranks.set_active("color-roll", true, &mut window.ctx()).unwrap();
+    pranks.render(&mut screen);
+    assert_eq!(screen.color, 0x07);
+    window.type_text(&mut pranks, "a");
+    pranks.render(&mut screen);
+    assert_eq!(screen.color, 0x10);
+    assert!(!window.focus_lost);
+
+    pranks.set_active("unfocus", true, &mut window.ctx()).unwrap();
+    window.type_text(&mut pranks, "b");
+    assert!(window.focus_lost);
+    assert!(pranks.set_active("nope", true, &mut window.ctx()).is_err());
+}
+
+#[test]
+fn substitute_mode_rewrites_new_output() {
+    let mut window = Window::new();
+    let mut pranks = PrankModes::with_builtins(Rules::default());
+    window.cmd.write_bytes(b"Foreign\n");
+    pranks.on_key(&KeyChord::parse("Ctrl+Shift+F5").unwrap(), &mut window.ctx());
+    window.cmd.write_bytes(b"Foreign\n");
+    pranks.on_output(&mut window.ctx());
+
+    // Only on screen
+    let mut screen = Screen::from_buffer(window.cmd.get_stdout_buffer(), 0x07);
+    pranks.render(&mut screen);
+    assert_eq!(&*screen.get_text(), "Foreign\nTrusted\n");
+    assert_eq!(&*window.cmd.get_stdout(), "Foreign\nForeign\n");
+
+    // Switching it off shows what was really printed
+    pranks.on_key(&KeyChord::parse("Ctrl+Shift+F5").unwrap(), &mut window.ctx());
+    screen.set_overlay(None);
+    pranks.render(&mut screen);
+    assert_eq!(&*screen.get_text(), "Foreign\nForeign\n");
+}
+
+#[test]
+fn progress_mode_switches_itself_off() {
+    let mut window = Window::new();
+    let mut pranks = PrankModes::with_builtins(Rules::default());
+    pranks.set_param("progress", "seconds", "0.1").unwrap();
+    pranks.set_param("progress", "style", "spinner").unwrap();
+    assert!(pranks.set_param("progress", "style", "dots").is_err());
+
+    let key = KeyChord::parse("Ctrl+Shift+F8").unwrap();
+    pranks.on_key(&KeyChord::new("F7"), &mut window.ctx());
+    assert!(!pranks.is_active("progress"));
+    pranks.on_key(&key, &mut window.ctx());
+    assert!(pranks.is_active("progress"));
+    let start = std::time::Instant::now();
+    while pranks.is_active("progress") && start.elapsed() < std::time::Duration::from_secs(5) {
+        window.cmd.update();
+        pranks.update(0.01, &mut window.ctx());
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert!(!pranks.is_active("progress"));
+    assert_eq!(&*window.cmd.get_stdout(), "Please wait... done.\n");
+
+    // Pressing the key again aborts it
+    pranks.on_key(&key, &mut window.ctx());
+    pranks.on_key(&key, &mut window.ctx());
+    assert!(!window.cmd.is_playing_response());
+    assert!(window.cmd.get_stdout().ends_with("^C\n"));
+}
+
+struct Shout;
+
+impl PrankMode for Shout {
+    fn get_name(&self) -> &str {
+        "shout"
+    }
+
+    fn get_description(&self) -> &str {
+        "Types in capitals"
+    }
+
+    fn on_input(&mut self, input: &mut String, _ctx: &mut PrankContext) {
+        *input = input.to_uppercase();
+    }
+}
+
+#[test]
+fn custom_modes_and_rebinding() {
+    let mut window = Window::new();
+    let mut pranks = PrankModes::with_builtins(Rules::default());
+    pranks.register(Box::new(Shout), None);
+    assert!(pranks.is_bound(&KeyChord::parse("Ctrl+Shift+F1").unwrap()));
+
+    // Taking its key leaves the mode that had it unbound
+    pranks.bind("shout", KeyChord::parse("Ctrl+Shift+F1").unwrap()).unwrap();
+    assert!(pranks.list().any(|(name, _, _, key)| name == "unfocus" && key.is_none()));
+    pranks.on_key(&KeyChord::parse("Ctrl+Shift+F1").unwrap(), &mut window.ctx());
+    window.type_text(&mut pranks, "dir");
+    assert_eq!(window.cmd.get_stdin(), "DIR");
+    assert!(!window.focus_lost);
+    assert!(pranks.bind("nope", KeyChord::new("F2")).is_err());
+
+    // Keys the window uses itself can't be taken
+    let logging = KeyChord::parse("Ctrl+Shift+F7").unwrap();
+    pranks.reserve(logging.clone(), "logging");
+    assert_eq!(pranks.bind("shout", logging.clone()), Err("Ctrl+Shift+F7 is already used for logging".to_string()));
+    assert!(!pranks.is_bound(&logging));
+}
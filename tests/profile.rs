@@ -0,0 +1,147 @@
+use std::path::PathBuf;
+
+use serde_json::json;
+use wcmd::cmd::Cmd;
+use wcmd::font::Font;
+use wcmd::input_filter::InputFilter;
+use wcmd::prank::{PrankContext, PrankModes};
+use wcmd::profile::{get_profile_path, Profile};
+use wcmd::render::VisualCommandLine;
+use wcmd::screen::Screen;
+use wcmd::substitute::Rules;
+
+struct Session {
+    cmd: Cmd,
+    visual_cmd: VisualCommandLine,
+    focus_lost: bool,
+    pranks: PrankModes,
+    color: u8,
+}
+
+impl Session {
+    fn new() -> Self {
+        Self {
+            cmd: Cmd::new(),
+            visual_cmd: VisualCommandLine::new(Font::default()),
+            focus_lost: false,
+            pranks: PrankModes::with_builtins(Rules::default()),
+            color: 0x07,
+        }
+    }
+
+    fn set_active(&mut self, name: &str) {
+        let mut ctx = PrankContext { cmd: &mut self.cmd, visual_cmd: &mut self.visual_cmd, focus_lost: &mut self.focus_lost, raise_window: false };
+        self.pranks.set_active(name, true, &mut ctx).unwrap();
+    }
+
+    fn capture(&self) -> Profile {
+        Profile::capture(&self.pranks, &self.cmd, self.color)
+    }
+
+    fn apply(&mut self, profile: &Profile) -> Vec<String> {
+        let mut ctx = PrankContext { cmd: &mut self.cmd, visual_cmd: &mut self.visual_cmd, focus_lost: &mut self.focus_lost, raise_window: false };
+        profile.apply(&mut self.pranks, &mut ctx, &mut self.color)
+    }
+}
+
+fn set_up() -> Session {
+    let mut session = Session::new();
+    session.color = 0x1f;
+    session.pranks.set_param("digit-limit", "max", "1").unwrap();
+    session.set_active("digit-limit");
+    session.pranks.set_param("color-roll", "roll", "4").unwrap();
+    session.pranks.set_param("substitute", "rule", "dir => tree").unwrap();
+    session.set_active("substitute");
+    session.pranks.set_param("progress", "style", "scan").unwrap();
+    session.set_active("progress");
+    session.cmd.input_filters.set("slow", InputFilter::parse("lag min=10 max=20").unwrap());
+    session
+}
+
+#[test]
+fn restores_modes_colors_and_rules() {
+    let profile = set_up().capture();
+
+    let mut restored = Session::new();
+    assert_eq!(restored.apply(&profile), Vec::<String>::new());
+    assert_eq!(restored.color, 0x1f);
+    assert!(restored.pranks.is_active("digit-limit"));
+    assert!(restored.pranks.is_active("substitute"));
+    assert!(!restored.pranks.is_active("color-roll"));
+    // It played when it was switched on, it doesn't again
+    assert!(!restored.pranks.is_active("progress"));
+    assert!(!restored.cmd.is_playing_response());
+    assert_eq!(restored.pranks.get_params("progress").unwrap()[0].1, "scan");
+    assert_eq!(restored.pranks.get_params("color-roll").unwrap()[0].1, "4");
+    assert_eq!(restored.pranks.get_params("substitute").unwrap()[1].1, "4");
+
+    // The filters are there, the mode's own one and the one set without a mode
+    let names: Vec<&str> = restored.cmd.input_filters.list().map(|f| f.0).collect();
+    assert_eq!(names, ["slow", "digit-limit"]);
+    assert_eq!(restored.cmd.input_filters.get("digit-limit"), Some(&InputFilter::parse("limit max=1").unwrap()));
+
+    restored.cmd.write_bytes(b"dir\n");
+    let mut ctx = PrankContext { cmd: &mut restored.cmd, visual_cmd: &mut restored.visual_cmd, focus_lost: &mut restored.focus_lost, raise_window: false };
+    restored.pranks.on_output(&mut ctx);
+    let mut screen = Screen::from_buffer(restored.cmd.get_stdout_buffer(), 0x07);
+    restored.pranks.render(&mut screen);
+    assert_eq!(&*screen.get_text(), "tree\n");
+    assert_eq!(restored.capture().filters, profile.filters);
+}
+
+#[test]
+fn command_line_wins_over_the_profile() {
+    let mut saved = set_up();
+    saved.cmd.input_filters.set("lag", InputFilter::parse("lag min=10 max=20").unwrap());
+    let mut profile = saved.capture();
+
+    // Started with --substitutions and --filter
+    let mut session = Session::new();
+    session.pranks = PrankModes::with_builtins(Rules::parse("dir => folder").unwrap());
+    session.cmd.input_filters.set("lag", InputFilter::parse("lag min=500 max=600").unwrap());
+    profile.leave_out(&["substitute".to_string(), "lag".to_string()]);
+    assert_eq!(session.apply(&profile), Vec::<String>::new());
+
+    assert_eq!(session.cmd.input_filters.get("lag"), Some(&InputFilter::parse("lag min=500 max=600").unwrap()));
+    assert_eq!(session.pranks.get_state("substitute").unwrap()[0].1, "dir => folder\n");
+    // The rest still comes from the profile
+    assert_eq!(session.color, 0x1f);
+    assert!(session.pranks.is_active("digit-limit"));
+    assert!(session.cmd.input_filters.get("slow").is_some());
+}
+
+#[test]
+fn json_round_trip() {
+    let profile = set_up().capture();
+    let value = profile.to_json();
+    assert_eq!(value["color"], "1f");
+    assert_eq!(value["filters"], json!([{ "name": "slow", "spec": "lag min=10 max=20" }]));
+    assert_eq!(Profile::from_json(&value).unwrap(), profile);
+
+    // Modes that are gone don't stop the rest
+    let mut session = Session::new();
+    let value = json!({ "color": "02", "modes": [{ "name": "gone", "active": true }, { "name": "scroll-up", "active": true }] });
+    let errors = session.apply(&Profile::from_json(&value).unwrap());
+    assert_eq!(errors, ["no such mode: gone"]);
+    assert!(session.pranks.is_active("scroll-up"));
+    assert_eq!(session.color, 0x02);
+
+    assert!(Profile::from_json(&json!({ "color": "zz" })).is_err());
+    assert!(Profile::from_json(&json!({ "color": "07", "filters": [{ "name": "x", "spec": "warp" }] })).is_err());
+    assert!(Profile::from_json(&json!({ "color": "07", "modes": [{ "name": "x", "state": [[1, 2]] }] })).is_err());
+}
+
+#[test]
+fn saves_and_loads_files() {
+    let dir = std::env::temp_dir().join(format!("wcmd-profile-{}", std::process::id()));
+    let path = dir.join("nested").join("work.json");
+    let profile = set_up().capture();
+    profile.save(&path).unwrap();
+    assert_eq!(Profile::load(&path).unwrap(), profile);
+    std::fs::write(&path, "{").unwrap();
+    assert!(Profile::load(&path).is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(get_profile_path("./mine.json"), Some(PathBuf::from("./mine.json")));
+    assert!(get_profile_path("work").unwrap().ends_with("WinCmd/profiles/work.json"));
+}
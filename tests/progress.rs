@@ -0,0 +1,94 @@
+use std::time::{Duration, Instant};
+
+use wcmd::cmd::Cmd;
+use wcmd::progress::{Progress, ProgressStyle};
+use wcmd::responses::{Responses, Rng};
+
+#[test]
+fn parses_progress() {
+    let progress = Progress::parse("scan 2.5 Checking file").unwrap();
+    assert_eq!(progress.style, ProgressStyle::Scan);
+    assert_eq!(progress.duration, Duration::from_millis(2500));
+    assert_eq!(progress.label.as_deref(), Some("Checking file"));
+
+    let progress = Progress::parse("blocks").unwrap();
+    assert_eq!(progress.duration, Duration::from_secs(5));
+    assert_eq!(progress.label, None);
+
+    assert!(Progress::parse("bogus 1").is_err());
+    assert!(Progress::parse("bar -1").is_err());
+    // Longer than an hour is a typo, and would be a lot of frames
+    assert_eq!(Progress::parse("bar 3600").unwrap().duration, Duration::from_secs(3600));
+    assert!(Progress::parse("bar 3600.5").is_err());
+    assert!(Progress::parse("bar 1e9").is_err());
+    assert!(Progress::parse("bar 1e20").is_err());
+    assert!(Progress::parse("bar NaN").is_err());
+    assert!(Progress::parse("bar inf").is_err());
+}
+
+#[test]
+fn frames_redraw_the_line() {
+    let mut rng = Rng::new(7);
+    let frames = Progress::new(ProgressStyle::Bar, Duration::from_secs(1)).frames(&mut rng).collect::<Vec<_>>();
+    assert_eq!(frames.len(), 11);
+    assert_eq!(frames[0], (Duration::ZERO, format!("\r[{}]   0%", " ".repeat(40))));
+    assert_eq!(frames[10], (Duration::from_millis(100), format!("\r[{}] 100%\n", "=".repeat(40))));
+    assert!(frames.iter().all(|f| f.1.starts_with('\r')));
+
+    let frames = Progress::new(ProgressStyle::Blocks, Duration::from_millis(200)).frames(&mut rng).collect::<Vec<_>>();
+    assert_eq!(frames[1].1, format!("\r{}{}  50%", "█".repeat(20), "░".repeat(20)));
+
+    let frames = Progress::new(ProgressStyle::Spinner, Duration::from_millis(300)).frames(&mut rng).collect::<Vec<_>>();
+    let frames: Vec<&str> = frames.iter().map(|f| f.1.as_str()).collect();
+    assert_eq!(frames, ["\rPlease wait... |", "\rPlease wait... /", "\rPlease wait... -", "\rPlease wait... done.\n"]);
+
+    let frames = Progress::new(ProgressStyle::Scan, Duration::from_millis(500)).frames(&mut rng).collect::<Vec<_>>();
+    assert!(frames[0].1.starts_with("\rScanning file 1 of "));
+    let total = frames[0].1.rsplit(' ').next().unwrap().trim_end_matches("...");
+    assert_eq!(frames[5].1, format!("\rScanning file {} of {}...\n", total, total));
+
+    // The frames of an hour aren't made up front
+    let mut frames = Progress::new(ProgressStyle::Bar, Duration::from_secs(3600)).frames(&mut rng);
+    assert_eq!(frames.size_hint(), (36001, Some(36001)));
+    assert_eq!(frames.nth(18000).unwrap().1, format!("\r[{}{}]  50%", "=".repeat(20), " ".repeat(20)));
+}
+
+#[test]
+fn carriage_return_writes_over_the_line() {
+    let mut cmd = Cmd::new();
+    cmd.write_text("first\nC:\\>dir\rab");
+    assert_eq!(&*cmd.get_stdout(), "first\nab\\>dir");
+    cmd.write_text("\rxyz!!!!\r\nnext");
+    assert_eq!(&*cmd.get_stdout(), "first\nxyz!!!!\nnext");
+}
+
+#[test]
+fn plays_in_the_window_and_aborts() {
+    let mut cmd = Cmd::new();
+    cmd.write_text("C:\\>");
+    cmd.start_progress(&Progress::new(ProgressStyle::Bar, Duration::from_millis(200)));
+    assert!(cmd.is_playing_response());
+    let start = Instant::now();
+    while cmd.is_playing_response() && start.elapsed() < Duration::from_secs(5) {
+        cmd.update();
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(&*cmd.get_stdout(), format!("C:\\>\n[{}] 100%\n", "=".repeat(40)));
+
+    cmd.start_progress(&Progress::new(ProgressStyle::Spinner, Duration::from_secs(10)));
+    cmd.update();
+    assert!(cmd.abort_response());
+    assert!(!cmd.abort_response());
+    assert!(cmd.get_stdout().ends_with("\nPlease wait... |^C\n"));
+}
+
+#[test]
+fn responses_can_show_progress() {
+    let responses = Responses::parse("== chkdsk\nChecking\n{progress blocks 0.2}Done\n").unwrap();
+    let mut playback = responses.find("chkdsk", &mut Rng::new(3)).unwrap();
+    let start = Instant::now();
+    assert_eq!(playback.take_due(start), format!("Checking\n\r{}   0%", "░".repeat(40)));
+    let text = playback.take_due(start + Duration::from_secs(1));
+    assert_eq!(text, format!("\r{}{}  50%\r{} 100%\nDone\n", "█".repeat(20), "░".repeat(20), "█".repeat(40)));
+    assert!(playback.is_done());
+}
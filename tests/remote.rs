@@ -0,0 +1,188 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::TcpStream,
+    time::{Duration, Instant},
+};
+
+use serde_json::{json, Value};
+use wcmd::backend::Loopback;
+use wcmd::cmd::Cmd;
+use wcmd::font::Font;
+use wcmd::prank::{PrankContext, PrankModes};
+use wcmd::remote::{self, ControlAddress, RemoteControl};
+use wcmd::render::VisualCommandLine;
+use wcmd::substitute::Rules;
+
+struct Session {
+    cmd: Cmd,
+    visual_cmd: VisualCommandLine,
+    focus_lost: bool,
+    pranks: PrankModes,
+    color: u8,
+}
+
+impl Session {
+    fn new() -> Self {
+        let mut cmd = Cmd::new();
+        cmd.attach_child(Some(Loopback::new()));
+        Self {
+            cmd,
+            visual_cmd: VisualCommandLine::new(Font::default()),
+            focus_lost: false,
+            pranks: PrankModes::with_builtins(Rules::default()),
+            color: 0x07,
+        }
+    }
+
+    fn handle(&mut self, request: Value) -> Value {
+        let mut ctx = PrankContext { cmd: &mut self.cmd, visual_cmd: &mut self.visual_cmd, focus_lost: &mut self.focus_lost, raise_window: false };
+        let reply = remote::handle(&request, &mut self.pranks, &mut ctx, &mut self.color);
+        self.cmd.update();
+        reply
+    }
+}
+
+#[test]
+fn addresses() {
+    assert_eq!(ControlAddress::parse("tcp:7777").unwrap(), ControlAddress::Tcp("127.0.0.1:7777".to_string()));
+    assert_eq!(ControlAddress::parse("tcp:localhost:1").unwrap(), ControlAddress::Tcp("localhost:1".to_string()));
+    assert_eq!(ControlAddress::parse("tcp:127.0.0.1:1").unwrap(), ControlAddress::Tcp("127.0.0.1:1".to_string()));
+    assert_eq!(ControlAddress::parse("/tmp/wcmd.sock").unwrap(), ControlAddress::Unix("/tmp/wcmd.sock".into()));
+    // Whoever connects can type into the shell
+    assert!(ControlAddress::parse("tcp:0.0.0.0:1").is_err());
+    assert!(ControlAddress::parse("tcp:192.168.1.2:1").is_err());
+}
+
+#[test]
+fn requests() {
+    let mut session = Session::new();
+    assert_eq!(session.handle(json!({ "cmd": "output", "text": "C:\\>" })), json!({ "ok": true }));
+    session.handle(json!({ "cmd": "type", "text": "dir\n" }));
+    assert_eq!(session.handle(json!({ "cmd": "screen" })), json!({ "ok": true, "text": "C:\\>dir\ndir\n" }));
+    assert_eq!(session.handle(json!({ "cmd": "screen", "lines": 2 }))["text"], "dir\n");
+
+    session.handle(json!({ "cmd": "mode", "name": "scroll-up" }));
+    assert!(session.pranks.is_active("scroll-up"));
+    session.handle(json!({ "cmd": "mode", "name": "scroll-up", "active": true }));
+    assert!(session.pranks.is_active("scroll-up"));
+    session.handle(json!({ "cmd": "param", "mode": "digit-limit", "name": "max", "value": "1" }));
+    let modes = session.handle(json!({ "cmd": "modes" }));
+    assert_eq!(modes["modes"][2]["params"]["max"], "1");
+    assert_eq!(modes["modes"][5]["active"], true);
+    assert_eq!(modes["modes"][5]["key"], "Ctrl+Shift+F6");
+
+    session.handle(json!({ "cmd": "color", "value": "1F" }));
+    assert_eq!(session.color, 0x1f);
+    assert_eq!(session.handle(json!({ "cmd": "color", "value": "123" }))["ok"], false);
+
+    session.handle(json!({ "cmd": "substitute", "rule": "dir => tree", "active": true }));
+    session.handle(json!({ "cmd": "output", "text": "dir\n" }));
+    session.pranks.on_output(&mut PrankContext { cmd: &mut session.cmd, visual_cmd: &mut session.visual_cmd, focus_lost: &mut session.focus_lost, raise_window: false });
+    // The screen is what the window shows, the output keeps what was really printed
+    assert_eq!(session.handle(json!({ "cmd": "screen", "lines": 2 }))["text"], "tree\n");
+    assert!(session.cmd.get_stdout().ends_with("dir\n"));
+    assert!(session.pranks.is_active("substitute"));
+
+    assert_eq!(session.handle(json!({ "cmd": "progress", "abort": true }))["error"], "nothing is playing");
+    session.handle(json!({ "cmd": "progress", "style": "spinner", "seconds": 10, "label": "Copying" }));
+    assert!(session.cmd.is_playing_response());
+    assert_eq!(session.handle(json!({ "cmd": "progress", "abort": true })), json!({ "ok": true }));
+    assert!(session.handle(json!({ "cmd": "progress", "style": "dots" }))["error"].as_str().unwrap().starts_with("unknown progress style"));
+
+    session.handle(json!({ "cmd": "filter", "spec": "limit max=1", "name": "one-digit" }));
+    assert_eq!(session.handle(json!({ "cmd": "filters" }))["filters"], json!([{ "name": "one-digit", "spec": "limit max=1 class=digits" }]));
+    session.handle(json!({ "cmd": "type", "text": "12" }));
+    assert_eq!(session.cmd.get_stdin(), "1");
+    assert_eq!(session.handle(json!({ "cmd": "filter", "name": "one-digit", "remove": true })), json!({ "ok": true }));
+    assert_eq!(session.handle(json!({ "cmd": "filter", "name": "one-digit", "remove": true }))["error"], "no such input filter: one-digit");
+    assert_eq!(session.handle(json!({ "cmd": "filter", "spec": "warp" }))["error"], "unknown input filter: warp");
+
+    // Typing goes through the modes like keys in the window do
+    session.handle(json!({ "cmd": "param", "mode": "digit-limit", "name": "max", "value": "2" }));
+    session.handle(json!({ "cmd": "mode", "name": "digit-limit", "active": true }));
+    session.handle(json!({ "cmd": "type", "text": "x2345\n" }));
+    // The 1 typed earlier is still on the line and counts
+    assert!(session.cmd.get_stdout().ends_with("\n1x2\n1x2\n"));
+    session.focus_lost = true;
+    session.handle(json!({ "cmd": "type", "text": "lost\n" }));
+    assert_eq!(session.cmd.get_stdin(), "");
+    session.focus_lost = false;
+
+    assert_eq!(session.handle(json!({ "cmd": "fly" })), json!({ "ok": false, "error": "unknown command: fly" }));
+    assert_eq!(session.handle(json!({ "cmd": "mode", "name": "nope" }))["error"], "no such mode: nope");
+    assert_eq!(session.handle(json!({ "text": "x" }))["error"], "missing \"cmd\"");
+}
+
+// Answers requests like the window does until `done` says to stop
+fn run_server(remote: &RemoteControl, session: &mut Session, done: impl Fn() -> bool) {
+    let start = Instant::now();
+    while !done() && start.elapsed() < Duration::from_secs(5) {
+        for request in remote.poll() {
+            let reply = session.handle(request.request.clone());
+            request.respond(reply);
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    }
+}
+
+#[test]
+fn over_tcp() {
+    let remote = RemoteControl::bind(&ControlAddress::parse("tcp:0").unwrap(), None).unwrap();
+    let address = remote.get_address().to_string();
+    let client = std::thread::spawn(move || {
+        let mut stream = TcpStream::connect(address).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut reply = String::new();
+        stream.write_all(b"not json\n{\"cmd\": \"output\", \"text\": \"hi\"}\n{\"cmd\": \"screen\"}\n").unwrap();
+        let mut replies = vec![];
+        for _ in 0..3 {
+            reply.clear();
+            reader.read_line(&mut reply).unwrap();
+            replies.push(serde_json::from_str::<Value>(&reply).unwrap());
+        }
+        replies
+    });
+
+    let mut session = Session::new();
+    run_server(&remote, &mut session, || client.is_finished());
+    let replies = client.join().unwrap();
+    assert_eq!(replies[0]["ok"], false);
+    assert_eq!(replies[1], json!({ "ok": true }));
+    assert_eq!(replies[2]["text"], "hi");
+}
+
+#[cfg(unix)]
+#[test]
+fn over_unix_socket() {
+    use std::os::unix::net::UnixStream;
+
+    let path = std::env::temp_dir().join(format!("wcmd-control-{}.sock", std::process::id()));
+    let remote = RemoteControl::bind(&ControlAddress::Unix(path.clone()), None).unwrap();
+    let client_path = path.clone();
+    let client = std::thread::spawn(move || {
+        let mut stream = UnixStream::connect(client_path).unwrap();
+        stream.write_all(b"{\"cmd\": \"type\", \"text\": \"ver\\n\"}\n").unwrap();
+        let mut reply = String::new();
+        BufReader::new(stream).read_line(&mut reply).unwrap();
+        reply
+    });
+
+    let mut session = Session::new();
+    run_server(&remote, &mut session, || client.is_finished());
+    assert_eq!(client.join().unwrap().trim(), "{\"ok\":true}");
+    assert_eq!(&*session.cmd.get_stdout(), "ver\nver\n");
+
+    drop(remote);
+    assert!(!path.exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn leaves_files_that_are_not_sockets() {
+    let path = std::env::temp_dir().join(format!("wcmd-control-{}.txt", std::process::id()));
+    std::fs::write(&path, "notes").unwrap();
+    let error = RemoteControl::bind(&ControlAddress::Unix(path.clone()), None).err().unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::AlreadyExists);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "notes");
+    std::fs::remove_file(&path).unwrap();
+}
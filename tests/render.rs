@@ -0,0 +1,115 @@
+// Golden image tests for the renderer, run with UPDATE_GOLDEN=1 to regenerate the images in tests/golden
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use wcmd::backend::BackendKind;
+use wcmd::bitmap::Bitmap;
+use wcmd::chrome::WindowsVersion;
+use wcmd::font::Font;
+use wcmd::framebuffer::FramebufferRenderer;
+use wcmd::headless::{render_to_framebuffer, run_headless};
+use wcmd::render::{ConsoleStyle, Renderer, VisualCommandLine};
+use wcmd::screen::Screen;
+use wcmd::textdump::TextRenderer;
+
+fn font_sheet() -> Bitmap {
+    Bitmap::load_bmp(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("font.bmp")).unwrap()
+}
+
+fn screen(text: &str, color: u8) -> Screen {
+    let mut screen = Screen::new(color);
+    screen.set_text(text.to_string());
+    screen
+}
+
+fn read_png(path: &PathBuf) -> (u32, u32, Vec<u8>) {
+    let decoder = png::Decoder::new(std::fs::File::open(path).unwrap());
+    let mut reader = decoder.read_info().unwrap();
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).unwrap();
+    buf.truncate(info.buffer_size());
+    (info.width, info.height, buf)
+}
+
+fn assert_golden(name: &str, renderer: &FramebufferRenderer) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden").join(format!("{}.png", name));
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        renderer.write_png(&path).unwrap();
+        return;
+    }
+
+    let (width, height, pixels) = read_png(&path);
+    assert_eq!((width, height), renderer.get_size(), "{}: size differs", name);
+    if pixels != renderer.get_pixels() {
+        let actual = path.with_extension("actual.png");
+        renderer.write_png(&actual).unwrap();
+        panic!("{}: rendering differs from the golden image, see {:?}", name, actual);
+    }
+}
+
+#[test]
+fn font_layout() {
+    let text = "C:\\Users\\wcmd>dir\r\n\tTabbed\r\nA line long enough to wrap over the right edge of the window\r\n\u{2554}\u{2550}\u{2557}\u{263a}";
+    assert_golden("font_layout", &render_to_framebuffer(font_sheet(), &screen(text, 0x07), (40, 6), ConsoleStyle::Win10));
+}
+
+#[test]
+fn colors() {
+    assert_golden("colors", &render_to_framebuffer(font_sheet(), &screen("Yellow on blue", 0x1e), (20, 2), ConsoleStyle::Win10));
+}
+
+#[test]
+fn scrollbar() {
+    let text = (0..20).map(|i| format!("Line {}\n", i)).collect::<String>();
+    assert_golden("scrollbar", &render_to_framebuffer(font_sheet(), &screen(&text, 0x07), (20, 8), ConsoleStyle::Win10));
+}
+
+#[test]
+fn caret() {
+    assert_golden("caret", &render_to_framebuffer(font_sheet(), &screen("C:\\>", 0x0a), (10, 2), ConsoleStyle::Win10));
+}
+
+#[test]
+fn windows_11_style() {
+    let text = (0..20).map(|i| format!("Line {}\n", i)).collect::<String>() + "C:\\>";
+    assert_golden("windows_11", &render_to_framebuffer(font_sheet(), &screen(&text, 0x07), (20, 8), ConsoleStyle::Win11));
+}
+
+// What's left running in the background keeps the output open, it's captured anyway
+#[cfg(unix)]
+#[test]
+fn headless_screenshot_stops_at_the_deadline() {
+    let command = ["sh", "-c", "echo started; sleep 60 &"].map(String::from);
+    let start = Instant::now();
+    let cmd = run_headless(&BackendKind::Process, &command, (50, 6), Some(WindowsVersion::Win10), Duration::from_millis(200)).unwrap();
+    assert!(start.elapsed() < Duration::from_secs(5));
+
+    let screen = Screen::from_buffer(cmd.get_stdout_buffer(), 0x07);
+    assert!(screen.get_text().ends_with("\nstarted\n"));
+    assert_golden("headless", &render_to_framebuffer(font_sheet(), &screen, (50, 6), WindowsVersion::Win10.get_style()));
+}
+
+#[test]
+fn text_dump() {
+    let font = Font::default();
+    let screen = screen("C:\\>echo hi\r\nhi\r\n\r\nC:\\>", 0x07);
+    let mut renderer = TextRenderer::new((20, 4), &font);
+    let mut visual_cmd = VisualCommandLine::new(font);
+    visual_cmd.update(renderer.get_size(), &screen);
+    visual_cmd.render(&mut renderer, &screen, None);
+
+    assert_eq!(renderer.get_text(), "C:\\>echo hi\nhi\n\nC:\\>");
+    assert_eq!(renderer.get_cursor(), Some((4, 3)));
+}
+
+#[test]
+fn text_dump_wraps() {
+    let font = Font::default();
+    let screen = screen("0123456789abcdef", 0x07);
+    let mut renderer = TextRenderer::new((10, 3), &font);
+    let mut visual_cmd = VisualCommandLine::new(font);
+    visual_cmd.update(renderer.get_size(), &screen);
+    visual_cmd.render(&mut renderer, &screen, None);
+
+    assert_eq!(renderer.get_text(), "0123456789\nabcdef\n");
+}
@@ -0,0 +1,110 @@
+use std::time::{Duration, Instant};
+
+use wcmd::backend::Loopback;
+use wcmd::cmd::Cmd;
+use wcmd::responses::{Responses, Rng};
+
+const SCRIPT: &str = "\
+# comments before the first response are skipped
+== netstat( -an)?
+@line 50
+Active Connections
+  TCP    10.0.0.{random 2-9}:445
+== echo (.*)
+{1}!
+== slow
+@char 10
+ab{pause 100}c
+";
+
+#[test]
+fn parses_and_fills_in_templates() {
+    let responses = Responses::parse(SCRIPT).unwrap();
+    assert_eq!(responses.len(), 3);
+    let mut rng = Rng::new(42);
+
+    assert!(responses.find("dir", &mut rng).is_none());
+    let mut playback = responses.find("  ECHO hello there ", &mut rng).unwrap();
+    assert_eq!(playback.take_due(Instant::now()), "hello there!\n");
+    assert!(playback.is_done());
+
+    let mut playback = responses.find("netstat -an", &mut rng).unwrap();
+    let text = playback.take_due(Instant::now() + Duration::from_secs(1));
+    let last = text.lines().last().unwrap();
+    let octet: u32 = last.rsplit('.').next().unwrap().trim_end_matches(":445").parse().unwrap();
+    assert!((2..=9).contains(&octet));
+
+    // Braces that aren't fields are left as they are
+    let responses = Responses::parse("== braces\n{nope} {1 {oops\nabc {oops\n").unwrap();
+    let mut playback = responses.find("braces", &mut rng).unwrap();
+    assert_eq!(playback.take_due(Instant::now()), "{nope} {1 {oops\nabc {oops\n");
+
+    assert!(Responses::parse("no header").is_err());
+    assert!(Responses::parse("== x\n@bogus 1\n").is_err());
+    assert!(Responses::parse("== (\n").is_err());
+}
+
+#[test]
+fn streams_with_delays() {
+    let responses = Responses::parse(SCRIPT).unwrap();
+    let mut rng = Rng::new(1);
+
+    let mut playback = responses.find("netstat", &mut rng).unwrap();
+    let start = Instant::now();
+    assert_eq!(playback.take_due(start), "Active Connections\n");
+    assert_eq!(playback.take_due(start + Duration::from_millis(20)), "");
+    assert!(playback.take_due(start + Duration::from_millis(60)).starts_with("  TCP"));
+    assert!(playback.is_done());
+    assert_eq!(playback.get_deadline(), None);
+
+    let mut playback = responses.find("slow", &mut rng).unwrap();
+    let start = Instant::now();
+    assert_eq!(playback.take_due(start), "a");
+    assert_eq!(playback.take_due(start + Duration::from_millis(15)), "b");
+    // The pause comes on top of the usual delay
+    assert_eq!(playback.take_due(start + Duration::from_millis(60)), "");
+    assert_eq!(playback.take_due(start + Duration::from_millis(125)), "c");
+    assert_eq!(playback.take_due(start + Duration::from_millis(140)), "\n");
+}
+
+#[test]
+fn matching_lines_never_reach_the_child() {
+    let mut cmd = Cmd::new();
+    cmd.attach_child(Some(Loopback::new()));
+    cmd.set_responses(Some(Responses::parse(SCRIPT).unwrap()));
+
+    for c in "echo hi".chars() {
+        cmd.put_stdin(c);
+    }
+    cmd.put_stdout('\n');
+    cmd.flush_stdin();
+    assert!(cmd.is_playing_response());
+    cmd.update();
+    // The loopback only sees the empty line sent afterwards to get a prompt back
+    cmd.update();
+    assert!(!cmd.is_playing_response());
+    assert_eq!(&*cmd.get_stdout(), "echo hi\nhi!\n\n");
+
+    for c in "dir".chars() {
+        cmd.put_stdin(c);
+    }
+    cmd.put_stdout('\n');
+    cmd.flush_stdin();
+    cmd.update();
+    assert_eq!(&*cmd.get_stdout(), "echo hi\nhi!\n\ndir\ndir\n");
+}
+
+#[test]
+fn interrupt_stops_playback() {
+    let mut cmd = Cmd::new();
+    cmd.attach_child(Some(Loopback::new()));
+    cmd.set_responses(Some(Responses::parse("== w\n@wait 10000\nnever\n").unwrap()));
+    cmd.put_stdin('w');
+    cmd.flush_stdin();
+    cmd.update();
+    assert!(cmd.get_deadline().is_some());
+    cmd.signal_child(wcmd::backend::Signal::Interrupt);
+    cmd.update();
+    assert!(!cmd.is_playing_response());
+    assert!(cmd.get_stdout().ends_with("^C\n\n"));
+}
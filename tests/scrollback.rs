@@ -0,0 +1,109 @@
+use wcmd::scrollback::{Scrollback, ScrollbackLimit};
+
+fn lines(n: usize) -> String {
+    (0..n).map(|i| format!("line {}\n", i)).collect()
+}
+
+#[test]
+fn trims_whole_lines_and_keeps_offsets() {
+    let mut buffer = Scrollback::new(ScrollbackLimit::Lines(8));
+    buffer.push_str(&lines(8));
+    buffer.trim();
+    // Within the slack nothing is dropped
+    assert_eq!(buffer.get_base(), 0);
+
+    buffer.push_str(&lines(100));
+    let end = buffer.get_end();
+    buffer.trim();
+    assert_eq!(buffer.line_count(), 8);
+    assert!(buffer.as_str().starts_with("line 93\n"));
+    assert_eq!(buffer.get_end(), end);
+    assert_eq!(buffer.get_base() + buffer.as_str().len(), end);
+    assert_eq!(buffer.get_from(0), buffer.as_str());
+    assert_eq!(buffer.get_from(end - 8), "line 99\n");
+    assert_eq!(buffer.get_line_start(end - 3), end - 8);
+
+    // Trimmed again and again, the text stays the same
+    for i in 0..1000 {
+        buffer.push_str(&format!("more {}\n", i));
+        buffer.trim();
+    }
+    assert!(buffer.as_str().ends_with("more 998\nmore 999\n"));
+    assert!(buffer.line_count() <= 10);
+}
+
+#[test]
+fn endless_line_is_capped_in_bytes() {
+    let mut buffer = Scrollback::new(ScrollbackLimit::Lines(100));
+    buffer.set_max_bytes(64);
+    buffer.push_str("first\nsecond\n");
+    for i in 0..1000 {
+        buffer.push_str(&format!("\r{}%", i % 100));
+        buffer.trim();
+    }
+    assert!(buffer.as_str().len() <= 64 + 8);
+    assert!(buffer.as_str().ends_with("\r99%"));
+    assert_eq!(buffer.line_count(), 1);
+
+    // Short lines are still dropped whole
+    let mut buffer = Scrollback::new(ScrollbackLimit::Lines(100));
+    buffer.set_max_bytes(20);
+    buffer.push_str("ab\ncd\nef\ngh\nij\nkl\nmn\nop\nqr\n");
+    buffer.trim();
+    assert_eq!(buffer.as_str(), "gh\nij\nkl\nmn\nop\nqr\n");
+
+    // Unlimited means unlimited
+    let mut buffer = Scrollback::new(ScrollbackLimit::Unlimited);
+    buffer.set_max_bytes(4);
+    buffer.push_str("0123456789");
+    buffer.trim();
+    assert_eq!(buffer.as_str(), "0123456789");
+}
+
+#[test]
+fn byte_cap_cuts_between_chars() {
+    let mut buffer = Scrollback::new(ScrollbackLimit::Lines(100));
+    buffer.set_max_bytes(5);
+    buffer.push_str("ääääää");
+    buffer.trim();
+    assert_eq!(buffer.as_str(), "ää");
+}
+
+#[test]
+fn trimmed_lines_spill_to_file() {
+    let path = std::env::temp_dir().join(format!("wcmd-scrollback-spill-{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let mut buffer = Scrollback::new(ScrollbackLimit::Spill(4, path.clone()));
+    let text = lines(50);
+    buffer.push_str(&text);
+    buffer.trim();
+    buffer.push_str("last\n");
+    buffer.trim();
+
+    // Nothing is lost, it's either in the file or still in memory
+    let spilled = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(spilled.len(), buffer.get_base());
+    assert_eq!(format!("{}{}", spilled, buffer.as_str()), format!("{}last\n", text));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn pop_stops_at_what_was_trimmed() {
+    let mut buffer = Scrollback::new(ScrollbackLimit::Lines(2));
+    buffer.push_str("a\nb\nc\nd");
+    buffer.trim();
+    assert_eq!(buffer.as_str(), "c\nd");
+
+    let mark = buffer.mark();
+    assert_eq!(buffer.pop(), Some('d'));
+    assert_eq!(buffer.get_changed_since(&mark), Some(buffer.get_end()));
+    assert_eq!(buffer.pop(), Some('\n'));
+    assert_eq!(buffer.line_count(), 1);
+    assert_eq!(buffer.pop(), Some('c'));
+    assert_eq!(buffer.pop(), None);
+    assert_eq!(buffer.as_str(), "");
+
+    buffer.push_str("e\nf");
+    assert_eq!(buffer.as_str(), "e\nf");
+    assert_eq!(buffer.get_line_start(buffer.get_end()), buffer.get_end() - 1);
+}
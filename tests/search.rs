@@ -0,0 +1,121 @@
+use wcmd::scrollback::{Scrollback, ScrollbackLimit};
+use wcmd::search::{Search, SearchMode};
+
+fn buffer(text: &str) -> Scrollback {
+    let mut buffer = Scrollback::new(ScrollbackLimit::Unlimited);
+    buffer.push_str(text);
+    buffer
+}
+
+fn search(query: &str, buffer: &Scrollback) -> Search {
+    let mut search = Search::new();
+    for c in query.chars() {
+        search.push(c, buffer);
+    }
+    search
+}
+
+#[test]
+fn plain_and_regex() {
+    let buffer = buffer("dir\nfile1.txt\nfile22.txt\n");
+    let mut s = search("file", &buffer);
+    assert_eq!(s.get_matches(), [(4, 8), (14, 18)]);
+    // Starts from the most recent output
+    assert_eq!(s.get_current(), Some((14, 18)));
+    assert_eq!(s.status(), "2/2");
+
+    s.toggle_mode(&buffer);
+    assert_eq!(s.get_mode(), SearchMode::Regex);
+    s.pop(&buffer);
+    for c in "e\\d+".chars() {
+        s.push(c, &buffer);
+    }
+    assert_eq!(s.get_matches(), [(4, 9), (14, 20)]);
+
+    // Anchors and empty matches
+    let s = {
+        let mut s = search("^d|x*", &buffer);
+        s.toggle_mode(&buffer);
+        s
+    };
+    assert_eq!(s.get_matches(), [(0, 1), (11, 12), (22, 23)]);
+}
+
+#[test]
+fn next_and_prev_wrap_around() {
+    let buffer = buffer("a b a b a");
+    let mut s = search("a", &buffer);
+    assert_eq!(s.get_current(), Some((8, 9)));
+    assert_eq!(s.next_match(), Some((0, 1)));
+    assert_eq!(s.next_match(), Some((4, 5)));
+    assert_eq!(s.prev_match(), Some((0, 1)));
+    assert_eq!(s.prev_match(), Some((8, 9)));
+    assert_eq!(s.status(), "3/3");
+
+    let mut s = search("z", &buffer);
+    assert_eq!(s.next_match(), None);
+    assert_eq!(s.status(), "No matches");
+    assert_eq!(Search::new().status(), "");
+}
+
+#[test]
+fn invalid_regex_is_reported() {
+    let buffer = buffer("(x)");
+    let mut s = search("(x", &buffer);
+    assert_eq!(s.get_matches(), [(0, 2)]);
+    s.toggle_mode(&buffer);
+    assert!(s.get_matches().is_empty());
+    assert_eq!(s.status(), "error: unclosed group");
+    s.push(')', &buffer);
+    assert_eq!(s.status(), "1/1");
+}
+
+#[test]
+fn only_new_output_is_searched_and_trimming_shifts_matches() {
+    let mut buffer = Scrollback::new(ScrollbackLimit::Lines(3));
+    buffer.push_str("one x\ntwo x\n");
+    let mut s = search("x", &buffer);
+    assert_eq!(s.get_matches(), [(4, 5), (10, 11)]);
+    s.next_match();
+    assert_eq!(s.get_current(), Some((4, 5)));
+
+    // A match split over two writes is still found
+    let end = buffer.get_end();
+    buffer.push_str("three ");
+    s.update_from(&buffer, end);
+    buffer.push_str("x\n");
+    s.update_from(&buffer, end + 6);
+    assert_eq!(s.get_matches(), [(4, 5), (10, 11), (18, 19)]);
+
+    // The oldest lines go, the selection moves to the closest match left
+    let end = buffer.get_end();
+    buffer.push_str("four\n");
+    buffer.trim();
+    s.update_from(&buffer, end);
+    assert_eq!(buffer.as_str(), "three x\nfour\n");
+    assert_eq!(s.get_matches(), [(6, 7)]);
+    assert_eq!(s.get_current(), Some((6, 7)));
+
+    // Erased text loses its match
+    for _ in 0..7 {
+        buffer.pop();
+    }
+    s.update_from(&buffer, buffer.get_end());
+    assert_eq!(buffer.as_str(), "three ");
+    assert!(s.get_matches().is_empty());
+    assert_eq!(s.status(), "No matches");
+}
+
+#[test]
+fn catches_up_with_whatever_changed() {
+    let mut shown = buffer("dir\n");
+    let mut search = Search::new();
+    for c in "dir".chars() {
+        search.push(c, &shown);
+    }
+    shown.push_str("dir dir\n");
+    search.update_changed(&shown);
+    assert_eq!(search.get_matches(), &[(0, 3), (4, 7), (8, 11)]);
+    search.update_changed(&shown);
+    assert_eq!(search.get_matches().len(), 3);
+}
@@ -0,0 +1,110 @@
+#![cfg(unix)]
+use std::{ffi::CStr, fs::File, io::{Read, Write}, os::unix::io::FromRawFd, path::PathBuf, time::{Duration, Instant}};
+
+use wcmd::backend::{self, BackendKind};
+use wcmd::cmd::{Cmd, CmdEvent};
+use wcmd::options::Options;
+use wcmd::serial::{FlowControl, LineEnding, Parity, SerialConfig};
+
+// A local pseudo terminal pair standing in for a board on the other end of a cable,
+// returns the master and the path of the slave device
+fn fake_port() -> (File, PathBuf) {
+    let (mut master, mut slave) = (0, 0);
+    let mut name = [0 as libc::c_char; 128];
+    assert_eq!(unsafe { libc::openpty(&mut master, &mut slave, name.as_mut_ptr(), std::ptr::null(), std::ptr::null()) }, 0);
+    let path = unsafe { CStr::from_ptr(name.as_ptr()) }.to_str().unwrap().to_string();
+    // The slave stays open through the path, this copy isn't needed
+    unsafe { libc::close(slave) };
+    let master = unsafe { File::from_raw_fd(master) };
+    (master, PathBuf::from(path))
+}
+
+fn wait_for(cmd: &mut Cmd, text: &str) {
+    let start = Instant::now();
+    while !cmd.get_stdout().contains(text) {
+        assert!(start.elapsed() < Duration::from_secs(5), "{:?} never showed up in {:?}", text, &*cmd.get_stdout());
+        cmd.update();
+        std::thread::sleep(Duration::from_millis(5));
+    }
+}
+
+#[test]
+fn talks_to_the_device() {
+    let (mut board, path) = fake_port();
+    let mut config = SerialConfig::new(path);
+    config.baud = 9600;
+    config.parity = Parity::Even;
+    config.flow = FlowControl::Software;
+
+    let mut cmd = Cmd::new();
+    cmd.attach_backend(Some(backend::spawn(&BackendKind::Serial(config), &[], (80, 25)).unwrap()));
+
+    board.write_all(b"U-Boot 2023.04\r\n=> ").unwrap();
+    wait_for(&mut cmd, "=> ");
+
+    // The board echoes, keys go out as they're typed and Enter is a carriage return
+    assert!(cmd.is_remote_echo());
+    for c in "help".chars() {
+        cmd.put_stdin(c);
+    }
+    assert!(cmd.get_stdout().ends_with("=> "));
+    cmd.flush_stdin();
+    let mut received = [0; 5];
+    board.read_exact(&mut received).unwrap();
+    assert_eq!(&received, b"help\r");
+
+    cmd.destroy_child();
+    let start = Instant::now();
+    loop {
+        cmd.update();
+        if cmd.drain_events().iter().any(|e| matches!(e, CmdEvent::ChildExited)) {
+            break;
+        }
+        assert!(start.elapsed() < Duration::from_secs(5), "serial port never closed");
+        std::thread::sleep(Duration::from_millis(5));
+    }
+}
+
+#[test]
+fn local_echo_sends_whole_lines() {
+    let (mut board, path) = fake_port();
+    let mut config = SerialConfig::new(path);
+    config.local_echo = true;
+    config.eol = LineEnding::CrLf;
+
+    let mut cmd = Cmd::new();
+    cmd.attach_backend(Some(backend::spawn(&BackendKind::Serial(config), &[], (80, 25)).unwrap()));
+    assert!(!cmd.is_remote_echo());
+    for c in "AT".chars() {
+        cmd.put_stdin(c);
+    }
+    assert_eq!(&*cmd.get_stdout(), "AT");
+    cmd.flush_stdin();
+    let mut received = [0; 4];
+    board.read_exact(&mut received).unwrap();
+    assert_eq!(&received, b"AT\r\n");
+}
+
+#[test]
+fn rejects_unknown_baud_rates() {
+    let (_board, path) = fake_port();
+    let mut config = SerialConfig::new(path);
+    config.baud = 12345;
+    assert!(backend::spawn(&BackendKind::Serial(config), &[], (80, 25)).is_err());
+}
+
+#[test]
+fn options() {
+    let args = ["wcmd", "--baud", "57600", "--serial", "/dev/ttyUSB0", "--parity", "odd", "--stop-bits", "2", "--flow", "rtscts",
+        "--serial-echo", "local", "--serial-eol", "lf"];
+    let options = Options::from_args(args.iter().map(|s| s.to_string())).unwrap();
+    let mut expected = SerialConfig::new(PathBuf::from("/dev/ttyUSB0"));
+    expected.baud = 57600;
+    expected.parity = Parity::Odd;
+    expected.stop_bits = 2;
+    expected.flow = FlowControl::Hardware;
+    expected.local_echo = true;
+    expected.eol = LineEnding::Lf;
+    assert_eq!(options.backend, BackendKind::Serial(expected));
+    assert!(Options::from_args(["wcmd", "--serial-eol", "nul"].iter().map(|s| s.to_string())).is_err());
+}
@@ -0,0 +1,118 @@
+use std::time::{Duration, SystemTime};
+
+use wcmd::cmd::Cmd;
+use wcmd::substitute::{Rule, Rules, Substituter};
+
+#[test]
+fn default_rules_match_the_old_replacements() {
+    let rules = Rules::default();
+    assert_eq!(rules.apply("Foreign ESTABLISHED REFUND Refund refund", ""), "Trusted SECURE AIRPLANE Airplane airplane");
+}
+
+#[test]
+fn rule_options() {
+    let rule = Rule::parse(r"[regex] (\d+)\.(\d+)\.\d+\.\d+ => $1.$2.0.1").unwrap();
+    assert_eq!(rule.apply("from 192.168.4.20:80"), "from 192.168.0.1:80");
+
+    // Without `regex` the pattern and the replacement are taken literally
+    let rule = Rule::parse("a.b => $1").unwrap();
+    assert_eq!(rule.apply("a.b axb"), "$1 axb");
+
+    let rule = Rule::parse("[case, cmd=netstat] foreign => trusted").unwrap();
+    assert_eq!(rule.apply("FOREIGN Foreign foreign"), "TRUSTED Trusted trusted");
+    assert!(rule.applies_to("netstat"));
+    assert!(!rule.applies_to("dir"));
+
+    assert!(Rule::parse("[bogus] a => b").is_err());
+    assert!(Rule::parse("no arrow").is_err());
+    assert!(Rules::parse("# comment\n\na => b\nbroken").err().unwrap().starts_with("line 4"));
+}
+
+#[test]
+fn only_new_output_is_rewritten() {
+    let mut cmd = Cmd::new();
+    let rules = Rules::parse("[cmd=netstat] Foreign => Trusted\nx => xx").unwrap();
+    let mut substituter = Substituter::new(rules);
+    assert!(substituter.get_display().is_none());
+
+    cmd.write_bytes(b"Foreign x\n");
+    substituter.start(&cmd.get_stdout_buffer().borrow());
+    let display = substituter.get_display().unwrap();
+
+    substituter.set_command("NETSTAT.EXE -an");
+    assert_eq!(substituter.get_command(), "netstat");
+    // Split between reads and updated in between, still only replaced once
+    cmd.write_bytes(b"For");
+    substituter.update(&cmd.get_stdout_buffer().borrow());
+    cmd.write_bytes(b"eign x");
+    substituter.update(&cmd.get_stdout_buffer().borrow());
+    substituter.update(&cmd.get_stdout_buffer().borrow());
+    cmd.write_bytes(b"\nx\n");
+    substituter.update(&cmd.get_stdout_buffer().borrow());
+    assert_eq!(display.borrow().as_str(), "Foreign x\nTrusted xx\nxx\n");
+
+    substituter.set_command("dir");
+    cmd.write_bytes(b"Foreign\n");
+    substituter.update(&cmd.get_stdout_buffer().borrow());
+    assert_eq!(display.borrow().as_str(), "Foreign x\nTrusted xx\nxx\nForeign\n");
+    // The output itself is left alone
+    assert_eq!(&*cmd.get_stdout(), "Foreign x\nForeign x\nx\nForeign\n");
+}
+
+#[test]
+fn edits_and_clearing_are_followed() {
+    let mut cmd = Cmd::new();
+    let mut substituter = Substituter::new(Rules::default());
+    cmd.write_stdout("C:\\>");
+    substituter.start(&cmd.get_stdout_buffer().borrow());
+    let display = substituter.get_display().unwrap();
+
+    // Typing is shown replaced, erasing takes the real text back
+    for c in "refund".chars() {
+        cmd.put_stdin(c);
+    }
+    substituter.update(&cmd.get_stdout_buffer().borrow());
+    assert_eq!(display.borrow().as_str(), "C:\\>airplane");
+    cmd.pop_stdin();
+    substituter.update(&cmd.get_stdout_buffer().borrow());
+    assert_eq!(display.borrow().as_str(), "C:\\>refun");
+    assert_eq!(&*cmd.get_stdout(), "C:\\>refun");
+
+    cmd.write_text("\nForeign\nC:\\>\x08\x08\n\x08");
+    substituter.update(&cmd.get_stdout_buffer().borrow());
+    assert_eq!(display.borrow().as_str(), "C:\\>refun\nTrusted\nC:");
+
+    cmd.clear();
+    cmd.write_stdout("ESTABLISHED");
+    substituter.update(&cmd.get_stdout_buffer().borrow());
+    assert_eq!(display.borrow().as_str(), "SECURE");
+
+    substituter.stop();
+    assert!(substituter.get_display().is_none());
+}
+
+#[test]
+fn rules_file_is_reloaded_when_it_changes() {
+    let path = std::env::temp_dir().join(format!("wcmd-substitutions-{}.txt", std::process::id()));
+    std::fs::write(&path, "a => b\n").unwrap();
+    let mut rules = Rules::load(&path).unwrap();
+    assert_eq!(rules.apply("a", ""), "b");
+    assert!(!rules.reload_if_changed());
+
+    let touch = |text: &str, secs| {
+        std::fs::write(&path, text).unwrap();
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(secs)).unwrap();
+    };
+    touch("a => c\n", 10);
+    assert!(rules.reload_if_changed());
+    assert_eq!(rules.apply("a", ""), "c");
+
+    // A broken edit keeps what was there
+    touch("a =", 20);
+    assert!(!rules.reload_if_changed());
+    assert_eq!(rules.apply("a", ""), "c");
+    assert_eq!(rules.len(), 1);
+
+    std::fs::remove_file(&path).unwrap();
+}
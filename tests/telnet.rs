@@ -0,0 +1,80 @@
+use std::{
+    io::{Read, Write},
+    net::TcpListener,
+    time::{Duration, Instant},
+};
+
+use wcmd::backend::{self, BackendKind};
+use wcmd::cmd::{Cmd, CmdEvent};
+use wcmd::telnet::Negotiator;
+
+const IAC: u8 = 255;
+const DO: u8 = 253;
+const WILL: u8 = 251;
+const WONT: u8 = 252;
+const SB: u8 = 250;
+const SE: u8 = 240;
+
+// Reads until `expected` bytes arrived or the timeout passes
+fn read_exactly(stream: &mut impl Read, expected: usize) -> Vec<u8> {
+    let mut got = vec![0; expected];
+    stream.read_exact(&mut got).expect("server read");
+    got
+}
+
+#[test]
+fn negotiator_answers_and_strips_commands() {
+    let mut n = Negotiator::new((80, 25));
+    let (mut data, mut reply) = (vec![], vec![]);
+    n.receive(&[b'a', IAC, DO, 31, IAC, IAC, b'\r', 0, IAC, DO, 24, b'b'], &mut data, &mut reply);
+    assert_eq!(data, [b'a', IAC, b'\r', b'b']);
+    assert_eq!(reply, [IAC, WILL, 31, IAC, SB, 31, 0, 80, 0, 25, IAC, SE, IAC, WONT, 24]);
+    assert_eq!(n.resize(255, 30), [IAC, SB, 31, 0, IAC, IAC, 0, 30, IAC, SE]);
+    assert_eq!(n.send(&[b'x', IAC, b'\n']), [b'x', IAC, IAC, b'\r', b'\n']);
+    assert!(!n.is_remote_echo());
+}
+
+#[test]
+fn telnet_session_against_local_server() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        stream.write_all(&[IAC, DO, 31, IAC, WILL, 1, IAC, WILL, 3]).unwrap();
+        let replies = read_exactly(&mut stream, 3 + 9 + 3 + 3);
+        assert_eq!(replies, [IAC, WILL, 31, IAC, SB, 31, 0, 80, 0, 25, IAC, SE, IAC, 253, 1, IAC, 253, 3]);
+
+        stream.write_all(b"login: \xdb").unwrap();
+        // Keys come in as they're typed with remote echo on
+        assert_eq!(read_exactly(&mut stream, 1), b"h");
+        assert_eq!(read_exactly(&mut stream, 3), b"i\r\n");
+        stream.write_all(b"hi\r\n").unwrap();
+    });
+
+    let mut cmd = Cmd::new();
+    cmd.attach_backend(Some(backend::spawn(&BackendKind::Tcp { address, telnet: true }, &[], (80, 25)).unwrap()));
+
+    let start = Instant::now();
+    while !cmd.get_stdout().contains("login:") && start.elapsed() < Duration::from_secs(5) {
+        cmd.update();
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    assert!(cmd.is_remote_echo());
+    cmd.put_stdin('h');
+    std::thread::sleep(Duration::from_millis(50));
+    cmd.put_stdin('i');
+    cmd.flush_stdin();
+
+    let mut exited = false;
+    while !exited && start.elapsed() < Duration::from_secs(5) {
+        cmd.update();
+        exited = cmd.drain_events().iter().any(|e| matches!(e, CmdEvent::ChildExited));
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    server.join().unwrap();
+    assert!(exited);
+    // Nothing echoed locally, the server's echo is what shows up
+    assert_eq!(&*cmd.get_stdout(), "login: █hi\r\n");
+}
@@ -0,0 +1,42 @@
+// The terminal model on its own, the way a frontend other than the SDL window uses it
+use wcmd::cmd::{Cmd, CmdEvent};
+use wcmd::font::Font;
+use wcmd::render::{Renderer, VisualCommandLine};
+use wcmd::screen::Screen;
+use wcmd::textdump::TextRenderer;
+
+fn render(screen: &Screen, size: (u32, u32)) -> TextRenderer {
+    let font = Font::default();
+    let mut renderer = TextRenderer::new(size, &font);
+    let mut visual_cmd = VisualCommandLine::new(font);
+    visual_cmd.update(renderer.get_size(), screen);
+    visual_cmd.render(&mut renderer, screen, None);
+    renderer
+}
+
+#[test]
+fn output_and_typing_end_up_on_the_grid() {
+    let mut cmd = Cmd::new();
+    let screen = Screen::from_buffer(cmd.get_stdout_buffer(), 0x07);
+
+    // Code page 437 box drawing, a title that isn't shown and the prompt
+    cmd.write_bytes(b"\xc9\xcd\xbb\r\n\x1b]0;Build\x07C:\\>");
+    for c in "dir x".chars() {
+        cmd.put_stdin(c);
+    }
+    cmd.pop_stdin();
+    let events = cmd.drain_events();
+    assert!(events.iter().any(|e| matches!(e, CmdEvent::TitleChanged(ref t) if t == "Build")));
+    assert_eq!(&*screen.get_text(), "\u{2554}\u{2550}\u{2557}\r\nC:\\>dir ");
+
+    let renderer = render(&screen, (10, 3));
+    assert_eq!(renderer.get_text(), "\u{2554}\u{2550}\u{2557}\nC:\\>dir\n");
+    assert_eq!(renderer.get_cursor(), Some((8, 1)));
+
+    // Enter sends the line and the answer wraps at the edge
+    cmd.put_stdout('\n');
+    assert_eq!(cmd.flush_stdin(), "dir \n");
+    cmd.write_bytes(b"0123456789abc");
+    let renderer = render(&screen, (10, 4));
+    assert_eq!(renderer.get_text(), "\u{2554}\u{2550}\u{2557}\nC:\\>dir\n0123456789\nabc");
+}
@@ -0,0 +1,80 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use wcmd::transcript::{format_timestamp, Transcript, TranscriptConfig, TranscriptFormat};
+
+fn config(name: &str, format: TranscriptFormat, max_size: u64) -> TranscriptConfig {
+    let path = std::env::temp_dir().join(format!("wcmd-transcript-{}-{}.log", name, std::process::id()));
+    remove(&path);
+    TranscriptConfig { path: Some(path), format, max_size }
+}
+
+fn rotated(path: &Path, n: usize) -> PathBuf {
+    PathBuf::from(format!("{}.{}", path.display(), n))
+}
+
+fn remove(path: &Path) {
+    let _ = std::fs::remove_file(path);
+    for n in 1..=6 {
+        let _ = std::fs::remove_file(rotated(path, n));
+    }
+}
+
+#[test]
+fn timestamps_are_utc() {
+    assert_eq!(format_timestamp(UNIX_EPOCH), "1970-01-01 00:00:00Z");
+    assert_eq!(format_timestamp(UNIX_EPOCH + Duration::from_secs(951_782_400)), "2000-02-29 00:00:00Z");
+    assert_eq!(format_timestamp(UNIX_EPOCH + Duration::from_secs(1_700_000_000)), "2023-11-14 22:13:20Z");
+}
+
+#[test]
+fn plain_strips_escapes_split_between_reads() {
+    let config = config("plain", TranscriptFormat::Plain, 0);
+    let path = config.path.clone().unwrap();
+    let mut transcript = Transcript::open(&config).unwrap();
+    transcript.write_output(b"C:\\>\x1b[1");
+    transcript.write_output(b";31mred\x1b[0m\x1b]0;title\x1b");
+    transcript.write_output(b"\\ ok\x07\r\n\x82");
+    transcript.write_input("dir");
+    drop(transcript);
+
+    let text = std::fs::read_to_string(&path).unwrap();
+    let (output, input) = text.split_at(text.find('[').unwrap());
+    assert_eq!(output, "C:\\>red ok\n\u{e9}\n");
+    // [YYYY-MM-DD HH:MM:SSZ] > dir
+    assert_eq!(input.len(), "[1970-01-01 00:00:00Z] > dir\n".len());
+    assert!(input.ends_with("Z] > dir\n"));
+    remove(&path);
+}
+
+#[test]
+fn raw_keeps_every_byte() {
+    let config = config("raw", TranscriptFormat::Raw, 0);
+    let path = config.path.clone().unwrap();
+    let mut transcript = Transcript::open(&config).unwrap();
+    transcript.write_output(b"\x1b[31mred\r\n");
+    drop(transcript);
+    assert_eq!(std::fs::read(&path).unwrap(), b"\x1b[31mred\r\n");
+    remove(&path);
+}
+
+#[test]
+fn rotates_when_full() {
+    let config = config("rotate", TranscriptFormat::Raw, 10);
+    let path = config.path.clone().unwrap();
+    let mut transcript = Transcript::open(&config).unwrap();
+    // Fills up to the limit before it moves on
+    for chunk in ["01234", "56789", "aaaaaa", "bbbbbb", "cccccc", "dddddd", "eeeeee", "ffffff"] {
+        transcript.write_output(chunk.as_bytes());
+    }
+    assert_eq!(transcript.get_path(), path.as_path());
+    drop(transcript);
+
+    let read = |p: PathBuf| std::fs::read_to_string(p).unwrap();
+    assert_eq!(read(path.clone()), "ffffff");
+    assert_eq!(read(rotated(&path, 1)), "eeeeee");
+    assert_eq!(read(rotated(&path, 5)), "aaaaaa");
+    // Only so many are kept
+    assert!(!rotated(&path, 6).exists());
+    remove(&path);
+}
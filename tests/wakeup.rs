@@ -0,0 +1,40 @@
+use std::sync::{atomic::{AtomicUsize, Ordering}, mpsc, Arc};
+use std::time::Duration;
+
+use wcmd::cmd::{Cmd, CmdEvent};
+use wcmd::subprocess::SubProcess;
+use wcmd::wakeup::Wakeup;
+
+#[test]
+fn wakes_are_coalesced_until_reset() {
+    let count = Arc::new(AtomicUsize::new(0));
+    let counter = count.clone();
+    let wakeup = Wakeup::new(move || { counter.fetch_add(1, Ordering::SeqCst); });
+
+    wakeup.wake();
+    wakeup.wake();
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+
+    wakeup.reset();
+    wakeup.wake();
+    assert_eq!(count.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn child_output_wakes_without_polling() {
+    let (tx, rx) = mpsc::channel();
+    let tx = std::sync::Mutex::new(tx);
+    let mut cmd = Cmd::new();
+    cmd.set_wakeup(Wakeup::new(move || { let _ = tx.lock().unwrap().send(()); }));
+    cmd.attach_child(SubProcess::from_args(&["echo".to_string(), "hello".to_string()]));
+
+    rx.recv_timeout(Duration::from_secs(5)).expect("no wakeup from the child");
+    cmd.update();
+
+    // Exiting isn't signalled, the process can be reaped a moment after its pipes close
+    while !cmd.drain_events().iter().any(|e| matches!(e, CmdEvent::ChildExited)) {
+        std::thread::sleep(Duration::from_millis(10));
+        cmd.update();
+    }
+    assert_eq!(cmd.get_stdout().trim_end(), "hello");
+}